@@ -0,0 +1,178 @@
+//! # Recommend Guest Configuration
+//!
+//! Given a bare-metal dump, suggest what a hypervisor on this host
+//! should enable for its guests. Each recommendation is tied to a
+//! catalog feature and applies if the feature is present or, for
+//! fallbacks, if it is absent.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::{Feature, FeatureStatus};
+use crate::report::Section;
+
+/// A recommendation that depends on a single feature.
+struct Advice {
+    feature: &'static str,
+    if_present: Option<&'static str>,
+    if_absent: Option<&'static str>,
+}
+
+const ADVICE: &[Advice] = &[
+    Advice {
+        feature: "EPT",
+        if_present: Some("use EPT instead of shadow paging (kvm_intel.ept=1)"),
+        if_absent: None,
+    },
+    Advice {
+        feature: "EPT 1GB pages",
+        if_present: Some("back guest memory with 1 GB huge pages"),
+        if_absent: None,
+    },
+    Advice {
+        feature: "Unrestricted guest usable",
+        if_present: None,
+        if_absent: Some("guests that boot in real mode are slow, prefer UEFI firmware"),
+    },
+    Advice {
+        feature: "APICv complete",
+        if_present: Some("enable APICv (kvm_intel.enable_apicv=1)"),
+        if_absent: Some(
+            "offer Hyper-V APIC access MSRs (hv-vapic) and PV EOI to reduce APIC exits",
+        ),
+    },
+    Advice {
+        feature: "Process posted interrupts",
+        if_present: Some(
+            "posted interrupts avoid VM exits for virtual interrupt delivery; device posting additionally needs VT-d posted interrupts",
+        ),
+        if_absent: None,
+    },
+    Advice {
+        feature: "IPI virtualization",
+        if_present: Some("enable IPI virtualization (kvm_intel.enable_ipiv=1)"),
+        if_absent: Some("offer PV IPI hypercalls (KVM PV send IPI, Hyper-V hv-ipi)"),
+    },
+    Advice {
+        feature: "AVIC",
+        if_present: Some("enable AVIC (kvm_amd.avic=1)"),
+        if_absent: Some(
+            "offer Hyper-V APIC access MSRs (hv-vapic) and PV EOI to reduce APIC exits",
+        ),
+    },
+    Advice {
+        feature: "Virtual NMI",
+        if_present: Some("enable virtual NMIs (kvm_amd.vnmi=1)"),
+        if_absent: None,
+    },
+    Advice {
+        feature: "VMCS Shadowing",
+        if_present: Some(
+            "nested guests benefit from VMCS shadowing (kvm_intel.enable_shadow_vmcs=1)",
+        ),
+        if_absent: Some("offer enlightened VMCS (hv-evmcs) to nested Hyper-V guests"),
+    },
+    Advice {
+        feature: "VMX preemption timer",
+        if_present: Some(
+            "emulate the guest APIC timer with the preemption timer (kvm_intel.preemption_timer=1)",
+        ),
+        if_absent: None,
+    },
+    Advice {
+        feature: "TSC scaling",
+        if_present: Some("guests can migrate between hosts with different TSC frequencies"),
+        if_absent: Some(
+            "offer Hyper-V reenlightenment (hv-reenlightenment) or kvmclock for migration",
+        ),
+    },
+    Advice {
+        feature: "Page-modification logging",
+        if_present: Some("use PML for dirty tracking during live migration (kvm_intel.pml=1)"),
+        if_absent: None,
+    },
+    Advice {
+        feature: "Bus-lock VM exits",
+        if_present: Some("rate-limit guests that abuse split locks"),
+        if_absent: None,
+    },
+    Advice {
+        feature: "Notify VM exits",
+        if_present: Some("protect the host against guests that stall the CPU"),
+        if_absent: None,
+    },
+    Advice {
+        feature: "SEV-SNP",
+        if_present: Some("offer confidential guests with SEV-SNP"),
+        if_absent: None,
+    },
+];
+
+/// Recommendations for guests on this host.
+///
+/// Features that are unknown or don't apply to this CPU don't lead
+/// to recommendations.
+pub fn section(catalog: &[Feature], cpu_info: &dyn CpuInformation) -> Section {
+    let mut section = Section::new("Recommendations for guests");
+
+    for advice in ADVICE {
+        let status = match catalog.iter().find(|f| f.name == advice.feature) {
+            Some(feature) => feature.is_present(cpu_info),
+            None => continue,
+        };
+
+        let text = match status {
+            FeatureStatus::Present => advice.if_present,
+            FeatureStatus::Absent => advice.if_absent,
+            FeatureStatus::Unknown { .. } | FeatureStatus::NotApplicable => None,
+        };
+
+        if let Some(text) = text {
+            section.row(advice.feature, text);
+        }
+    }
+
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog;
+    use crate::features::BoolExpression::Constant;
+    use crate::features::DEFAULT_CATEGORY;
+    use crate::test_support::NoInformation;
+
+    #[test]
+    fn advice_refers_to_existing_features() {
+        let features = catalog::features();
+
+        for advice in ADVICE {
+            assert!(
+                features.iter().any(|f| f.name == advice.feature),
+                "advice for unknown feature {}",
+                advice.feature
+            );
+        }
+    }
+
+    #[test]
+    fn advice_follows_feature_status() {
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "EPT", Constant(true)),
+            Feature::new(DEFAULT_CATEGORY, "EPT 1GB pages", Constant(false)),
+            Feature::new(DEFAULT_CATEGORY, "APICv complete", Constant(false)),
+        ];
+
+        let section = section(&features, &NoInformation);
+
+        assert_eq!(
+            section.get("EPT"),
+            Some("use EPT instead of shadow paging (kvm_intel.ept=1)")
+        );
+        assert_eq!(section.get("EPT 1GB pages"), None);
+        assert!(section
+            .get("APICv complete")
+            .expect("fallback advice")
+            .contains("hv-vapic"));
+        assert_eq!(section.get("VMCS Shadowing"), None);
+    }
+}
@@ -1,10 +1,11 @@
 //! # Parse AIDA CPUID Dumps
 //!
 //! Extract CPUID and MSR information out of AIDA CPUID dumps. This
-//! code only interprets CPUID values from logical CPU 0. It also
-//! ignores any duplicated MSRs in the input data. From manual
-//! inspection, the duplicated MSRs are performance counters and not
-//! interesting.
+//! code interprets CPUID values from logical CPU 0. The CPUID values
+//! of the other logical CPUs are only available via
+//! [CpuInformation::logical_cpus]. It also ignores any duplicated MSRs
+//! in the input data. From manual inspection, the duplicated MSRs are
+//! performance counters and not interesting.
 //!
 //! See [AidaCpuidDump].
 
@@ -22,6 +23,7 @@ type MsrMap = Map<u32, u64>;
 pub struct AidaCpuidDump {
     cpuid: CpuidMap,
     msrs: MsrMap,
+    logical_cpus: Vec<LogicalCpu>,
 }
 
 impl CpuInformation for AidaCpuidDump {
@@ -32,6 +34,58 @@ impl CpuInformation for AidaCpuidDump {
     fn rdmsr(&self, index: u32) -> Option<u64> {
         self.msrs.get(&index).copied()
     }
+
+    fn logical_cpus(&self) -> Vec<&dyn CpuInformation> {
+        self.logical_cpus
+            .iter()
+            .map(|cpu| cpu as &dyn CpuInformation)
+            .collect()
+    }
+
+    fn cpuid_queries(&self) -> Vec<CpuidQuery> {
+        self.cpuid.keys().copied().collect()
+    }
+
+    fn msr_indices(&self) -> Vec<u32> {
+        self.msrs.keys().copied().collect()
+    }
+}
+
+/// The CPUID values of a single logical CPU.
+///
+/// AIDA dumps MSRs only once, so these are never known.
+#[derive(Debug, Clone)]
+struct LogicalCpu {
+    cpuid: CpuidMap,
+}
+
+impl CpuInformation for LogicalCpu {
+    fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+        self.cpuid.get(&query).cloned()
+    }
+
+    fn rdmsr(&self, _index: u32) -> Option<u64> {
+        None
+    }
+}
+
+/// Collect the CPUID lines of a group.
+fn cpuid_map(lines: &[InputLine]) -> CpuidMap {
+    lines
+        .iter()
+        .filter_map(|line| {
+            if let InputLine::Cpuid { query, result } = line {
+                Some((*query, *result))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The number of a logical CPU group, e.g. 3 for `Logical CPU #3`.
+fn logical_cpu_number(group_name: &str) -> Option<u32> {
+    group_name.strip_prefix("Logical CPU #")?.parse().ok()
 }
 
 impl std::fmt::Display for AidaCpuidDump {
@@ -218,20 +272,23 @@ impl FromStr for AidaCpuidDump {
         // Turn the parsed groups into an easy-to-query map.
         let groups: Map<String, Vec<InputLine>> = groups_vec.into_iter().collect();
 
+        let mut logical_cpus: Vec<(u32, LogicalCpu)> = groups
+            .iter()
+            .filter_map(|(name, lines)| {
+                let cpuid = cpuid_map(lines);
+
+                Some((logical_cpu_number(name)?, LogicalCpu { cpuid }))
+            })
+            .collect();
+        logical_cpus.sort_by_key(|(number, _)| *number);
+
         // Construct our final return value.
         Ok(AidaCpuidDump {
-            cpuid: groups
-                .get("Logical CPU #0")
-                .ok_or(ParseAidaCpuidDumpError {})?
-                .iter()
-                .filter_map(|line| {
-                    if let InputLine::Cpuid { query, result } = line {
-                        Some((*query, *result))
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+            cpuid: cpuid_map(
+                groups
+                    .get("Logical CPU #0")
+                    .ok_or(ParseAidaCpuidDumpError {})?,
+            ),
             msrs: groups
                 .get("MSR Registers")
                 .ok_or(ParseAidaCpuidDumpError {})?
@@ -244,6 +301,7 @@ impl FromStr for AidaCpuidDump {
                     }
                 })
                 .collect(),
+            logical_cpus: logical_cpus.into_iter().map(|(_, cpu)| cpu).collect(),
         })
     }
 }
@@ -401,6 +459,20 @@ MSR 0000001B: 0000-0000-FEE0-0900
             }
         );
 
+        let logical_cpus = aida_dump.logical_cpus();
+        assert_eq!(logical_cpus.len(), 2);
+        assert_eq!(
+            logical_cpus[1]
+                .cpuid(CpuidQuery {
+                    leaf: 4,
+                    subleaf: 1
+                })
+                .map(|r| r.eax),
+            Some(0x1C004122)
+        );
+
+        assert_eq!(aida_dump.msr_indices(), vec![0x17, 0x1b]);
+        assert_eq!(aida_dump.cpuid_queries().len(), 2);
         assert_eq!(aida_dump.msrs.len(), 2);
         assert_eq!(
             *aida_dump.msrs.get(&0x17).expect("to find MSR value"),
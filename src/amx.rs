@@ -0,0 +1,168 @@
+//! # Decode AMX Capabilities
+//!
+//! Advanced Matrix Extensions (AMX) add tile registers and a tile
+//! matrix multiply unit (TMUL). CPUID leaf 7 enumerates the data
+//! types TMUL supports. CPUID leaf 0x1D describes the tile palettes,
+//! i.e. how many tiles there are and how large they can be, and CPUID
+//! leaf 0x1E the dimensions TMUL can handle.
+//!
+//! See the Intel SDM Vol. 1, Chapter 18, and Vol. 2A, CPUID—CPU
+//! Identification.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The CPUID leaf with tile palette information.
+pub const CPUID_TILE_INFORMATION: u32 = 0x1d;
+
+/// The CPUID leaf with TMUL information.
+pub const CPUID_TMUL_INFORMATION: u32 = 0x1e;
+
+/// The bit in CPUID leaf 7 EDX that enumerates tile registers.
+const AMX_TILE: Bit = 24;
+
+/// The AMX bits in CPUID leaf 7 subleaf 0 EDX.
+const SUBLEAF0_FEATURES: &[(Bit, &str)] = &[(22, "AMX-BF16"), (24, "AMX-TILE"), (25, "AMX-INT8")];
+
+/// The AMX bits in CPUID leaf 7 subleaf 1 EAX.
+const SUBLEAF1_EAX_FEATURES: &[(Bit, &str)] = &[(21, "AMX-FP16")];
+
+/// The AMX bits in CPUID leaf 7 subleaf 1 EDX.
+const SUBLEAF1_EDX_FEATURES: &[(Bit, &str)] = &[(8, "AMX-COMPLEX")];
+
+fn query(leaf: u32, subleaf: u32) -> CpuidQuery {
+    CpuidQuery { leaf, subleaf }
+}
+
+/// Add the parameters of a tile palette.
+fn palette_rows(section: &mut Section, palette: u32, r: CpuidResult) {
+    let row = |name: &str| format!("Palette {} {}", palette, name);
+
+    section.row(&row("total tile bytes"), r.eax & 0xffff);
+    section.row(&row("bytes per tile"), r.eax >> 16);
+    section.row(&row("bytes per row"), r.ebx & 0xffff);
+    section.row(&row("tile registers"), r.ebx >> 16);
+    section.row(&row("rows"), r.ecx & 0xffff);
+}
+
+/// Decode AMX information.
+///
+/// `palettes` holds the subleaves of leaf 0x1D from 1 on.
+fn amx_section(
+    subleaf0: CpuidResult,
+    subleaf1: Option<CpuidResult>,
+    palettes: &[CpuidResult],
+    tmul: Option<CpuidResult>,
+) -> Section {
+    let mut section = Section::new(&format!("AMX (CPUID {:#x})", CPUID_TILE_INFORMATION));
+
+    for (bit, name) in SUBLEAF0_FEATURES {
+        section.flag(name, subleaf0.edx.into(), *bit);
+    }
+    if let Some(r) = subleaf1 {
+        for (bit, name) in SUBLEAF1_EAX_FEATURES {
+            section.flag(name, r.eax.into(), *bit);
+        }
+        for (bit, name) in SUBLEAF1_EDX_FEATURES {
+            section.flag(name, r.edx.into(), *bit);
+        }
+    }
+
+    for (palette, r) in (1..).zip(palettes) {
+        palette_rows(&mut section, palette, *r);
+    }
+
+    if let Some(r) = tmul {
+        section.row("TMUL maximum K", r.ebx & 0xff);
+        section.row("TMUL maximum N bytes", (r.ebx >> 8) & 0xffff);
+    }
+
+    section
+}
+
+/// Decode AMX information, if the CPU supports tiles.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let leaf7 = match cpu_info
+        .cpuid(7.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(7.into()))
+        .filter(|r| (r.edx >> AMX_TILE) & 1 == 1)
+    {
+        Some(r) => r,
+        None => return vec![],
+    };
+    let subleaf1 = (leaf7.eax >= 1)
+        .then(|| cpu_info.cpuid(query(7, 1)))
+        .flatten();
+
+    let max_palette = cpu_info
+        .cpuid(CPUID_TILE_INFORMATION.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(CPUID_TILE_INFORMATION.into()))
+        .map_or(0, |r| r.eax);
+    let palettes: Vec<CpuidResult> = (1..=max_palette)
+        .map_while(|palette| cpu_info.cpuid(query(CPUID_TILE_INFORMATION, palette)))
+        .collect();
+
+    let tmul = cpu_info
+        .cpuid(CPUID_TMUL_INFORMATION.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(CPUID_TMUL_INFORMATION.into()));
+
+    vec![amx_section(leaf7, subleaf1, &palettes, tmul)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    fn result(eax: u32, ebx: u32, ecx: u32, edx: u32) -> CpuidResult {
+        CpuidResult { eax, ebx, ecx, edx }
+    }
+
+    #[test]
+    fn amx_is_decoded() {
+        // A Sapphire Rapids CPU.
+        let section = amx_section(
+            result(2, 0, 0, 0x0340_0000),
+            Some(result(0x0020_0000, 0, 0, 0)),
+            &[result(0x0400_2000, 0x0008_0040, 0x10, 0)],
+            Some(result(0, 0x0000_4010, 0, 0)),
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("AMX-BF16"), "Y");
+        assert_eq!(row("AMX-TILE"), "Y");
+        assert_eq!(row("AMX-INT8"), "Y");
+        assert_eq!(row("AMX-FP16"), "Y");
+        assert_eq!(row("AMX-COMPLEX"), "N");
+        assert_eq!(row("Palette 1 total tile bytes"), "8192");
+        assert_eq!(row("Palette 1 bytes per tile"), "1024");
+        assert_eq!(row("Palette 1 bytes per row"), "64");
+        assert_eq!(row("Palette 1 tile registers"), "8");
+        assert_eq!(row("Palette 1 rows"), "16");
+        assert_eq!(row("TMUL maximum K"), "16");
+        assert_eq!(row("TMUL maximum N bytes"), "64");
+    }
+
+    #[test]
+    fn cpus_without_tiles_are_not_decoded() {
+        struct TestCpu;
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+                Some(result(if query.leaf == 0 { 0x20 } else { 0 }, 0, 0, 0))
+            }
+
+            fn rdmsr(&self, _index: u32) -> Option<u64> {
+                None
+            }
+        }
+
+        assert!(sections(&TestCpu).is_empty());
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(SUBLEAF0_FEATURES);
+    }
+}
@@ -0,0 +1,119 @@
+//! # Audit Reserved Bits
+//!
+//! Reserved bits in CPUID leaves and MSRs read as zero on current
+//! CPUs. A set reserved bit means that the dump is corrupted or that
+//! the CPU implements something that is not documented yet. Either
+//! way, it's worth a closer look.
+//!
+//! The masks here only cover registers whose layout is stable and
+//! fully documented.
+
+use crate::cpu_information::CpuidRegister::{self, *};
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::report::Section;
+use crate::svm::{CPUID_SVM_FEATURES, VM_CR};
+use crate::vmx::{IA32_FEATURE_CONTROL, IA32_VMX_BASIC, IA32_VMX_MISC};
+
+/// The reserved bits of a CPUID register.
+const CPUID_RESERVED: &[(u32, CpuidRegister, u32)] = &[
+    (1, Ecx, 1 << 16),
+    (1, Edx, (1 << 10) | (1 << 20)),
+    (
+        CPUID_SVM_FEATURES,
+        Edx,
+        (1 << 8) | (1 << 9) | (1 << 11) | (1 << 14) | (1 << 22),
+    ),
+];
+
+/// The reserved bits of a MSR.
+const MSR_RESERVED: &[(u32, u64)] = &[
+    (
+        IA32_FEATURE_CONTROL,
+        0xffff_ffff_ff00_0000 | (1 << 22) | (1 << 21) | (1 << 19) | (1 << 16) | 0xf8,
+    ),
+    (IA32_VMX_BASIC, 0xf800_e000_0000_0000 | (1 << 31)),
+    (IA32_VMX_MISC, 0x1f << 9),
+    (VM_CR, !0x1f),
+];
+
+/// Check all known reserved bits in the dump.
+///
+/// The section lists every register with set reserved bits.
+pub fn section(cpu_info: &dyn CpuInformation) -> Section {
+    let mut section = Section::new("Reserved bits that are set");
+
+    for &(leaf, register, mask) in CPUID_RESERVED {
+        let query = CpuidQuery::from(leaf);
+
+        if let Some(r) = cpu_info
+            .cpuid(query)
+            .filter(|_| cpu_info.is_cpuid_query_valid(query))
+        {
+            let set = r.get(register) & mask;
+            if set != 0 {
+                section.row(
+                    &format!("CPUID {:#x}.{}", leaf, register),
+                    format!("{:#x}", set),
+                );
+            }
+        }
+    }
+
+    for &(index, mask) in MSR_RESERVED {
+        if let Some(set) = cpu_info.rdmsr(index).map(|v| v & mask).filter(|s| *s != 0) {
+            section.row(&format!("MSR {:#x}", index), format!("{:#x}", set));
+        }
+    }
+
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidResult;
+
+    /// A CPU with a set reserved bit in CPUID leaf 1 and the given
+    /// IA32_VMX_BASIC.
+    struct TestCpu(u64);
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            Some(CpuidResult {
+                eax: if query.leaf == 0 { 1 } else { 0 },
+                ebx: 0,
+                ecx: 0,
+                edx: if query.leaf == 1 { 1 << 20 } else { 0 },
+            })
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            match index {
+                IA32_FEATURE_CONTROL => Some(0x0010_0005),
+                IA32_VMX_BASIC => Some(self.0),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn set_reserved_bits_are_reported() {
+        let section = section(&TestCpu(0x80da_0400_0000_0004));
+
+        assert_eq!(
+            section.rows,
+            vec![
+                ("CPUID 0x1.EDX".to_owned(), "0x100000".to_owned()),
+                ("MSR 0x480".to_owned(), "0x8000000000000000".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn fred_vmx_basic_is_not_reserved() {
+        // Bit 58: VMX support for FRED nested exceptions.
+        let section = section(&TestCpu(0x04da_0400_0000_0004));
+
+        assert_eq!(section.get("MSR 0x480"), None);
+    }
+}
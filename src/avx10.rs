@@ -0,0 +1,101 @@
+//! # Decode AVX10
+//!
+//! AVX10 replaces the individual AVX-512 feature bits with a version
+//! number: each version implies a fixed set of instructions. CPUID
+//! leaf 7 subleaf 1 EDX bit 19 enumerates AVX10, and CPUID leaf 0x24
+//! reports the version and the supported vector lengths.
+//!
+//! See the Intel Advanced Vector Extensions 10 Architecture
+//! Specification.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The CPUID leaf with AVX10 information.
+pub const CPUID_AVX10: u32 = 0x24;
+
+/// The bit in CPUID leaf 7 subleaf 1 EDX that enumerates AVX10.
+pub const AVX10: Bit = 19;
+
+/// The vector lengths in EBX.
+const VECTOR_LENGTHS: &[(Bit, &str)] = &[
+    (16, "128-bit vectors"),
+    (17, "256-bit vectors"),
+    (18, "512-bit vectors"),
+];
+
+/// Decode CPUID leaf 0x24.
+fn avx10_section(r: CpuidResult) -> Section {
+    let mut section = Section::new(&format!("AVX10 (CPUID {:#x})", CPUID_AVX10));
+
+    section.row("Version", r.ebx & 0xff);
+    for (bit, name) in VECTOR_LENGTHS {
+        section.flag(name, r.ebx.into(), *bit);
+    }
+
+    section
+}
+
+/// Decode AVX10 information, if the CPU supports AVX10.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let subleaf1 = CpuidQuery {
+        leaf: 7,
+        subleaf: 1,
+    };
+    let supported = cpu_info.is_cpuid_query_valid(subleaf1)
+        && cpu_info
+            .cpuid(subleaf1)
+            .is_some_and(|r| (r.edx >> AVX10) & 1 == 1);
+
+    cpu_info
+        .cpuid(CPUID_AVX10.into())
+        .filter(|_| supported && cpu_info.is_cpuid_query_valid(CPUID_AVX10.into()))
+        .map(avx10_section)
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaf_is_decoded() {
+        // A Granite Rapids CPU.
+        let section = avx10_section(CpuidResult {
+            eax: 0,
+            ebx: 0x0007_0001,
+            ecx: 0,
+            edx: 0,
+        });
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Version"), "1");
+        assert_eq!(row("128-bit vectors"), "Y");
+        assert_eq!(row("256-bit vectors"), "Y");
+        assert_eq!(row("512-bit vectors"), "Y");
+    }
+
+    #[test]
+    fn cpus_without_avx10_are_not_decoded() {
+        struct TestCpu;
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+                Some(CpuidResult {
+                    eax: if query.leaf == 0 { 0x24 } else { 0 },
+                    ebx: 0x0007_0001,
+                    ecx: 0,
+                    edx: 0,
+                })
+            }
+
+            fn rdmsr(&self, _index: u32) -> Option<u64> {
+                None
+            }
+        }
+
+        assert!(sections(&TestCpu).is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! # Decode the AVX-512 Extensions
+//!
+//! AVX-512 is not one feature but a family of extensions that CPUs
+//! implement in different combinations, and their bits are spread over
+//! four registers of CPUID leaf 7. This shows all of them as a compact
+//! matrix with one row per register.
+//!
+//! See the Intel Architecture Instruction Set Extensions Programming
+//! Reference.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidRegister, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The AVX-512 extensions in subleaf 0 of CPUID leaf 7.
+const SUBLEAF0: &[(CpuidRegister, &[(Bit, &str)])] = &[
+    (
+        CpuidRegister::Ebx,
+        &[
+            (16, "F"),
+            (17, "DQ"),
+            (21, "IFMA"),
+            (26, "PF"),
+            (27, "ER"),
+            (28, "CD"),
+            (30, "BW"),
+            (31, "VL"),
+        ],
+    ),
+    (
+        CpuidRegister::Ecx,
+        &[
+            (1, "VBMI"),
+            (6, "VBMI2"),
+            (11, "VNNI"),
+            (12, "BITALG"),
+            (14, "VPOPCNTDQ"),
+        ],
+    ),
+    (
+        CpuidRegister::Edx,
+        &[
+            (2, "4VNNIW"),
+            (3, "4FMAPS"),
+            (8, "VP2INTERSECT"),
+            (23, "FP16"),
+        ],
+    ),
+];
+
+/// The AVX-512 extensions in subleaf 1 of CPUID leaf 7.
+const SUBLEAF1: &[(CpuidRegister, &[(Bit, &str)])] = &[(CpuidRegister::Eax, &[(5, "BF16")])];
+
+/// Append one row per register, listing each extension as `Y` or `N`.
+fn matrix_rows(
+    section: &mut Section,
+    subleaf: u32,
+    registers: &[(CpuidRegister, &[(Bit, &str)])],
+    r: CpuidResult,
+) {
+    for (register, extensions) in registers {
+        let value = r.get(*register);
+
+        section.row(
+            &format!("CPUID 0x7.{:#x} {}", subleaf, register),
+            extensions
+                .iter()
+                .map(|(bit, name)| {
+                    format!(
+                        "{} {}",
+                        name,
+                        if (value >> bit) & 1 == 1 { 'Y' } else { 'N' }
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("  "),
+        );
+    }
+}
+
+/// Decode the AVX-512 extensions from subleaf 0 and, if the CPU
+/// enumerates it, subleaf 1 of CPUID leaf 7.
+fn avx512_section(subleaf0: CpuidResult, subleaf1: Option<CpuidResult>) -> Section {
+    let mut section = Section::new("AVX-512 extensions (CPUID 0x7)");
+
+    matrix_rows(&mut section, 0, SUBLEAF0, subleaf0);
+    if let Some(r) = subleaf1 {
+        matrix_rows(&mut section, 1, SUBLEAF1, r);
+    }
+
+    section
+}
+
+/// Decode the AVX-512 extensions, if the CPU has CPUID leaf 7.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let subleaf1 = CpuidQuery {
+        leaf: 7,
+        subleaf: 1,
+    };
+
+    cpu_info
+        .cpuid(7.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(7.into()))
+        .map(|r| avx512_section(r, cpu_info.cpuid(subleaf1).filter(|_| r.eax >= 1)))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn extensions_are_decoded() {
+        // Everything except the Xeon Phi extensions and VP2INTERSECT.
+        let section = avx512_section(
+            CpuidResult {
+                eax: 1,
+                ebx: 0xd023_0000,
+                ecx: 0x5842,
+                edx: 0x0080_0000,
+            },
+            Some(CpuidResult {
+                eax: 0x20,
+                ebx: 0,
+                ecx: 0,
+                edx: 0,
+            }),
+        );
+
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 EBX"),
+            Some("F Y  DQ Y  IFMA Y  PF N  ER N  CD Y  BW Y  VL Y")
+        );
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 ECX"),
+            Some("VBMI Y  VBMI2 Y  VNNI Y  BITALG Y  VPOPCNTDQ Y")
+        );
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 EDX"),
+            Some("4VNNIW N  4FMAPS N  VP2INTERSECT N  FP16 Y")
+        );
+        assert_eq!(section.get("CPUID 0x7.0x1 EAX"), Some("BF16 Y"));
+    }
+
+    #[test]
+    fn xeon_phi_extensions_are_decoded() {
+        // F, CD, PF, ER, VPOPCNTDQ, 4VNNIW and 4FMAPS, as on Knights
+        // Mill.
+        let section = avx512_section(
+            CpuidResult {
+                eax: 0,
+                ebx: 0x1c01_0000,
+                ecx: 0x4000,
+                edx: 0xc,
+            },
+            None,
+        );
+
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 EBX"),
+            Some("F Y  DQ N  IFMA N  PF Y  ER Y  CD Y  BW N  VL N")
+        );
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 ECX"),
+            Some("VBMI N  VBMI2 N  VNNI N  BITALG N  VPOPCNTDQ Y")
+        );
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 EDX"),
+            Some("4VNNIW Y  4FMAPS Y  VP2INTERSECT N  FP16 N")
+        );
+    }
+
+    #[test]
+    fn missing_subleaf_is_left_out() {
+        let section = avx512_section(
+            CpuidResult {
+                eax: 0,
+                ebx: 0,
+                ecx: 0,
+                edx: 0,
+            },
+            None,
+        );
+
+        assert_eq!(section.rows.len(), 3);
+        assert_eq!(
+            section.get("CPUID 0x7.0x0 EDX"),
+            Some("4VNNIW N  4FMAPS N  VP2INTERSECT N  FP16 N")
+        );
+    }
+
+    #[test]
+    fn extensions_are_sorted_and_unique() {
+        for (_, extensions) in SUBLEAF0.iter().chain(SUBLEAF1) {
+            assert_sorted_unique(extensions);
+        }
+    }
+}
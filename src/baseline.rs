@@ -0,0 +1,318 @@
+//! # Compute a Common Baseline
+//!
+//! Guests in a cluster should only see features that every host has,
+//! so that they can be migrated to any of them. This intersects the
+//! dumps of all hosts: the catalog features that all hosts have, the
+//! CPUID feature bits that all hosts set, the XSAVE state components
+//! that all hosts support and the smallest physical address width.
+//! Features that restrict the CPU, such as a locked
+//! IA32_FEATURE_CONTROL, are left out, because guests can't have them.
+//!
+//! The CPUID masks are what a hypervisor ANDs with the host values to
+//! build the guest CPU model. Only registers that hold feature flags
+//! are masked, because the bits of other leaves are not booleans.
+
+use serde::Serialize;
+
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::features::{Feature, FeatureStatus};
+use crate::hybrid::FEATURE_REGISTERS;
+use crate::report::Section;
+use crate::xsave;
+
+/// A catalog feature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeatureName {
+    pub category: String,
+    pub name: String,
+}
+
+/// A feature that some, but not all hosts have.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PartialFeature {
+    pub category: String,
+    pub name: String,
+
+    /// The hosts that don't have the feature or where it's unknown.
+    pub missing_on: Vec<String>,
+}
+
+/// The bits of a CPUID register that all hosts set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegisterMask {
+    pub leaf: u32,
+    pub subleaf: u32,
+    pub register: String,
+    pub mask: u32,
+}
+
+/// The greatest common CPU model of a set of hosts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Baseline {
+    pub hosts: Vec<String>,
+    pub features: Vec<FeatureName>,
+    pub partial_features: Vec<PartialFeature>,
+    pub cpuid: Vec<RegisterMask>,
+
+    /// The XSAVE state components in XCR0 and IA32_XSS.
+    pub xsave: u64,
+
+    /// `None` if no host enumerates its physical address width.
+    pub physical_address_bits: Option<u32>,
+}
+
+impl Baseline {
+    /// Intersect the named hosts.
+    pub fn new(catalog: &[Feature], hosts: &[(String, &dyn CpuInformation)]) -> Baseline {
+        let mut features = vec![];
+        let mut partial_features = vec![];
+
+        for feature in catalog.iter().filter(|f| !f.restriction) {
+            let statuses: Vec<FeatureStatus> =
+                hosts.iter().map(|(_, h)| feature.is_present(*h)).collect();
+            let missing_on: Vec<String> = hosts
+                .iter()
+                .zip(&statuses)
+                .filter(|(_, s)| **s != FeatureStatus::Present)
+                .map(|((name, _), _)| name.clone())
+                .collect();
+
+            if missing_on.is_empty() {
+                features.push(FeatureName {
+                    category: feature.category.clone(),
+                    name: feature.name.clone(),
+                });
+            } else if statuses.contains(&FeatureStatus::Present) {
+                partial_features.push(PartialFeature {
+                    category: feature.category.clone(),
+                    name: feature.name.clone(),
+                    missing_on,
+                });
+            }
+        }
+
+        // A host without the leaf sets none of its bits.
+        let cpuid = FEATURE_REGISTERS
+            .iter()
+            .map(|&(leaf, subleaf, register)| {
+                let query = CpuidQuery { leaf, subleaf };
+
+                RegisterMask {
+                    leaf,
+                    subleaf,
+                    register: register.to_string(),
+                    mask: hosts.iter().fold(u32::MAX, |mask, (_, h)| {
+                        mask & h
+                            .cpuid(query)
+                            .filter(|_| h.is_cpuid_query_valid(query))
+                            .map_or(0, |r| r.get(register))
+                    }),
+                }
+            })
+            .collect();
+
+        Baseline {
+            hosts: hosts.iter().map(|(name, _)| name.clone()).collect(),
+            features,
+            partial_features,
+            cpuid,
+            xsave: hosts.iter().fold(u64::MAX, |mask, (_, h)| {
+                mask & xsave::supported_mask(*h).unwrap_or(0)
+            }),
+            physical_address_bits: hosts
+                .iter()
+                .filter_map(|(_, h)| h.physical_address_bits())
+                .min(),
+        }
+    }
+
+    /// Show the baseline, the common features by category and the
+    /// features that only some hosts have.
+    pub fn sections(&self) -> Vec<Section> {
+        let mut baseline = Section::new(&format!("Common baseline of {} hosts", self.hosts.len()));
+        if let Some(bits) = self.physical_address_bits {
+            baseline.row("Physical address bits", bits);
+        }
+        baseline.row("XSAVE components", format!("{:#x}", self.xsave));
+        for mask in &self.cpuid {
+            baseline.row(
+                &format!(
+                    "CPUID {:#x}.{:#x} {}",
+                    mask.leaf, mask.subleaf, mask.register
+                ),
+                format!("{:#010x}", mask.mask),
+            );
+        }
+
+        let mut common = Section::new("Features on all hosts");
+        let mut categories: Vec<&str> = vec![];
+        for feature in &self.features {
+            if !categories.contains(&feature.category.as_str()) {
+                categories.push(&feature.category);
+            }
+        }
+        for category in categories {
+            common.row(
+                category,
+                self.features
+                    .iter()
+                    .filter(|f| f.category == category)
+                    .map(|f| f.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+
+        let mut partial = Section::new("Features not on all hosts");
+        for feature in &self.partial_features {
+            partial.row(
+                &format!("{}: {}", feature.category, feature.name),
+                format!("missing on {}", feature.missing_on.join(", ")),
+            );
+        }
+
+        vec![baseline, common, partial]
+            .into_iter()
+            .filter(|section| !section.rows.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidRegister::{Ebx, Ecx};
+    use crate::cpu_information::CpuidResult;
+    use crate::features::BoolExpression::{CpuidBitSet, MsrBitSet};
+    use crate::features::DEFAULT_CATEGORY;
+
+    struct TestCpu {
+        leaf1_ecx: u32,
+        leaf7_ebx: u32,
+        msr: Option<u64>,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let result = |ebx, ecx| CpuidResult {
+                eax: 7,
+                ebx,
+                ecx,
+                edx: 0,
+            };
+
+            match (query.leaf, query.subleaf) {
+                (0, 0) => Some(result(0, 0)),
+                (1, 0) => Some(result(0, self.leaf1_ecx)),
+                (7, 0) => Some(result(self.leaf7_ebx, 0)),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            self.msr.filter(|_| index == 0x3a)
+        }
+    }
+
+    fn catalog() -> Vec<Feature> {
+        vec![
+            Feature::new(DEFAULT_CATEGORY, "VMX", CpuidBitSet(1.into(), Ecx, 5)),
+            Feature::new(DEFAULT_CATEGORY, "AVX512F", CpuidBitSet(7.into(), Ebx, 16)),
+            Feature::new(DEFAULT_CATEGORY, "SMEP", CpuidBitSet(7.into(), Ebx, 7)),
+            Feature::new(DEFAULT_CATEGORY, "VMX locked", MsrBitSet(0x3a, 0)).restriction(),
+        ]
+    }
+
+    #[test]
+    fn hosts_are_intersected() {
+        let old = TestCpu {
+            leaf1_ecx: 0x20,
+            leaf7_ebx: 0x80,
+            msr: Some(5),
+        };
+        let new = TestCpu {
+            leaf1_ecx: 0x21,
+            leaf7_ebx: 0x1_0080,
+            msr: None,
+        };
+        let baseline = Baseline::new(
+            &catalog(),
+            &[("old".to_owned(), &old), ("new".to_owned(), &new)],
+        );
+
+        assert_eq!(
+            baseline
+                .features
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["VMX", "SMEP"]
+        );
+        assert_eq!(
+            baseline.partial_features,
+            vec![PartialFeature {
+                category: DEFAULT_CATEGORY.to_owned(),
+                name: "AVX512F".to_owned(),
+                missing_on: vec!["old".to_owned()],
+            }]
+        );
+
+        let mask = |leaf, register: &str| {
+            baseline
+                .cpuid
+                .iter()
+                .find(|m| m.leaf == leaf && m.subleaf == 0 && m.register == register)
+                .map(|m| m.mask)
+        };
+        assert_eq!(mask(1, "ECX"), Some(0x20));
+        assert_eq!(mask(7, "EBX"), Some(0x80));
+        assert_eq!(mask(0x8000_0001, "EDX"), Some(0));
+        assert_eq!(baseline.xsave, 0);
+        assert_eq!(baseline.physical_address_bits, None);
+
+        let sections = baseline.sections();
+        assert_eq!(sections[0].title, "Common baseline of 2 hosts");
+        assert_eq!(sections[0].get("CPUID 0x7.0x0 EBX"), Some("0x00000080"));
+        assert_eq!(sections[1].get(DEFAULT_CATEGORY), Some("VMX, SMEP"));
+        assert_eq!(
+            sections[2].get(&format!("{}: AVX512F", DEFAULT_CATEGORY)),
+            Some("missing on old")
+        );
+    }
+
+    #[test]
+    fn baseline_serializes_to_json() {
+        let host = TestCpu {
+            leaf1_ecx: 0x20,
+            leaf7_ebx: 0,
+            msr: None,
+        };
+        let json = serde_json::to_value(Baseline::new(&catalog(), &[("a".to_owned(), &host)]))
+            .expect("serializable");
+
+        assert_eq!(json["hosts"], serde_json::json!(["a"]));
+        assert_eq!(json["features"][0]["name"], "VMX");
+        assert_eq!(json["cpuid"][0]["mask"], 0x20);
+        assert_eq!(json["physical_address_bits"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn restrictions_are_left_out() {
+        let locked = TestCpu {
+            leaf1_ecx: 0x20,
+            leaf7_ebx: 0,
+            msr: Some(1),
+        };
+        let unlocked = TestCpu {
+            msr: Some(0),
+            ..locked
+        };
+        let baseline = Baseline::new(
+            &catalog(),
+            &[("a".to_owned(), &locked), ("b".to_owned(), &unlocked)],
+        );
+
+        assert!(baseline.features.iter().all(|f| f.name != "VMX locked"));
+        assert!(baseline.partial_features.is_empty());
+    }
+}
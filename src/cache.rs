@@ -0,0 +1,245 @@
+//! # Decode the Cache Hierarchy
+//!
+//! Intel CPUs describe their caches in CPUID leaf 4, AMD CPUs with
+//! topology extensions in CPUID leaf 0x8000001D. Both use the same
+//! encoding: each subleaf describes one cache until a subleaf with
+//! cache type 0.
+//!
+//! See the Intel SDM Vol. 2A, CPUID—CPU Identification, and the AMD
+//! APM Vol. 3, Appendix E.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult, Vendor};
+use crate::report::Section;
+
+/// Intel's deterministic cache parameters leaf.
+pub const CPUID_CACHE_PARAMETERS: u32 = 0x4;
+
+/// AMD's cache topology leaf.
+pub const CPUID_AMD_CACHE_TOPOLOGY: u32 = 0x8000_001d;
+
+/// The bit in CPUID leaf 0x80000001 ECX that enumerates leaf
+/// 0x8000001D.
+const TOPOLOGY_EXTENSIONS: u32 = 22;
+
+/// The kind of data a cache holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheType {
+    Data,
+    Instruction,
+    Unified,
+    Reserved(u32),
+}
+
+impl std::fmt::Display for CacheType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CacheType::Data => f.write_str("data"),
+            CacheType::Instruction => f.write_str("instruction"),
+            CacheType::Unified => f.write_str("unified"),
+            CacheType::Reserved(value) => write!(f, "type {}", value),
+        }
+    }
+}
+
+/// A single cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cache {
+    pub level: u32,
+    pub cache_type: CacheType,
+    pub ways: u32,
+    pub partitions: u32,
+    pub line_size: u32,
+    pub sets: u32,
+    pub fully_associative: bool,
+
+    /// The maximum number of logical processors that share the
+    /// cache.
+    pub shared_by: u32,
+
+    /// Whether the cache includes the lower levels.
+    pub inclusive: bool,
+}
+
+impl Cache {
+    /// Decode a subleaf. Returns `None` for the terminating subleaf.
+    pub fn from_cpuid(r: CpuidResult) -> Option<Self> {
+        let cache_type = match r.eax & 0x1f {
+            0 => return None,
+            1 => CacheType::Data,
+            2 => CacheType::Instruction,
+            3 => CacheType::Unified,
+            other => CacheType::Reserved(other),
+        };
+
+        Some(Cache {
+            level: (r.eax >> 5) & 0x7,
+            cache_type,
+            ways: (r.ebx >> 22) + 1,
+            partitions: ((r.ebx >> 12) & 0x3ff) + 1,
+            line_size: (r.ebx & 0xfff) + 1,
+            sets: r.ecx.wrapping_add(1),
+            fully_associative: (r.eax >> 9) & 1 == 1,
+            shared_by: ((r.eax >> 14) & 0xfff) + 1,
+            inclusive: (r.edx >> 1) & 1 == 1,
+        })
+    }
+
+    /// The size in bytes.
+    pub fn size(&self) -> u64 {
+        u64::from(self.ways)
+            * u64::from(self.partitions)
+            * u64::from(self.line_size)
+            * u64::from(self.sets)
+    }
+}
+
+/// Format a size in bytes with a binary unit.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 && b % (1 << 20) == 0 => format!("{} MiB", b >> 20),
+        b if b >= 1 << 10 && b % (1 << 10) == 0 => format!("{} KiB", b >> 10),
+        b => format!("{} bytes", b),
+    }
+}
+
+impl std::fmt::Display for Cache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}, ", format_size(self.size()))?;
+
+        if self.fully_associative {
+            write!(f, "fully associative")?;
+        } else {
+            write!(f, "{}-way", self.ways)?;
+        }
+
+        write!(
+            f,
+            ", {}-byte lines, shared by {} logical processors",
+            self.line_size, self.shared_by
+        )?;
+
+        if self.inclusive {
+            write!(f, ", inclusive")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The cache leaf that applies to the CPU.
+pub fn cache_leaf(cpu_info: &dyn CpuInformation) -> Option<u32> {
+    let leaf = match cpu_info.vendor()? {
+        Vendor::Intel => CPUID_CACHE_PARAMETERS,
+        Vendor::Amd => {
+            let topology_extensions = cpu_info
+                .cpuid(0x8000_0001.into())
+                .is_some_and(|r| (r.ecx >> TOPOLOGY_EXTENSIONS) & 1 == 1);
+
+            if !topology_extensions {
+                return None;
+            }
+
+            CPUID_AMD_CACHE_TOPOLOGY
+        }
+    };
+
+    cpu_info.is_cpuid_query_valid(leaf.into()).then_some(leaf)
+}
+
+/// Walk the subleaves of a cache leaf.
+pub fn caches(cpu_info: &dyn CpuInformation, leaf: u32) -> Vec<Cache> {
+    (0..)
+        .map(|subleaf| cpu_info.cpuid(CpuidQuery { leaf, subleaf }))
+        .map_while(|r| r.and_then(Cache::from_cpuid))
+        .collect()
+}
+
+fn caches_section(leaf: u32, caches: &[Cache]) -> Section {
+    let mut section = Section::new(&format!("Caches (CPUID {:#x})", leaf));
+
+    for cache in caches {
+        section.row(&format!("L{} {}", cache.level, cache.cache_type), cache);
+    }
+
+    section
+}
+
+/// Decode the cache hierarchy, if the CPU enumerates it.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    cache_leaf(cpu_info)
+        .map(|leaf| (leaf, caches(cpu_info, leaf)))
+        .filter(|(_, caches)| !caches.is_empty())
+        .map(|(leaf, caches)| caches_section(leaf, &caches))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(eax: u32, ebx: u32, ecx: u32, edx: u32) -> CpuidResult {
+        CpuidResult { eax, ebx, ecx, edx }
+    }
+
+    #[test]
+    fn intel_caches_are_decoded() {
+        // A Coffee Lake with 8 cores.
+        let caches: Vec<Cache> = [
+            result(0x1c00_4121, 0x01c0_003f, 0x0000_003f, 0x0000_0000),
+            result(0x1c00_4122, 0x01c0_003f, 0x0000_003f, 0x0000_0000),
+            result(0x1c00_4143, 0x00c0_003f, 0x0000_03ff, 0x0000_0000),
+            result(0x1c03_c163, 0x03c0_003f, 0x0000_3fff, 0x0000_0006),
+        ]
+        .iter()
+        .filter_map(|&r| Cache::from_cpuid(r))
+        .collect();
+
+        let section = caches_section(4, &caches);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(
+            row("L1 data"),
+            "32 KiB, 8-way, 64-byte lines, shared by 2 logical processors"
+        );
+        assert_eq!(
+            row("L1 instruction"),
+            "32 KiB, 8-way, 64-byte lines, shared by 2 logical processors"
+        );
+        assert_eq!(
+            row("L2 unified"),
+            "256 KiB, 4-way, 64-byte lines, shared by 2 logical processors"
+        );
+        assert_eq!(
+            row("L3 unified"),
+            "16 MiB, 16-way, 64-byte lines, shared by 16 logical processors, inclusive"
+        );
+    }
+
+    #[test]
+    fn amd_caches_are_decoded() {
+        // The L3 of a Zen 3 CCX.
+        let cache = Cache::from_cpuid(result(0x0003_c163, 0x03c0_003f, 0x0000_7fff, 0x0000_0001))
+            .expect("valid cache");
+
+        assert_eq!(cache.level, 3);
+        assert_eq!(cache.size(), 32 << 20);
+        assert_eq!(cache.shared_by, 16);
+        assert!(!cache.inclusive);
+    }
+
+    #[test]
+    fn fully_associative_caches_have_no_ways() {
+        let cache = Cache::from_cpuid(result(0x0000_0321, 0x0000_003f, 0, 0)).expect("valid cache");
+
+        assert_eq!(
+            cache.to_string(),
+            "64 bytes, fully associative, 64-byte lines, shared by 1 logical processors"
+        );
+    }
+
+    #[test]
+    fn walk_stops_at_null_cache() {
+        assert_eq!(Cache::from_cpuid(result(0, 0, 0, 0)), None);
+    }
+}
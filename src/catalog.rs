@@ -0,0 +1,794 @@
+//! # Built-in Feature Catalog
+//!
+//! The features that are checked by default. Each feature belongs to
+//! a category, which is used to group the report.
+
+use std::collections::BTreeSet as Set;
+
+use crate::avx10::AVX10;
+use crate::cpu_information::CpuidRegister::*;
+use crate::cpu_information::Vendor::*;
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::features::BoolExpression::*;
+use crate::features::{Feature, FeatureStatus};
+use crate::key_locker::{CPUID_KEY_LOCKER, KEY_LOCKER};
+use crate::msr::{
+    APIC_BASE_GLOBAL_ENABLE, APIC_BASE_X2APIC_ENABLE, EFER, EFER_SVME, IA32_APIC_BASE,
+};
+use crate::mwait::WAITPKG;
+use crate::sev::{CPUID_MEMORY_ENCRYPTION, SYSCFG, SYSCFG_MEM_ENCRYPTION_MOD_EN};
+use crate::sgx::{CPUID_SGX, FEATURE_CONTROL_SGX_ENABLE, FEATURE_CONTROL_SGX_LC};
+use crate::speculation::{
+    amd_speculation_control, arch_capability, IA32_MCU_OPT_CTRL, IA32_TSX_CTRL, MSR_TSX_FORCE_ABORT,
+};
+use crate::svm::{svm_feature, VM_CR, VM_CR_LOCK, VM_CR_SVMDIS};
+use crate::vmx::{
+    entry_control, exit_control, pinbased_control, procbased_control, secondary_control,
+    tertiary_control, IA32_FEATURE_CONTROL, IA32_VMX_EPT_VPID_CAP, IA32_VMX_VMFUNC,
+};
+use crate::xsave::{supervisor_component, CET_S_STATE, CET_U_STATE, UINTR_STATE};
+
+pub const FIRMWARE: &str = "Firmware configuration";
+pub const INSTRUCTION_SET: &str = "Instruction set";
+pub const AVX512: &str = "AVX-512";
+pub const MEMORY_PROTECTION: &str = "Memory protection";
+pub const CET: &str = "Control-flow enforcement";
+pub const SGX: &str = "SGX";
+pub const SVM: &str = "SVM";
+pub const CONFIDENTIAL_COMPUTING: &str = "Confidential computing";
+pub const SPECULATION_CONTROL: &str = "Speculation control";
+pub const TSX: &str = "TSX";
+pub const VMX_CONTROLS: &str = "VMX controls";
+pub const EPT: &str = "EPT";
+pub const INTERRUPT_VIRTUALIZATION: &str = "Interrupt virtualization";
+pub const VM_ENTRY_EXIT: &str = "VM entry and exit";
+pub const TIME_VIRTUALIZATION: &str = "Time virtualization";
+
+/// The built-in features.
+pub fn features() -> Vec<Feature> {
+    // The AVX-512 extensions all build on the foundation.
+    let avx512 =
+        |name: &str, expression| Feature::new(AVX512, name, expression).depends_on(&["AVX512F"]);
+
+    let leaf7_subleaf1 = CpuidQuery {
+        leaf: 7,
+        subleaf: 1,
+    };
+
+    let mut features = vec![
+        Feature::new(
+            FIRMWARE,
+            "IA32_FEATURE_CONTROL locked",
+            MsrBitSet(IA32_FEATURE_CONTROL, 0),
+        )
+        .vendor(Intel)
+        .restriction(),
+        Feature::new(
+            FIRMWARE,
+            "VMX enabled inside SMX",
+            MsrBitSet(IA32_FEATURE_CONTROL, 1),
+        )
+        .vendor(Intel)
+        .host_state(),
+        Feature::new(
+            FIRMWARE,
+            "VMX enabled outside SMX",
+            MsrBitSet(IA32_FEATURE_CONTROL, 2),
+        )
+        .vendor(Intel)
+        .host_state(),
+        // If firmware didn't lock IA32_FEATURE_CONTROL, the OS can still
+        // enable VMX itself.
+        Feature::new(
+            FIRMWARE,
+            "VMX usable",
+            !MsrBitSet(IA32_FEATURE_CONTROL, 0) | MsrBitSet(IA32_FEATURE_CONTROL, 2),
+        )
+        .vendor(Intel)
+        .host_state(),
+        Feature::new(FIRMWARE, "VM_CR locked", MsrBitSet(VM_CR, VM_CR_LOCK))
+            .vendor(Amd)
+            .restriction(),
+        Feature::new(
+            FIRMWARE,
+            "SVM disabled by firmware",
+            MsrBitSet(VM_CR, VM_CR_SVMDIS),
+        )
+        .vendor(Amd)
+        .restriction(),
+        // Like IA32_FEATURE_CONTROL, an unlocked VM_CR lets the OS
+        // clear SVMDIS itself.
+        Feature::new(
+            FIRMWARE,
+            "SVM usable",
+            !MsrBitSet(VM_CR, VM_CR_SVMDIS) | !MsrBitSet(VM_CR, VM_CR_LOCK),
+        )
+        .vendor(Amd)
+        .host_state(),
+        Feature::new(INSTRUCTION_SET, "AVX", CpuidBitSet(1.into(), Ecx, 28)),
+        Feature::new(INSTRUCTION_SET, "MMX", CpuidBitSet(1.into(), Edx, 23)),
+        Feature::new(INSTRUCTION_SET, "SHA", CpuidBitSet(7.into(), Ebx, 29)),
+        Feature::new(INSTRUCTION_SET, "LASS", CpuidBitSet(leaf7_subleaf1, Eax, 6)),
+        Feature::new(
+            INSTRUCTION_SET,
+            "LKGS",
+            CpuidBitSet(leaf7_subleaf1, Eax, 18),
+        ),
+        // FRED relies on LKGS to load the user GS base.
+        Feature::new(
+            INSTRUCTION_SET,
+            "FRED",
+            CpuidBitSet(leaf7_subleaf1, Eax, 17),
+        )
+        .depends_on(&["LKGS"]),
+        Feature::new(
+            INSTRUCTION_SET,
+            "WRMSRNS",
+            CpuidBitSet(leaf7_subleaf1, Eax, 19),
+        ),
+        Feature::new(INSTRUCTION_SET, "LAM", CpuidBitSet(leaf7_subleaf1, Eax, 26)),
+        Feature::new(
+            INSTRUCTION_SET,
+            "WAITPKG",
+            CpuidBitSet(7.into(), Ecx, WAITPKG),
+        ),
+        Feature::new(INSTRUCTION_SET, "UINTR", CpuidBitSet(7.into(), Edx, 5)),
+        // The OS needs XSAVES to switch the UINTR state between
+        // threads.
+        Feature::new(
+            INSTRUCTION_SET,
+            "UINTR XSAVE state",
+            supervisor_component(UINTR_STATE),
+        )
+        .depends_on(&["UINTR"]),
+        Feature::new(INSTRUCTION_SET, "APX", CpuidBitSet(leaf7_subleaf1, Edx, 21)),
+        Feature::new(AVX512, "AVX512F", CpuidBitSet(7.into(), Ebx, 16)),
+        avx512("AVX512CD", CpuidBitSet(7.into(), Ebx, 28)),
+        avx512("AVX512BW", CpuidBitSet(7.into(), Ebx, 30)),
+        avx512("AVX512DQ", CpuidBitSet(7.into(), Ebx, 17)),
+        avx512("AVX512VL", CpuidBitSet(7.into(), Ebx, 31)),
+        avx512("AVX512_IFMA", CpuidBitSet(7.into(), Ebx, 21)),
+        // Only Xeon Phi CPUs have PF, ER, 4VNNIW and 4FMAPS.
+        avx512("AVX512PF", CpuidBitSet(7.into(), Ebx, 26)),
+        avx512("AVX512ER", CpuidBitSet(7.into(), Ebx, 27)),
+        avx512("AVX512_VBMI", CpuidBitSet(7.into(), Ecx, 1)),
+        avx512("AVX512_VBMI2", CpuidBitSet(7.into(), Ecx, 6)),
+        avx512("AVX512_VNNI", CpuidBitSet(7.into(), Ecx, 11)),
+        avx512("AVX512_BITALG", CpuidBitSet(7.into(), Ecx, 12)),
+        avx512("AVX512_VPOPCNTDQ", CpuidBitSet(7.into(), Ecx, 14)),
+        avx512("AVX512_4VNNIW", CpuidBitSet(7.into(), Edx, 2)),
+        avx512("AVX512_4FMAPS", CpuidBitSet(7.into(), Edx, 3)),
+        avx512("AVX512_VP2INTERSECT", CpuidBitSet(7.into(), Edx, 8)),
+        avx512("AVX512_FP16", CpuidBitSet(7.into(), Edx, 23)),
+        avx512("AVX512_BF16", CpuidBitSet(leaf7_subleaf1, Eax, 5)),
+        // AVX10 implies the AVX-512 instructions of its version, but
+        // not necessarily the individual AVX-512 bits.
+        Feature::new(AVX512, "AVX10", CpuidBitSet(leaf7_subleaf1, Edx, AVX10)),
+        Feature::new(
+            INSTRUCTION_SET,
+            "Key Locker",
+            CpuidBitSet(7.into(), Ecx, KEY_LOCKER),
+        ),
+        Feature::new(
+            INSTRUCTION_SET,
+            "AES Key Locker instructions",
+            CpuidBitSet(CPUID_KEY_LOCKER.into(), Ebx, 0),
+        )
+        .depends_on(&["Key Locker"]),
+        Feature::new(
+            INSTRUCTION_SET,
+            "Wide Key Locker instructions",
+            CpuidBitSet(CPUID_KEY_LOCKER.into(), Ebx, 2),
+        )
+        .depends_on(&["AES Key Locker instructions"]),
+        Feature::new(MEMORY_PROTECTION, "PKU", CpuidBitSet(7.into(), Ecx, 3)),
+        // OSPKE reflects CR4.PKE, i.e. whether the OS enabled PKU when
+        // the dump was taken.
+        Feature::new(
+            MEMORY_PROTECTION,
+            "PKU enabled by OS",
+            CpuidBitSet(7.into(), Ecx, 4),
+        )
+        .depends_on(&["PKU"])
+        .host_state(),
+        Feature::new(MEMORY_PROTECTION, "PKS", CpuidBitSet(7.into(), Ecx, 31)),
+        Feature::new(CET, "CET shadow stack", CpuidBitSet(7.into(), Ecx, 7)),
+        Feature::new(CET, "CET IBT", CpuidBitSet(7.into(), Edx, 20)),
+        // The OS context-switches the CET MSRs with XSAVES.
+        Feature::new(CET, "CET_U XSAVE state", supervisor_component(CET_U_STATE)),
+        Feature::new(CET, "CET_S XSAVE state", supervisor_component(CET_S_STATE)),
+        Feature::new(SGX, "SGX", CpuidBitSet(7.into(), Ebx, 2)).vendor(Intel),
+        Feature::new(SGX, "SGX1", CpuidBitSet(CPUID_SGX.into(), Eax, 0))
+            .vendor(Intel)
+            .depends_on(&["SGX"]),
+        Feature::new(SGX, "SGX2", CpuidBitSet(CPUID_SGX.into(), Eax, 1))
+            .vendor(Intel)
+            .depends_on(&["SGX1"]),
+        // Unlike VMX, SGX can only be used once IA32_FEATURE_CONTROL
+        // is locked.
+        Feature::new(
+            SGX,
+            "SGX enabled by firmware",
+            MsrBitSet(IA32_FEATURE_CONTROL, 0)
+                & MsrBitSet(IA32_FEATURE_CONTROL, FEATURE_CONTROL_SGX_ENABLE),
+        )
+        .vendor(Intel)
+        .depends_on(&["SGX"])
+        .host_state(),
+        Feature::new(
+            SGX,
+            "SGX launch control",
+            CpuidBitSet(7.into(), Ecx, 30)
+                & MsrBitSet(IA32_FEATURE_CONTROL, FEATURE_CONTROL_SGX_LC),
+        )
+        .vendor(Intel)
+        .depends_on(&["SGX enabled by firmware"]),
+        // Intel and AMD enumerate the same speculation control MSRs in
+        // different CPUID leaves.
+        Feature::new(
+            SPECULATION_CONTROL,
+            "IA32_SPEC_CTRL",
+            CpuidBitSet(7.into(), Edx, 26)
+                | CpuidBitSet(7.into(), Edx, 27)
+                | CpuidBitSet(7.into(), Edx, 31)
+                | amd_speculation_control(14)
+                | amd_speculation_control(15)
+                | amd_speculation_control(24),
+        ),
+        Feature::new(
+            SPECULATION_CONTROL,
+            "IA32_PRED_CMD",
+            CpuidBitSet(7.into(), Edx, 26) | amd_speculation_control(12),
+        ),
+        Feature::new(
+            SPECULATION_CONTROL,
+            "IBRS",
+            CpuidBitSet(7.into(), Edx, 26) | amd_speculation_control(14),
+        )
+        .depends_on(&["IA32_SPEC_CTRL"]),
+        // AMD calls its equivalent of enhanced IBRS "Automatic IBRS".
+        Feature::new(
+            SPECULATION_CONTROL,
+            "Enhanced IBRS",
+            arch_capability(1) | CpuidBitSet(0x8000_0021.into(), Eax, 8),
+        )
+        .depends_on(&["IBRS"]),
+        Feature::new(
+            SPECULATION_CONTROL,
+            "STIBP",
+            CpuidBitSet(7.into(), Edx, 27) | amd_speculation_control(15),
+        )
+        .depends_on(&["IA32_SPEC_CTRL"]),
+        Feature::new(
+            SPECULATION_CONTROL,
+            "SSBD",
+            CpuidBitSet(7.into(), Edx, 31) | amd_speculation_control(24),
+        )
+        .depends_on(&["IA32_SPEC_CTRL"]),
+        // VERW clears the CPU buffers affected by MDS, TAA and MMIO
+        // stale data.
+        Feature::new(
+            SPECULATION_CONTROL,
+            "VERW buffer clearing",
+            CpuidBitSet(7.into(), Edx, 10),
+        )
+        .vendor(Intel),
+        Feature::new(
+            SPECULATION_CONTROL,
+            "SRBDS mitigation control",
+            CpuidBitSet(7.into(), Edx, 9),
+        )
+        .vendor(Intel),
+        Feature::new(
+            SPECULATION_CONTROL,
+            "SRBDS mitigation active",
+            CpuidBitSet(7.into(), Edx, 9) & !MsrBitSet(IA32_MCU_OPT_CTRL, 0),
+        )
+        .vendor(Intel)
+        .depends_on(&["SRBDS mitigation control"]),
+        // If TSX_CPUID_CLEAR is set, HLE and RTM are hidden from CPUID
+        // as well, so TSX looks absent even though the CPU has it.
+        Feature::new(TSX, "HLE", CpuidBitSet(7.into(), Ebx, 4)).vendor(Intel),
+        Feature::new(TSX, "RTM", CpuidBitSet(7.into(), Ebx, 11)).vendor(Intel),
+        Feature::new(
+            TSX,
+            "RTM always aborts (microcode)",
+            CpuidBitSet(7.into(), Edx, 11),
+        )
+        .vendor(Intel)
+        .restriction(),
+        Feature::new(
+            TSX,
+            "RTM disabled via IA32_TSX_CTRL",
+            arch_capability(7) & MsrBitSet(IA32_TSX_CTRL, 0),
+        )
+        .vendor(Intel)
+        .restriction(),
+        Feature::new(
+            TSX,
+            "TSX hidden via IA32_TSX_CTRL",
+            arch_capability(7) & MsrBitSet(IA32_TSX_CTRL, 1),
+        )
+        .vendor(Intel)
+        .restriction(),
+        Feature::new(
+            TSX,
+            "RTM disabled via TSX_FORCE_ABORT",
+            CpuidBitSet(7.into(), Edx, 13) & MsrBitSet(MSR_TSX_FORCE_ABORT, 0),
+        )
+        .vendor(Intel)
+        .restriction(),
+        Feature::new(
+            TSX,
+            "RTM usable",
+            CpuidBitSet(7.into(), Ebx, 11)
+                & !CpuidBitSet(7.into(), Edx, 11)
+                & !(arch_capability(7) & MsrBitSet(IA32_TSX_CTRL, 0))
+                & !(CpuidBitSet(7.into(), Edx, 13) & MsrBitSet(MSR_TSX_FORCE_ABORT, 0)),
+        )
+        .vendor(Intel)
+        .depends_on(&["RTM"]),
+        Feature::new(SVM, "SVM", CpuidBitSet(0x8000_0001.into(), Ecx, 2)).vendor(Amd),
+        // EFER.SVME shows whether the OS that took the dump had turned
+        // SVM on, e.g. because a hypervisor was running.
+        Feature::new(SVM, "SVM enabled in EFER", MsrBitSet(EFER, EFER_SVME))
+            .vendor(Amd)
+            .depends_on(&["SVM"])
+            .host_state(),
+        Feature::new(SVM, "Nested paging", svm_feature(0))
+            .vendor(Amd)
+            .depends_on(&["SVM"]),
+        Feature::new(SVM, "NRIP save", svm_feature(3))
+            .vendor(Amd)
+            .depends_on(&["SVM"]),
+        Feature::new(SVM, "VMCB clean bits", svm_feature(5))
+            .vendor(Amd)
+            .depends_on(&["SVM"]),
+        Feature::new(SVM, "Decode assists", svm_feature(7))
+            .vendor(Amd)
+            .depends_on(&["SVM"]),
+        Feature::new(SVM, "Pause filter", svm_feature(10))
+            .vendor(Amd)
+            .depends_on(&["SVM"]),
+        // With the threshold, only PAUSE instructions in quick
+        // succession count towards the filter.
+        Feature::new(SVM, "Pause filter threshold", svm_feature(12))
+            .vendor(Amd)
+            .depends_on(&["Pause filter"]),
+        Feature::new(
+            CONFIDENTIAL_COMPUTING,
+            "SME",
+            CpuidBitSet(CPUID_MEMORY_ENCRYPTION.into(), Eax, 0),
+        )
+        .vendor(Amd),
+        // TSME encrypts all memory without SYSCFG being involved and
+        // cannot be detected from a dump.
+        Feature::new(
+            CONFIDENTIAL_COMPUTING,
+            "SME enabled",
+            MsrBitSet(SYSCFG, SYSCFG_MEM_ENCRYPTION_MOD_EN),
+        )
+        .vendor(Amd)
+        .depends_on(&["SME"])
+        .host_state(),
+        Feature::new(
+            CONFIDENTIAL_COMPUTING,
+            "SEV",
+            CpuidBitSet(CPUID_MEMORY_ENCRYPTION.into(), Eax, 1),
+        )
+        .vendor(Amd)
+        .depends_on(&["SVM"]),
+        Feature::new(
+            CONFIDENTIAL_COMPUTING,
+            "SEV-ES",
+            CpuidBitSet(CPUID_MEMORY_ENCRYPTION.into(), Eax, 3),
+        )
+        .vendor(Amd)
+        .depends_on(&["SEV"]),
+        Feature::new(
+            CONFIDENTIAL_COMPUTING,
+            "SEV-SNP",
+            CpuidBitSet(CPUID_MEMORY_ENCRYPTION.into(), Eax, 4),
+        )
+        .vendor(Amd)
+        .depends_on(&["SEV-ES"]),
+        Feature::new(SGX, "ENCLV", CpuidBitSet(0x12.into(), Eax, 5))
+            .vendor(Intel)
+            .depends_on(&["SGX"]),
+        // The ENCLS- and ENCLV-exiting bitmaps are VMCS fields that
+        // exist if the respective control is supported.
+        Feature::new(SGX, "ENCLS exiting", secondary_control(15))
+            .vendor(Intel)
+            .depends_on(&["SGX"]),
+        Feature::new(SGX, "ENCLV exiting", secondary_control(28))
+            .vendor(Intel)
+            .depends_on(&["ENCLV"]),
+        Feature::new(EPT, "EPT", secondary_control(1)).vendor(Intel),
+        Feature::new(EPT, "EPT 2MB pages", MsrBitSet(IA32_VMX_EPT_VPID_CAP, 16))
+            .vendor(Intel)
+            .depends_on(&["EPT"]),
+        Feature::new(EPT, "EPT 1GB pages", MsrBitSet(IA32_VMX_EPT_VPID_CAP, 17))
+            .vendor(Intel)
+            .depends_on(&["EPT"]),
+        Feature::new(
+            EPT,
+            "EPT accessed and dirty flags",
+            MsrBitSet(IA32_VMX_EPT_VPID_CAP, 21),
+        )
+        .vendor(Intel)
+        .depends_on(&["EPT"]),
+        Feature::new(
+            EPT,
+            "EPT 5-level page walk",
+            MsrBitSet(IA32_VMX_EPT_VPID_CAP, 7),
+        )
+        .vendor(Intel)
+        .depends_on(&["EPT"]),
+        Feature::new(EPT, "VPID", secondary_control(5)).vendor(Intel),
+        Feature::new(VMX_CONTROLS, "VM functions", secondary_control(13)).vendor(Intel),
+        Feature::new(VMX_CONTROLS, "Bus-lock VM exits", secondary_control(30)).vendor(Intel),
+        // Notify VM exits fire when the guest blocks event delivery for
+        // too long, e.g. with a microcode-level instruction loop.
+        Feature::new(VMX_CONTROLS, "Notify VM exits", secondary_control(31)).vendor(Intel),
+        Feature::new(EPT, "EPTP switching", MsrBitSet(IA32_VMX_VMFUNC, 0))
+            .vendor(Intel)
+            .depends_on(&["VM functions", "EPT"]),
+        // Windows HVCI relies on this.
+        Feature::new(EPT, "Mode-based execute control", secondary_control(22))
+            .vendor(Intel)
+            .depends_on(&["EPT"]),
+        Feature::new(EPT, "Sub-page write permissions", secondary_control(23))
+            .vendor(Intel)
+            .depends_on(&["EPT"]),
+        // PML logs guest-physical addresses whose EPT dirty flag is set.
+        Feature::new(EPT, "Page-modification logging", secondary_control(17))
+            .vendor(Intel)
+            .depends_on(&["EPT accessed and dirty flags"]),
+        Feature::new(VMX_CONTROLS, "VMX", CpuidBitSet(1.into(), Ecx, 5)).vendor(Intel),
+        Feature::new(VMX_CONTROLS, "Unrestricted Guest", secondary_control(7))
+            .vendor(Intel)
+            .depends_on(&["EPT"]),
+        Feature::new(VMX_CONTROLS, "VMCS Shadowing", secondary_control(14)).vendor(Intel),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "x2APIC",
+            CpuidBitSet(1.into(), Ecx, 21),
+        ),
+        // Firmware or the OS switch the APIC to x2APIC mode, which also
+        // needs the APIC to be enabled at all.
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "x2APIC enabled",
+            MsrBitSet(IA32_APIC_BASE, APIC_BASE_X2APIC_ENABLE)
+                & MsrBitSet(IA32_APIC_BASE, APIC_BASE_GLOBAL_ENABLE),
+        )
+        .depends_on(&["x2APIC"])
+        .host_state(),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "TPR shadow",
+            procbased_control(21),
+        )
+        .vendor(Intel),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "APIC-register virtualization",
+            secondary_control(8),
+        )
+        .vendor(Intel)
+        .depends_on(&["TPR shadow"]),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "Virtualize x2APIC mode",
+            secondary_control(4),
+        )
+        .vendor(Intel)
+        .depends_on(&["TPR shadow"]),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "Virtual-interrupt delivery",
+            secondary_control(9),
+        )
+        .vendor(Intel)
+        .depends_on(&["APIC-register virtualization"]),
+        Feature::new(VMX_CONTROLS, "Virtual NMIs", pinbased_control(5)).vendor(Intel),
+        Feature::new(VMX_CONTROLS, "VMX preemption timer", pinbased_control(6)).vendor(Intel),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "Process posted interrupts",
+            pinbased_control(7),
+        )
+        .vendor(Intel)
+        .depends_on(&["Virtual-interrupt delivery"]),
+        // AVIC is enabled per guest in the VMCB. There is no global
+        // enable bit beyond SVM itself.
+        Feature::new(INTERRUPT_VIRTUALIZATION, "AVIC", svm_feature(13))
+            .vendor(Amd)
+            .depends_on(&["SVM", "SVM usable"]),
+        Feature::new(INTERRUPT_VIRTUALIZATION, "x2AVIC", svm_feature(18))
+            .vendor(Amd)
+            .depends_on(&["AVIC"]),
+        Feature::new(INTERRUPT_VIRTUALIZATION, "Virtual GIF", svm_feature(16))
+            .vendor(Amd)
+            .depends_on(&["SVM"]),
+        // Virtual NMI masking relies on the virtual GIF.
+        Feature::new(INTERRUPT_VIRTUALIZATION, "Virtual NMI", svm_feature(25))
+            .vendor(Amd)
+            .depends_on(&["Virtual GIF"]),
+        Feature::new(
+            INTERRUPT_VIRTUALIZATION,
+            "IPI virtualization",
+            tertiary_control(4),
+        )
+        .vendor(Intel)
+        .depends_on(&["TPR shadow", "Virtual-interrupt delivery"]),
+        Feature::new(VMX_CONTROLS, "LOADIWKEY exiting", tertiary_control(0)).vendor(Intel),
+        // HLAT has no CPUID bit of its own. It is enumerated by VMX
+        // support and the tertiary controls.
+        Feature::new(
+            EPT,
+            "HLAT",
+            CpuidBitSet(1.into(), Ecx, 5) & tertiary_control(1),
+        )
+        .vendor(Intel)
+        .depends_on(&["VMX", "EPT"]),
+        Feature::new(
+            VMX_CONTROLS,
+            "Virtualize IA32_SPEC_CTRL",
+            tertiary_control(7),
+        )
+        .vendor(Intel),
+        // Guests probe for both. The deadline timer has to be emulated
+        // on top of the host's timer, and IA32_TSC_ADJUST reflects
+        // writes to the guest TSC.
+        Feature::new(
+            TIME_VIRTUALIZATION,
+            "TSC deadline timer",
+            CpuidBitSet(1.into(), Ecx, 24),
+        ),
+        Feature::new(
+            TIME_VIRTUALIZATION,
+            "IA32_TSC_ADJUST",
+            CpuidBitSet(7.into(), Ebx, 1),
+        ),
+        Feature::new(TIME_VIRTUALIZATION, "TSC offsetting", procbased_control(3)).vendor(Intel),
+        // Scaling multiplies the guest TSC by the TSC multiplier before
+        // the offset is applied. This allows migrating guests between
+        // hosts with different TSC frequencies.
+        Feature::new(TIME_VIRTUALIZATION, "TSC scaling", secondary_control(25))
+            .vendor(Intel)
+            .depends_on(&["TSC offsetting"]),
+        Feature::new(
+            VM_ENTRY_EXIT,
+            "IA32_PAT switching",
+            entry_control(14) & exit_control(18) & exit_control(19),
+        )
+        .vendor(Intel),
+        Feature::new(
+            VM_ENTRY_EXIT,
+            "IA32_EFER switching",
+            entry_control(15) & exit_control(20) & exit_control(21),
+        )
+        .vendor(Intel),
+        Feature::new(
+            VM_ENTRY_EXIT,
+            "IA32_PERF_GLOBAL_CTRL switching",
+            entry_control(13) & exit_control(12),
+        )
+        .vendor(Intel),
+        Feature::new(
+            VM_ENTRY_EXIT,
+            "CET state switching",
+            entry_control(20) & exit_control(28),
+        )
+        .vendor(Intel),
+    ];
+
+    // Composite features give a single verdict for a whole chain of
+    // prerequisites.
+    let unrestricted_guest_usable = all_of(
+        &features,
+        VMX_CONTROLS,
+        "Unrestricted guest usable",
+        &["VMX", "VMX usable", "EPT", "Unrestricted Guest"],
+    )
+    .vendor(Intel);
+    features.push(unrestricted_guest_usable);
+
+    // Shadow stacks and IBT for user space and the kernel.
+    let cet = all_of(
+        &features,
+        CET,
+        "CET ready",
+        &[
+            "CET shadow stack",
+            "CET IBT",
+            "CET_U XSAVE state",
+            "CET_S XSAVE state",
+        ],
+    );
+    features.push(cet);
+
+    // What hypervisors usually mean by "APICv".
+    let apicv = all_of(
+        &features,
+        INTERRUPT_VIRTUALIZATION,
+        "APICv complete",
+        &[
+            "APIC-register virtualization",
+            "Virtual-interrupt delivery",
+            "Virtualize x2APIC mode",
+            "Process posted interrupts",
+        ],
+    )
+    .vendor(Intel);
+    features.push(apicv);
+
+    features
+}
+
+/// A feature that is present if all of the named features are
+/// present.
+///
+/// The named features become its dependencies, so the report can
+/// point out which part is missing.
+fn all_of(features: &[Feature], category: &str, name: &str, parts: &[&str]) -> Feature {
+    let expr = parts
+        .iter()
+        .map(|part| {
+            features
+                .iter()
+                .find(|f| f.name == *part)
+                .unwrap_or_else(|| panic!("{} refers to unknown feature {}", name, part))
+                .expression()
+                .clone()
+        })
+        .reduce(|acc, expr| acc & expr)
+        .expect("composite feature has parts");
+
+    Feature::new(category, name, expr).depends_on(parts)
+}
+
+/// Group features by category.
+///
+/// Categories are ordered by their first appearance in `features`.
+/// Within a category, features keep their relative order.
+pub fn by_category(features: &[Feature]) -> Vec<(&str, Vec<&Feature>)> {
+    let mut groups: Vec<(&str, Vec<&Feature>)> = vec![];
+
+    for feature in features {
+        match groups.iter_mut().find(|(c, _)| *c == feature.category) {
+            Some((_, group)) => group.push(feature),
+            None => groups.push((&feature.category, vec![feature])),
+        }
+    }
+
+    groups
+}
+
+/// Prerequisites of `feature` that are absent.
+///
+/// Prerequisites are followed transitively, so the result also
+/// contains absent prerequisites of prerequisites. Names that don't
+/// refer to a feature in `features` are ignored.
+pub fn absent_prerequisites<'a>(
+    feature: &Feature,
+    features: &'a [Feature],
+    cpu_info: &dyn CpuInformation,
+) -> Vec<&'a str> {
+    let mut absent = vec![];
+    let mut visited: Set<&str> = Set::new();
+    let mut pending: Vec<&str> = feature.depends_on.iter().map(|d| d.as_str()).collect();
+
+    while let Some(name) = pending.pop() {
+        let prerequisite = match features.iter().find(|f| f.name == name) {
+            Some(f) if visited.insert(&f.name) => f,
+            _ => continue,
+        };
+
+        if prerequisite.is_present(cpu_info) == FeatureStatus::Absent {
+            absent.push(prerequisite.name.as_str());
+        }
+
+        pending.extend(prerequisite.depends_on.iter().map(|d| d.as_str()));
+    }
+
+    absent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_are_grouped_in_order() {
+        let features = vec![
+            Feature::new("B", "1", MsrBitSet(1, 0)),
+            Feature::new("A", "2", MsrBitSet(1, 1)),
+            Feature::new("B", "3", MsrBitSet(1, 2)),
+        ];
+
+        let groups: Vec<(&str, Vec<&str>)> = by_category(&features)
+            .into_iter()
+            .map(|(c, fs)| (c, fs.iter().map(|f| f.name.as_str()).collect()))
+            .collect();
+
+        assert_eq!(groups, vec![("B", vec!["1", "3"]), ("A", vec!["2"])]);
+    }
+
+    #[test]
+    fn absent_prerequisites_are_found_transitively() {
+        use crate::cpu_information::{CpuidQuery, CpuidResult};
+
+        struct TestCpu;
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, _query: CpuidQuery) -> Option<CpuidResult> {
+                None
+            }
+
+            fn rdmsr(&self, index: u32) -> Option<u64> {
+                Some(if index == 1 { 0b0101 } else { 0 })
+            }
+        }
+
+        let features = vec![
+            Feature::new("X", "A", MsrBitSet(1, 0)),
+            Feature::new("X", "B", MsrBitSet(1, 1)).depends_on(&["A"]),
+            Feature::new("X", "C", MsrBitSet(1, 2)).depends_on(&["B"]),
+            Feature::new("X", "D", MsrBitSet(1, 3)).depends_on(&["C", "Unknown"]),
+            Feature::new("X", "E", MsrBitSet(1, 4)).depends_on(&["E"]),
+        ];
+
+        let absent = |i: usize| absent_prerequisites(&features[i], &features, &TestCpu);
+
+        assert!(absent(0).is_empty());
+        assert_eq!(absent(1), Vec::<&str>::new());
+        assert_eq!(absent(2), vec!["B"]);
+        assert_eq!(absent(3), vec!["B"]);
+        assert_eq!(absent(4), vec!["E"]);
+    }
+
+    #[test]
+    fn composite_features_require_all_parts() {
+        let features = vec![
+            Feature::new("X", "A", MsrBitSet(1, 0)),
+            Feature::new("X", "B", MsrBitSet(1, 1)),
+        ];
+
+        let composite = all_of(&features, "Y", "AB", &["A", "B"]);
+
+        assert_eq!(composite.category, "Y");
+        assert_eq!(composite.depends_on, vec!["A", "B"]);
+        assert_eq!(*composite.expression(), MsrBitSet(1, 0) & MsrBitSet(1, 1));
+    }
+
+    #[test]
+    fn dependencies_refer_to_existing_features() {
+        let features = features();
+
+        for feature in &features {
+            for dependency in &feature.depends_on {
+                assert!(
+                    features.iter().any(|f| &f.name == dependency),
+                    "{} depends on unknown feature {}",
+                    feature.name,
+                    dependency
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn feature_names_are_unique() {
+        let features = features();
+
+        for (i, feature) in features.iter().enumerate() {
+            assert!(
+                features[i + 1..].iter().all(|f| f.name != feature.name),
+                "duplicate feature {}",
+                feature.name
+            );
+        }
+    }
+}
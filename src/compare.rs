@@ -0,0 +1,160 @@
+//! # Compare Guest and Host
+//!
+//! A hypervisor decides which CPU features its guests see. Comparing
+//! a dump from inside a guest with one from its host shows which
+//! features the hypervisor passed through, which it hid and which
+//! it offers even though the host lacks them. Restrictions and state
+//! that firmware or the OS set up, such as x2APIC mode, are left out:
+//! they say nothing about the hypervisor.
+
+use std::fmt;
+
+use crate::cpu_information::CpuInformation;
+use crate::features::{Feature, FeatureStatus};
+use crate::report::Section;
+
+/// How a hypervisor treats a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Treatment {
+    /// The guest sees the feature like the host has it.
+    PassedThrough,
+
+    /// The host has the feature, but the guest doesn't see it.
+    Hidden,
+
+    /// The guest sees the feature, but the host doesn't have it. The
+    /// hypervisor emulates it.
+    Emulated,
+}
+
+impl fmt::Display for Treatment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Treatment::PassedThrough => "passed through",
+            Treatment::Hidden => "hidden",
+            Treatment::Emulated => "emulated",
+        })
+    }
+}
+
+/// Classify a feature by its status on the host and in the guest.
+///
+/// Returns `None` for features that neither has or that cannot be
+/// decided.
+pub fn treatment(host: &FeatureStatus, guest: &FeatureStatus) -> Option<Treatment> {
+    use FeatureStatus::*;
+
+    match (host, guest) {
+        (Present, Present) => Some(Treatment::PassedThrough),
+        (Present, Absent) => Some(Treatment::Hidden),
+        (Absent, Present) => Some(Treatment::Emulated),
+        _ => None,
+    }
+}
+
+/// Compare the catalog features between host and guest.
+pub fn section(
+    catalog: &[Feature],
+    host: &dyn CpuInformation,
+    guest: &dyn CpuInformation,
+) -> Section {
+    let mut section = Section::new("Guest compared to host");
+
+    for feature in catalog.iter().filter(|f| !f.restriction && !f.host_state) {
+        if let Some(treatment) = treatment(&feature.is_present(host), &feature.is_present(guest)) {
+            section.row(&feature.name, treatment);
+        }
+    }
+
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog;
+    use crate::cpu_information::{CpuidQuery, CpuidResult};
+    use crate::features::BoolExpression::MsrBitSet;
+    use crate::features::DEFAULT_CATEGORY;
+
+    /// An Intel CPU whose CPUID leaves up to 7 and MSRs all hold the
+    /// same value.
+    struct TestCpu(u64);
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let value = self.0 as u32;
+
+            match query.leaf {
+                // "GenuineIntel"
+                0 => Some(CpuidResult {
+                    eax: 7,
+                    ebx: 0x756e_6547,
+                    ecx: 0x6c65_746e,
+                    edx: 0x4965_6e69,
+                }),
+                1..=7 => Some(CpuidResult {
+                    eax: value,
+                    ebx: value,
+                    ecx: value,
+                    edx: value,
+                }),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn features_are_classified() {
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "A", MsrBitSet(1, 0)),
+            Feature::new(DEFAULT_CATEGORY, "B", MsrBitSet(1, 1)),
+            Feature::new(DEFAULT_CATEGORY, "C", MsrBitSet(1, 2)),
+            Feature::new(DEFAULT_CATEGORY, "D", MsrBitSet(1, 3)),
+        ];
+
+        let section = section(&features, &TestCpu(0b0011), &TestCpu(0b0101));
+
+        assert_eq!(section.get("A"), Some("passed through"));
+        assert_eq!(section.get("B"), Some("hidden"));
+        assert_eq!(section.get("C"), Some("emulated"));
+        assert_eq!(section.get("D"), None);
+    }
+
+    #[test]
+    fn restrictions_and_host_state_are_skipped() {
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "Locked", MsrBitSet(1, 0)).restriction(),
+            Feature::new(DEFAULT_CATEGORY, "Enabled", MsrBitSet(1, 1)).host_state(),
+        ];
+
+        let section = section(&features, &TestCpu(0b01), &TestCpu(0b10));
+
+        assert!(section.rows.is_empty());
+    }
+
+    #[test]
+    fn catalog_host_state_is_skipped() {
+        let section = section(&catalog::features(), &TestCpu(!0), &TestCpu(0));
+
+        for name in ["x2APIC enabled", "PKU enabled by OS", "VMX usable"] {
+            assert_eq!(section.get(name), None, "{}", name);
+        }
+        assert_eq!(section.get("IA32_FEATURE_CONTROL locked"), None);
+    }
+
+    #[test]
+    fn undecided_features_are_skipped() {
+        let unknown = FeatureStatus::Unknown { missing: vec![] };
+
+        assert_eq!(treatment(&FeatureStatus::Present, &unknown), None);
+        assert_eq!(
+            treatment(&FeatureStatus::NotApplicable, &FeatureStatus::Present),
+            None
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! # Check Dumps for Consistency
+//!
+//! If CPUID enumerates a feature, the dump should contain the data
+//! that belongs to it. A CPU with VMX, for example, has VMX
+//! capability MSRs. If they are missing, the dump is likely
+//! truncated or edited, and features that depend on them would only
+//! show up as unknown.
+//!
+//! Firmware can also hide features from CPUID via IA32_MISC_ENABLE.
+//! If it does, the report points this out, because the features look
+//! missing even though the CPU has them.
+
+use crate::cpu_information::CpuidRegister::*;
+use crate::cpu_information::{CpuInformation, DataRef};
+use crate::features::BoolExpression::{self, CpuidBitSet};
+use crate::features::{Bit, FeatureStatus};
+use crate::msr::{
+    IA32_MISC_ENABLE, MISC_ENABLE_LIMIT_CPUID, MISC_ENABLE_MONITOR, MISC_ENABLE_XD_DISABLE,
+};
+use crate::speculation::IA32_ARCH_CAPABILITIES;
+use crate::svm::{CPUID_SVM_FEATURES, VM_CR};
+use crate::vmx::{
+    procbased_control, IA32_VMX_BASIC, IA32_VMX_PINBASED_CTLS, IA32_VMX_PROCBASED_CTLS,
+    IA32_VMX_PROCBASED_CTLS2, IA32_VMX_PROCBASED_CTLS3,
+};
+
+/// Data that must be present (or absent) if CPUID enumerates
+/// something.
+struct Rule {
+    condition: BoolExpression,
+    condition_name: &'static str,
+    data: DataRef,
+    expect_present: bool,
+}
+
+fn rules() -> Vec<Rule> {
+    let vmx = || CpuidBitSet(1.into(), Ecx, 5);
+    let svm = || CpuidBitSet(0x8000_0001.into(), Ecx, 2);
+    let rule = |condition, condition_name, data| Rule {
+        condition,
+        condition_name,
+        data,
+        expect_present: true,
+    };
+
+    vec![
+        rule(vmx(), "VMX", DataRef::Msr(IA32_VMX_BASIC)),
+        rule(vmx(), "VMX", DataRef::Msr(IA32_VMX_PINBASED_CTLS)),
+        rule(vmx(), "VMX", DataRef::Msr(IA32_VMX_PROCBASED_CTLS)),
+        rule(
+            vmx() & procbased_control(31),
+            "secondary VM-execution controls",
+            DataRef::Msr(IA32_VMX_PROCBASED_CTLS2),
+        ),
+        rule(
+            vmx() & procbased_control(17),
+            "tertiary VM-execution controls",
+            DataRef::Msr(IA32_VMX_PROCBASED_CTLS3),
+        ),
+        rule(svm(), "SVM", DataRef::Msr(VM_CR)),
+        rule(svm(), "SVM", DataRef::Cpuid(CPUID_SVM_FEATURES.into())),
+        rule(
+            CpuidBitSet(7.into(), Edx, 29),
+            "IA32_ARCH_CAPABILITIES",
+            DataRef::Msr(IA32_ARCH_CAPABILITIES),
+        ),
+        Rule {
+            condition: !vmx(),
+            condition_name: "no VMX",
+            data: DataRef::Msr(IA32_VMX_BASIC),
+            expect_present: false,
+        },
+    ]
+}
+
+/// IA32_MISC_ENABLE settings that hide features from CPUID.
+const MISC_ENABLE_HIDES: &[(Bit, bool, &str)] = &[
+    (
+        MISC_ENABLE_LIMIT_CPUID,
+        true,
+        "limits CPUID to leaf 2, so features in higher leaves look missing",
+    ),
+    (MISC_ENABLE_XD_DISABLE, true, "hides NX from CPUID"),
+    (MISC_ENABLE_MONITOR, false, "hides MONITOR/MWAIT from CPUID"),
+];
+
+/// Whether the data is in the dump.
+fn is_present(cpu_info: &dyn CpuInformation, data: DataRef) -> bool {
+    match data {
+        DataRef::Cpuid(query) => cpu_info.cpuid(query).is_some(),
+        DataRef::Msr(index) => cpu_info.rdmsr(index).is_some(),
+    }
+}
+
+/// Contradictions between the CPUID and MSR data in a dump.
+pub fn warnings(cpu_info: &dyn CpuInformation) -> Vec<String> {
+    let misc_enable = cpu_info.rdmsr(IA32_MISC_ENABLE);
+    let hidden = MISC_ENABLE_HIDES
+        .iter()
+        .filter(move |(bit, set, _)| misc_enable.is_some_and(|v| ((v >> bit) & 1 == 1) == *set))
+        .map(|(_, _, consequence)| format!("IA32_MISC_ENABLE {}", consequence));
+
+    rules()
+        .into_iter()
+        .filter(|rule| rule.condition.status(cpu_info) == FeatureStatus::Present)
+        .filter(|rule| is_present(cpu_info, rule.data) != rule.expect_present)
+        .map(|rule| {
+            format!(
+                "CPUID enumerates {}, but the dump {} {}",
+                rule.condition_name,
+                if rule.expect_present {
+                    "has no"
+                } else {
+                    "contains"
+                },
+                rule.data
+            )
+        })
+        .chain(hidden)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::{CpuidQuery, CpuidResult};
+
+    struct TestCpu {
+        leaf1_ecx: u32,
+        msrs: Vec<u32>,
+        misc_enable: Option<u64>,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            match query.leaf {
+                0 | 1 | 0x8000_0000 | 0x8000_0001 => Some(CpuidResult {
+                    eax: if query.leaf == 0 { 1 } else { 0x8000_0001 },
+                    ebx: 0,
+                    ecx: if query.leaf == 1 { self.leaf1_ecx } else { 0 },
+                    edx: 0,
+                }),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            match index {
+                IA32_MISC_ENABLE => self.misc_enable,
+                _ => self.msrs.contains(&index).then_some(0),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_vmx_msrs_are_reported() {
+        let cpu = TestCpu {
+            leaf1_ecx: 1 << 5,
+            msrs: vec![IA32_VMX_BASIC, IA32_VMX_PINBASED_CTLS],
+            misc_enable: None,
+        };
+
+        assert_eq!(
+            warnings(&cpu),
+            vec!["CPUID enumerates VMX, but the dump has no MSR 0x482"]
+        );
+    }
+
+    #[test]
+    fn vmx_msrs_without_vmx_are_reported() {
+        let cpu = TestCpu {
+            leaf1_ecx: 0,
+            msrs: vec![IA32_VMX_BASIC],
+            misc_enable: None,
+        };
+
+        assert_eq!(
+            warnings(&cpu),
+            vec!["CPUID enumerates no VMX, but the dump contains MSR 0x480"]
+        );
+    }
+
+    #[test]
+    fn consistent_dumps_have_no_warnings() {
+        let cpu = TestCpu {
+            leaf1_ecx: 0,
+            msrs: vec![],
+            misc_enable: Some(1 << MISC_ENABLE_MONITOR),
+        };
+
+        assert!(warnings(&cpu).is_empty());
+    }
+
+    #[test]
+    fn features_hidden_by_misc_enable_are_reported() {
+        let cpu = TestCpu {
+            leaf1_ecx: 0,
+            msrs: vec![],
+            misc_enable: Some((1 << MISC_ENABLE_LIMIT_CPUID) | (1 << MISC_ENABLE_XD_DISABLE)),
+        };
+
+        assert_eq!(
+            warnings(&cpu),
+            vec![
+                "IA32_MISC_ENABLE limits CPUID to leaf 2, so features in higher leaves look missing",
+                "IA32_MISC_ENABLE hides NX from CPUID",
+                "IA32_MISC_ENABLE hides MONITOR/MWAIT from CPUID",
+            ]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use serde::Deserialize;
+
 /// The input to a `cpuid` invocation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CpuidQuery {
@@ -30,6 +32,27 @@ pub enum CpuidRegister {
     Edx,
 }
 
+impl CpuidRegister {
+    /// All registers in the order of [CpuidResult].
+    pub const ALL: [CpuidRegister; 4] = [
+        CpuidRegister::Eax,
+        CpuidRegister::Ebx,
+        CpuidRegister::Ecx,
+        CpuidRegister::Edx,
+    ];
+}
+
+impl std::fmt::Display for CpuidRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            CpuidRegister::Eax => "EAX",
+            CpuidRegister::Ebx => "EBX",
+            CpuidRegister::Ecx => "ECX",
+            CpuidRegister::Edx => "EDX",
+        })
+    }
+}
+
 impl CpuidResult {
     /// Retrieve a register value from a CPUID result.
     pub fn get(&self, reg: CpuidRegister) -> u32 {
@@ -42,6 +65,53 @@ impl CpuidResult {
     }
 }
 
+/// A reference to a piece of data in a [CpuInformation].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DataRef {
+    Cpuid(CpuidQuery),
+    Msr(u32),
+}
+
+impl std::fmt::Display for DataRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DataRef::Cpuid(query) => write!(f, "CPUID {:#x}.{:#x}", query.leaf, query.subleaf),
+            DataRef::Msr(index) => write!(f, "MSR {:#x}", index),
+        }
+    }
+}
+
+/// Describe the data that a decision lacks, e.g. "missing MSR 0x123".
+pub fn missing_data(refs: &[DataRef]) -> String {
+    format!(
+        "missing {}",
+        refs.iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// A CPU vendor as identified by the CPUID vendor string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Vendor {
+    /// `GenuineIntel`
+    Intel,
+
+    /// `AuthenticAMD`
+    Amd,
+}
+
+impl std::fmt::Display for Vendor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Vendor::Intel => "Intel",
+            Vendor::Amd => "AMD",
+        })
+    }
+}
+
 /// Converts a slice of 32-bit little-endian integers into a
 /// `Vec<u8>`. This also trims zero bytes at the end.
 fn dwords_to_bytes(dwords: &[u32]) -> Vec<u8> {
@@ -64,6 +134,28 @@ pub trait CpuInformation {
     /// Returns `None` if the result is unknown.
     fn rdmsr(&self, index: u32) -> Option<u64>;
 
+    /// The CPUID information of each logical CPU, ordered by CPU
+    /// number.
+    ///
+    /// Returns an empty list if only the boot CPU is known.
+    fn logical_cpus(&self) -> Vec<&dyn CpuInformation> {
+        vec![]
+    }
+
+    /// The CPUID queries with a known result, in ascending order.
+    ///
+    /// Returns an empty list if they cannot be enumerated.
+    fn cpuid_queries(&self) -> Vec<CpuidQuery> {
+        vec![]
+    }
+
+    /// The MSRs with a known value, in ascending order.
+    ///
+    /// Returns an empty list if they cannot be enumerated.
+    fn msr_indices(&self) -> Vec<u32> {
+        vec![]
+    }
+
     /// The maximum supported standard (`0x0000_xxxx`) CPUID leaf.
     fn max_standard_leaf(&self) -> u32 {
         self.cpuid(0.into()).map(|r| r.eax).unwrap_or(0)
@@ -76,6 +168,17 @@ pub trait CpuInformation {
             .unwrap_or(0x8000_0000)
     }
 
+    /// The physical address width in bits from CPUID leaf 0x80000008.
+    ///
+    /// Returns `None` if the leaf is missing or the width is outside
+    /// the 36 to 52 bits that x86-64 CPUs implement.
+    fn physical_address_bits(&self) -> Option<u32> {
+        self.cpuid(0x8000_0008.into())
+            .filter(|_| self.is_cpuid_query_valid(0x8000_0008.into()))
+            .map(|r| r.eax & 0xff)
+            .filter(|bits| (36..=52).contains(bits))
+    }
+
     /// Checks whether the CPU indicates that this query would return
     /// something valid.
     fn is_cpuid_query_valid(&self, query: CpuidQuery) -> bool {
@@ -93,6 +196,29 @@ pub trait CpuInformation {
             .map(|r| -> Vec<u8> { dwords_to_bytes(&[r.ebx, r.edx, r.ecx]) })
     }
 
+    /// Returns the vendor, if it is one we know about.
+    fn vendor(&self) -> Option<Vendor> {
+        match self.vendor_bytes()?.as_slice() {
+            b"GenuineIntel" => Some(Vendor::Intel),
+            b"AuthenticAMD" => Some(Vendor::Amd),
+            _ => None,
+        }
+    }
+
+    /// Returns the microcode revision from IA32_BIOS_SIGN_ID (MSR
+    /// 0x8B).
+    ///
+    /// Intel CPUs report the revision in the upper half of the MSR,
+    /// AMD CPUs (as PATCH_LEVEL) in the lower half.
+    fn microcode_revision(&self) -> Option<u32> {
+        let value = self.rdmsr(0x8b)?;
+
+        match self.vendor()? {
+            Vendor::Intel => Some((value >> 32) as u32),
+            Vendor::Amd => Some(value as u32),
+        }
+    }
+
     /// Returns the vendor name as string.
     ///
     /// This uses lossy conversion to UTF-8 in case the string is not
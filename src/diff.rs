@@ -0,0 +1,337 @@
+//! # Compare Two Dumps
+//!
+//! Two dumps of the same CPU model can still differ: in microcode,
+//! firmware settings, or in what a hypervisor shows its guests. This
+//! lists the catalog features that only one of the dumps has and every
+//! CPUID register and MSR whose value differs. Features that either
+//! dump cannot decide are left out, because whether they differ is
+//! unknown.
+//!
+//! The result serializes to JSON for scripts that compare many dumps.
+
+use serde::Serialize;
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidRegister};
+use crate::features::{Feature, FeatureStatus};
+use crate::msr;
+use crate::report::Section;
+
+/// Which of the two dumps something is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    A,
+    B,
+}
+
+/// A feature that only one dump has.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeatureDifference {
+    pub category: String,
+    pub name: String,
+    pub only_in: Side,
+}
+
+/// A CPUID register that differs. A value is `None` if the dump
+/// doesn't have the leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CpuidDifference {
+    pub leaf: u32,
+    pub subleaf: u32,
+    pub register: String,
+    pub a: Option<u32>,
+    pub b: Option<u32>,
+}
+
+/// An MSR that differs. A value is `None` if the dump doesn't have
+/// the MSR.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MsrDifference {
+    pub index: u32,
+    pub a: Option<u64>,
+    pub b: Option<u64>,
+}
+
+/// The differences between dumps A and B.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct Diff {
+    pub features: Vec<FeatureDifference>,
+    pub cpuid: Vec<CpuidDifference>,
+    pub msrs: Vec<MsrDifference>,
+}
+
+/// The sorted union of two sorted lists.
+fn union<T: Ord + Copy>(a: &[T], b: &[T]) -> Vec<T> {
+    let mut all: Vec<T> = a.iter().chain(b).copied().collect();
+
+    all.sort_unstable();
+    all.dedup();
+    all
+}
+
+/// Format an optional value, which is missing if it's `None`.
+fn value<T: std::fmt::LowerHex>(value: Option<T>) -> String {
+    value
+        .map(|v| format!("{:#x}", v))
+        .unwrap_or_else(|| "missing".to_owned())
+}
+
+impl Diff {
+    /// Compare the catalog features and the raw values in two dumps.
+    pub fn new(catalog: &[Feature], a: &dyn CpuInformation, b: &dyn CpuInformation) -> Diff {
+        let features = catalog
+            .iter()
+            .filter_map(|feature| {
+                let only_in = match (feature.is_present(a), feature.is_present(b)) {
+                    (FeatureStatus::Present, FeatureStatus::Absent) => Side::A,
+                    (FeatureStatus::Absent, FeatureStatus::Present) => Side::B,
+                    _ => return None,
+                };
+
+                Some(FeatureDifference {
+                    category: feature.category.clone(),
+                    name: feature.name.clone(),
+                    only_in,
+                })
+            })
+            .collect();
+
+        let cpuid = union(&a.cpuid_queries(), &b.cpuid_queries())
+            .into_iter()
+            .flat_map(|query: CpuidQuery| {
+                let (result_a, result_b) = (a.cpuid(query), b.cpuid(query));
+
+                CpuidRegister::ALL.iter().filter_map(move |&register| {
+                    let a = result_a.map(|r| r.get(register));
+                    let b = result_b.map(|r| r.get(register));
+
+                    (a != b).then(|| CpuidDifference {
+                        leaf: query.leaf,
+                        subleaf: query.subleaf,
+                        register: register.to_string(),
+                        a,
+                        b,
+                    })
+                })
+            })
+            .collect();
+
+        let msrs = union(&a.msr_indices(), &b.msr_indices())
+            .into_iter()
+            .filter_map(|index| {
+                let (a, b) = (a.rdmsr(index), b.rdmsr(index));
+
+                (a != b).then_some(MsrDifference { index, a, b })
+            })
+            .collect();
+
+        Diff {
+            features,
+            cpuid,
+            msrs,
+        }
+    }
+
+    /// Whether the dumps are the same.
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty() && self.cpuid.is_empty() && self.msrs.is_empty()
+    }
+
+    /// Show the differences with the dumps labelled `a` and `b`, e.g.
+    /// with their file names.
+    pub fn sections(&self, a: &str, b: &str) -> Vec<Section> {
+        let mut features = Section::new("Features in only one dump");
+        for difference in &self.features {
+            features.row(
+                &format!("{}: {}", difference.category, difference.name),
+                format!(
+                    "only in {}",
+                    match difference.only_in {
+                        Side::A => a,
+                        Side::B => b,
+                    }
+                ),
+            );
+        }
+
+        let mut cpuid = Section::new(&format!("CPUID differences ({} -> {})", a, b));
+        for difference in &self.cpuid {
+            cpuid.row(
+                &format!(
+                    "{:#x}.{:#x} {}",
+                    difference.leaf, difference.subleaf, difference.register
+                ),
+                format!("{} -> {}", value(difference.a), value(difference.b)),
+            );
+        }
+
+        let mut msrs = Section::new(&format!("MSR differences ({} -> {})", a, b));
+        for difference in &self.msrs {
+            msrs.row(
+                &match msr::find(difference.index) {
+                    Some(m) => format!("{:#x} ({})", difference.index, m.title),
+                    None => format!("{:#x}", difference.index),
+                },
+                format!("{} -> {}", value(difference.a), value(difference.b)),
+            );
+        }
+
+        vec![features, cpuid, msrs]
+            .into_iter()
+            .filter(|section| !section.rows.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidResult;
+    use crate::features::BoolExpression::{CpuidBitSet, MsrBitSet};
+    use crate::features::DEFAULT_CATEGORY;
+    use std::collections::BTreeMap;
+
+    struct TestCpu {
+        cpuid: BTreeMap<CpuidQuery, CpuidResult>,
+        msrs: BTreeMap<u32, u64>,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            self.cpuid.get(&query).copied()
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            self.msrs.get(&index).copied()
+        }
+
+        fn cpuid_queries(&self) -> Vec<CpuidQuery> {
+            self.cpuid.keys().copied().collect()
+        }
+
+        fn msr_indices(&self) -> Vec<u32> {
+            self.msrs.keys().copied().collect()
+        }
+    }
+
+    fn cpu(ecx: u32, msrs: &[(u32, u64)]) -> TestCpu {
+        let result = CpuidResult {
+            eax: 0x906ea,
+            ebx: 0,
+            ecx,
+            edx: 0,
+        };
+
+        TestCpu {
+            cpuid: vec![
+                (
+                    0.into(),
+                    CpuidResult {
+                        eax: 1,
+                        ecx: 0,
+                        ..result
+                    },
+                ),
+                (1.into(), result),
+            ]
+            .into_iter()
+            .collect(),
+            msrs: msrs.iter().copied().collect(),
+        }
+    }
+
+    fn catalog() -> Vec<Feature> {
+        vec![
+            Feature::new(
+                DEFAULT_CATEGORY,
+                "VMX",
+                CpuidBitSet(1.into(), CpuidRegister::Ecx, 5),
+            ),
+            Feature::new(
+                DEFAULT_CATEGORY,
+                "x2APIC",
+                CpuidBitSet(1.into(), CpuidRegister::Ecx, 21),
+            ),
+            Feature::new(DEFAULT_CATEGORY, "Locked", MsrBitSet(0x3a, 0)),
+        ]
+    }
+
+    #[test]
+    fn identical_dumps_have_no_differences() {
+        let a = cpu(0x20, &[(0x3a, 5)]);
+
+        assert!(Diff::new(&catalog(), &a, &a).is_empty());
+    }
+
+    #[test]
+    fn differences_are_found() {
+        let a = cpu(0x20, &[(0x3a, 5), (0x1b, 0xfee0_0900)]);
+        let b = cpu(0x20_0000, &[(0x1b, 0xfee0_0d00)]);
+        let diff = Diff::new(&catalog(), &a, &b);
+
+        assert_eq!(
+            diff.features,
+            vec![
+                FeatureDifference {
+                    category: DEFAULT_CATEGORY.to_owned(),
+                    name: "VMX".to_owned(),
+                    only_in: Side::A,
+                },
+                FeatureDifference {
+                    category: DEFAULT_CATEGORY.to_owned(),
+                    name: "x2APIC".to_owned(),
+                    only_in: Side::B,
+                },
+            ]
+        );
+        assert_eq!(
+            diff.cpuid,
+            vec![CpuidDifference {
+                leaf: 1,
+                subleaf: 0,
+                register: "ECX".to_owned(),
+                a: Some(0x20),
+                b: Some(0x20_0000),
+            }]
+        );
+        assert_eq!(
+            diff.msrs,
+            vec![
+                MsrDifference {
+                    index: 0x1b,
+                    a: Some(0xfee0_0900),
+                    b: Some(0xfee0_0d00),
+                },
+                MsrDifference {
+                    index: 0x3a,
+                    a: Some(5),
+                    b: None,
+                },
+            ]
+        );
+
+        let sections = diff.sections("a.txt", "b.txt");
+        assert_eq!(sections.len(), 3);
+        assert_eq!(
+            sections[0].get(&format!("{}: VMX", DEFAULT_CATEGORY)),
+            Some("only in a.txt")
+        );
+        assert_eq!(sections[1].get("0x1.0x0 ECX"), Some("0x20 -> 0x200000"));
+        assert_eq!(
+            sections[2].get("0x3a (Feature control)"),
+            Some("0x5 -> missing")
+        );
+    }
+
+    #[test]
+    fn diff_serializes_to_json() {
+        let a = cpu(0x20, &[]);
+        let b = cpu(0, &[]);
+        let json = serde_json::to_value(Diff::new(&catalog(), &a, &b)).expect("serializable");
+
+        assert_eq!(json["features"][0]["only_in"], "a");
+        assert_eq!(json["cpuid"][0]["register"], "ECX");
+        assert_eq!(json["cpuid"][0]["a"], 0x20);
+        assert_eq!(json["msrs"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,434 @@
+//! # Parse Feature Expressions
+//!
+//! Feature definitions that are loaded from files describe their
+//! condition as a small expression language. The syntax looks like
+//! this:
+//!
+//! ```text
+//! MSR 0x48B bit 46 AND NOT CPUID(7,0).EBX[2]
+//! ```
+//!
+//! The building blocks are:
+//!
+//! - `CPUID(leaf).REG[bit]` or `CPUID(leaf,subleaf).REG[bit]` checks
+//!   a bit in a CPUID result. `REG` is one of `EAX`, `EBX`, `ECX`
+//!   and `EDX`.
+//! - `MSR index bit n` checks a bit in an MSR.
+//! - `TRUE` and `FALSE` are constants.
+//! - `AND`, `OR` and `NOT` combine expressions. `&`, `|` and `!`
+//!   can be used instead. `NOT` binds strongest, `OR` weakest.
+//! - Parentheses group sub-expressions.
+//!
+//! Keywords are case-insensitive. Numbers are decimal or hexadecimal
+//! with a `0x` prefix.
+//!
+//! See [BoolExpression].
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::cpu_information::{CpuidQuery, CpuidRegister};
+use crate::features::{Bit, BoolExpression};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseExpressionError {
+    reason: String,
+}
+
+impl ParseExpressionError {
+    fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Failed to parse feature expression: {}", self.reason)
+    }
+}
+
+impl std::error::Error for ParseExpressionError {}
+
+/// A single token of the expression language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A keyword or register name. Always lowercase.
+    Word(String),
+    Number(u64),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "'{}'", w),
+            Token::Number(n) => write!(f, "'{:#x}'", n),
+            Token::Symbol(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(input: &str) -> Result<u64, ParseExpressionError> {
+    let lower = input.to_ascii_lowercase();
+    let result = match lower.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => lower.parse::<u64>(),
+    };
+
+    result.map_err(|_| ParseExpressionError::new(format!("invalid number '{}'", input)))
+}
+
+/// Split the input into tokens.
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseExpressionError> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !c.is_ascii_alphanumeric() && c != '_' {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            let word = &input[start..end];
+            tokens.push(if c.is_ascii_digit() {
+                Token::Number(parse_number(word)?)
+            } else {
+                Token::Word(word.to_ascii_lowercase())
+            });
+        } else if "()[],.&|!".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(ParseExpressionError::new(format!(
+                "unexpected character '{}'",
+                c
+            )));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive-descent parser over a token list.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseExpressionError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseExpressionError::new("unexpected end of expression"))?;
+
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// Consume the next token if it is the given keyword or symbol.
+    fn accept(&mut self, keyword: Option<&str>, symbol: char) -> bool {
+        let found = match self.peek() {
+            Some(Token::Word(w)) => Some(w.as_str()) == keyword,
+            Some(Token::Symbol(c)) => *c == symbol,
+            _ => false,
+        };
+
+        if found {
+            self.pos += 1;
+        }
+
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), ParseExpressionError> {
+        match self.next()? {
+            Token::Symbol(c) if c == symbol => Ok(()),
+            t => Err(ParseExpressionError::new(format!(
+                "expected '{}', found {}",
+                symbol, t
+            ))),
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseExpressionError> {
+        match self.next()? {
+            Token::Word(w) if w == word => Ok(()),
+            t => Err(ParseExpressionError::new(format!(
+                "expected '{}', found {}",
+                word, t
+            ))),
+        }
+    }
+
+    fn expect_number(&mut self) -> Result<u64, ParseExpressionError> {
+        match self.next()? {
+            Token::Number(n) => Ok(n),
+            t => Err(ParseExpressionError::new(format!(
+                "expected a number, found {}",
+                t
+            ))),
+        }
+    }
+
+    fn expect_u32(&mut self) -> Result<u32, ParseExpressionError> {
+        let n = self.expect_number()?;
+        u32::try_from(n)
+            .map_err(|_| ParseExpressionError::new(format!("{:#x} does not fit in 32 bits", n)))
+    }
+
+    /// Parse a bit position that must be smaller than `limit`.
+    fn expect_bit(&mut self, limit: u32) -> Result<Bit, ParseExpressionError> {
+        let n = self.expect_number()?;
+        if n >= u64::from(limit) {
+            return Err(ParseExpressionError::new(format!(
+                "bit {} is out of range for a {}-bit value",
+                n, limit
+            )));
+        }
+
+        Ok(n as Bit)
+    }
+
+    fn parse_or(&mut self) -> Result<BoolExpression, ParseExpressionError> {
+        let mut expr = self.parse_and()?;
+
+        while self.accept(Some("or"), '|') {
+            expr = expr | self.parse_and()?;
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<BoolExpression, ParseExpressionError> {
+        let mut expr = self.parse_unary()?;
+
+        while self.accept(Some("and"), '&') {
+            expr = expr & self.parse_unary()?;
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<BoolExpression, ParseExpressionError> {
+        if self.accept(Some("not"), '!') {
+            Ok(!self.parse_unary()?)
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<BoolExpression, ParseExpressionError> {
+        match self.next()? {
+            Token::Symbol('(') => {
+                let expr = self.parse_or()?;
+                self.expect_symbol(')')?;
+                Ok(expr)
+            }
+            Token::Word(w) if w == "true" => Ok(BoolExpression::Constant(true)),
+            Token::Word(w) if w == "false" => Ok(BoolExpression::Constant(false)),
+            Token::Word(w) if w == "cpuid" => self.parse_cpuid(),
+            Token::Word(w) if w == "msr" => self.parse_msr(),
+            t => Err(ParseExpressionError::new(format!(
+                "expected an expression, found {}",
+                t
+            ))),
+        }
+    }
+
+    /// Parse the remainder of `CPUID(leaf[,subleaf]).REG[bit]`.
+    fn parse_cpuid(&mut self) -> Result<BoolExpression, ParseExpressionError> {
+        self.expect_symbol('(')?;
+        let leaf = self.expect_u32()?;
+        let subleaf = if self.accept(None, ',') {
+            self.expect_u32()?
+        } else {
+            0
+        };
+        self.expect_symbol(')')?;
+        self.expect_symbol('.')?;
+
+        let reg = match self.next()? {
+            Token::Word(w) if w == "eax" => CpuidRegister::Eax,
+            Token::Word(w) if w == "ebx" => CpuidRegister::Ebx,
+            Token::Word(w) if w == "ecx" => CpuidRegister::Ecx,
+            Token::Word(w) if w == "edx" => CpuidRegister::Edx,
+            t => {
+                return Err(ParseExpressionError::new(format!(
+                    "expected a CPUID register, found {}",
+                    t
+                )))
+            }
+        };
+
+        self.expect_symbol('[')?;
+        let bit = self.expect_bit(u32::BITS)?;
+        self.expect_symbol(']')?;
+
+        Ok(BoolExpression::CpuidBitSet(
+            CpuidQuery { leaf, subleaf },
+            reg,
+            bit,
+        ))
+    }
+
+    /// Parse the remainder of `MSR index bit n`.
+    fn parse_msr(&mut self) -> Result<BoolExpression, ParseExpressionError> {
+        let index = self.expect_u32()?;
+        self.expect_word("bit")?;
+        let bit = self.expect_bit(u64::BITS)?;
+
+        Ok(BoolExpression::MsrBitSet(index, bit))
+    }
+}
+
+impl FromStr for BoolExpression {
+    type Err = ParseExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+
+        let expr = parser.parse_or()?;
+
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(ParseExpressionError::new(format!(
+                "unexpected trailing input {}",
+                t
+            ))),
+        }
+    }
+}
+
+/// Expressions are stored as strings in the expression language in
+/// feature definition files.
+impl<'de> Deserialize<'de> for BoolExpression {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        BoolExpression::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidRegister::*;
+    use crate::features::BoolExpression::*;
+
+    #[test]
+    fn numbers_are_parsed() {
+        assert_eq!(parse_number("46"), Ok(46));
+        assert_eq!(parse_number("0x48B"), Ok(0x48b));
+        assert_eq!(parse_number("0X48b"), Ok(0x48b));
+        assert!(parse_number("0xZZ").is_err());
+        assert!(parse_number("12a").is_err());
+    }
+
+    #[test]
+    fn leaf_expressions_are_parsed() {
+        assert_eq!(
+            BoolExpression::from_str("CPUID(7,0).EBX[29]"),
+            Ok(CpuidBitSet(7.into(), Ebx, 29))
+        );
+        assert_eq!(
+            BoolExpression::from_str("cpuid(0x12).eax[5]"),
+            Ok(CpuidBitSet(0x12.into(), Eax, 5))
+        );
+        assert_eq!(
+            BoolExpression::from_str("CPUID(4, 3).EDX[1]"),
+            Ok(CpuidBitSet(
+                CpuidQuery {
+                    leaf: 4,
+                    subleaf: 3
+                },
+                Edx,
+                1
+            ))
+        );
+        assert_eq!(
+            BoolExpression::from_str("MSR 0x48B bit 46"),
+            Ok(MsrBitSet(0x48b, 46))
+        );
+    }
+
+    #[test]
+    fn operators_have_precedence() {
+        let a = || MsrBitSet(0x48b, 33);
+        let b = || MsrBitSet(0x48b, 39);
+        let c = || CpuidBitSet(1.into(), Ecx, 5);
+
+        assert_eq!(
+            BoolExpression::from_str(
+                "MSR 0x48B bit 33 OR MSR 0x48B bit 39 AND NOT CPUID(1).ECX[5]"
+            ),
+            Ok(a() | (b() & !c()))
+        );
+        assert_eq!(
+            BoolExpression::from_str("(msr 0x48b bit 33 | msr 0x48b bit 39) & !cpuid(1).ecx[5]"),
+            Ok((a() | b()) & !c())
+        );
+        assert_eq!(
+            BoolExpression::from_str("NOT NOT MSR 0x48B bit 33"),
+            Ok(!!a())
+        );
+    }
+
+    #[test]
+    fn displayed_expressions_parse_back() {
+        let exprs = vec![
+            MsrBitSet(0x48b, 46) & !CpuidBitSet(7.into(), Ebx, 2),
+            (MsrBitSet(1, 0) | MsrBitSet(1, 1)) & !(MsrBitSet(1, 2) & MsrBitSet(1, 3)),
+            MsrBitSet(1, 0) | (MsrBitSet(1, 1) | MsrBitSet(1, 2)),
+            Constant(true) & !Constant(false),
+            CpuidBitSet(
+                CpuidQuery {
+                    leaf: 0x8000_001f,
+                    subleaf: 0x11,
+                },
+                Eax,
+                31,
+            ),
+        ];
+
+        for expr in exprs {
+            assert_eq!(BoolExpression::from_str(&expr.to_string()), Ok(expr));
+        }
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        assert!(BoolExpression::from_str("").is_err());
+        assert!(BoolExpression::from_str("MSR 0x48B").is_err());
+        assert!(BoolExpression::from_str("MSR 0x48B bit 64").is_err());
+        assert!(BoolExpression::from_str("CPUID(1).ECX[32]").is_err());
+        assert!(BoolExpression::from_str("CPUID(1).ESI[3]").is_err());
+        assert!(BoolExpression::from_str("CPUID(0x100000000).EAX[0]").is_err());
+        assert!(BoolExpression::from_str("MSR 0x48B bit 33 AND").is_err());
+        assert!(BoolExpression::from_str("(MSR 0x48B bit 33").is_err());
+        assert!(BoolExpression::from_str("MSR 0x48B bit 33 )").is_err());
+        assert!(BoolExpression::from_str("MSR 0x48B bit 33 $").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! # Load Feature Definitions
+//!
+//! Users can extend the built-in feature catalog with their own
+//! definitions. These live in TOML or JSON files that contain a list
+//! of features. In TOML, this looks like:
+//!
+//! ```toml
+//! [[feature]]
+//! name = "Mode-based execute control"
+//! category = "EPT"
+//! expression = "MSR 0x48B bit 54"
+//! ```
+//!
+//! The JSON equivalent is an object with a `feature` array. Files
+//! ending in `.json` are parsed as JSON, everything else as TOML. See
+//! [crate::expression_parse] for the expression syntax.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::features::Feature;
+
+#[derive(Debug)]
+pub enum LoadFeaturesErrorKind {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+}
+
+#[derive(Debug)]
+pub struct LoadFeaturesError {
+    path: PathBuf,
+    kind: LoadFeaturesErrorKind,
+}
+
+impl std::fmt::Display for LoadFeaturesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to load feature definitions from {}: ",
+            self.path.display()
+        )?;
+
+        match &self.kind {
+            LoadFeaturesErrorKind::Io(e) => write!(f, "{}", e),
+            LoadFeaturesErrorKind::Toml(e) => write!(f, "{}", e),
+            LoadFeaturesErrorKind::Json(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadFeaturesError {}
+
+/// The top-level structure of a feature definition file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeatureFile {
+    #[serde(default)]
+    feature: Vec<Feature>,
+}
+
+/// Parse feature definitions from a string.
+///
+/// `json` selects between JSON and TOML syntax.
+fn parse_features(input: &str, json: bool) -> Result<Vec<Feature>, LoadFeaturesErrorKind> {
+    let file: FeatureFile = if json {
+        serde_json::from_str(input).map_err(LoadFeaturesErrorKind::Json)?
+    } else {
+        toml::from_str(input).map_err(LoadFeaturesErrorKind::Toml)?
+    };
+
+    Ok(file.feature)
+}
+
+/// Load feature definitions from a TOML or JSON file.
+pub fn load_features(path: &Path) -> Result<Vec<Feature>, LoadFeaturesError> {
+    let json = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+
+    std::fs::read_to_string(path)
+        .map_err(LoadFeaturesErrorKind::Io)
+        .and_then(|input| parse_features(&input, json))
+        .map_err(|kind| LoadFeaturesError {
+            path: path.to_owned(),
+            kind,
+        })
+}
+
+/// Merge user-provided features into a catalog.
+///
+/// A feature with the same name as an existing one replaces it in
+/// place. All other features are appended in order.
+pub fn merge_features(catalog: &mut Vec<Feature>, extra: Vec<Feature>) {
+    for feature in extra {
+        match catalog.iter_mut().find(|f| f.name == feature.name) {
+            Some(existing) => *existing = feature,
+            None => catalog.push(feature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidRegister::*;
+    use crate::features::BoolExpression::*;
+    use crate::features::DEFAULT_CATEGORY;
+
+    #[test]
+    fn toml_features_are_parsed() {
+        let input = r#"
+[[feature]]
+name = "MBEC"
+category = "EPT"
+expression = "MSR 0x48B bit 54"
+
+[[feature]]
+name = "SMEP"
+expression = "CPUID(7).EBX[7]"
+"#;
+
+        let features = parse_features(input, false).expect("valid TOML input");
+
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0].name, "MBEC");
+        assert_eq!(features[0].category, "EPT");
+        assert_eq!(
+            features[1],
+            Feature::new(DEFAULT_CATEGORY, "SMEP", CpuidBitSet(7.into(), Ebx, 7))
+        );
+    }
+
+    #[test]
+    fn json_features_are_parsed() {
+        let input = r#"{ "feature": [ { "name": "MBEC", "expression": "MSR 0x48B bit 54" } ] }"#;
+
+        assert_eq!(
+            parse_features(input, true).expect("valid JSON input"),
+            vec![Feature::new(DEFAULT_CATEGORY, "MBEC", MsrBitSet(0x48b, 54))]
+        );
+    }
+
+    #[test]
+    fn invalid_features_are_rejected() {
+        assert!(parse_features("[[feature]]\nname = \"A\"\n", false).is_err());
+        assert!(parse_features(
+            "[[feature]]\nname = \"A\"\nexpression = \"MSR 1 bit 99\"\n",
+            false
+        )
+        .is_err());
+        assert!(parse_features(
+            "[[feature]]\nname = \"A\"\nexpression = \"MSR 1 bit 9\"\ncolor = \"red\"\n",
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn features_are_merged_by_name() {
+        let mut catalog = vec![
+            Feature::new(DEFAULT_CATEGORY, "A", MsrBitSet(1, 0)),
+            Feature::new(DEFAULT_CATEGORY, "B", MsrBitSet(1, 1)),
+        ];
+
+        merge_features(
+            &mut catalog,
+            vec![
+                Feature::new(DEFAULT_CATEGORY, "C", MsrBitSet(1, 2)),
+                Feature::new(DEFAULT_CATEGORY, "A", MsrBitSet(1, 3)),
+            ],
+        );
+
+        assert_eq!(
+            catalog,
+            vec![
+                Feature::new(DEFAULT_CATEGORY, "A", MsrBitSet(1, 3)),
+                Feature::new(DEFAULT_CATEGORY, "B", MsrBitSet(1, 1)),
+                Feature::new(DEFAULT_CATEGORY, "C", MsrBitSet(1, 2)),
+            ]
+        );
+    }
+}
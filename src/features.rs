@@ -1,11 +1,14 @@
 use std::ops::{BitAnd, BitOr, Not};
 
-use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidRegister};
+use serde::Deserialize;
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidRegister, DataRef, Vendor};
 
 pub type Bit = u8;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BoolExpression {
+    Constant(bool),
     CpuidBitSet(CpuidQuery, CpuidRegister, Bit),
     MsrBitSet(u32, Bit),
 
@@ -15,29 +18,161 @@ pub enum BoolExpression {
 }
 
 impl BoolExpression {
-    pub fn evaluate(&self, cpu_info: &dyn CpuInformation) -> Option<bool> {
+    /// Evaluate the expression as far as the available data allows.
+    ///
+    /// Every sub-expression that can be decided from `cpu_info` is
+    /// folded into a constant. The result is either a
+    /// [BoolExpression::Constant] or the residual expression that
+    /// only contains the checks that block a definitive answer,
+    /// because their CPUID leaves or MSRs are missing.
+    pub fn simplify(&self, cpu_info: &dyn CpuInformation) -> BoolExpression {
+        use BoolExpression::*;
+
         match self {
-            BoolExpression::CpuidBitSet(query, reg, bit) => {
+            Constant(_) => self.clone(),
+            CpuidBitSet(query, reg, bit) => {
                 assert!(u32::from(*bit) < u32::BITS);
 
                 if cpu_info.is_cpuid_query_valid(*query) {
-                    Some(cpu_info.cpuid(*query)?.get(*reg) & (1 << bit) != 0)
+                    cpu_info
+                        .cpuid(*query)
+                        .map(|r| Constant(r.get(*reg) & (1 << bit) != 0))
+                        .unwrap_or_else(|| self.clone())
                 } else {
                     // When the CPU says the leaf is not supported, the bit is considered unset.
-                    Some(false)
+                    Constant(false)
                 }
             }
-            BoolExpression::MsrBitSet(index, bit) => {
+            MsrBitSet(index, bit) => {
                 assert!(u32::from(*bit) < u64::BITS);
-                Some((cpu_info.rdmsr(*index)? & (1 << bit)) != 0)
+
+                cpu_info
+                    .rdmsr(*index)
+                    .map(|v| Constant(v & (1 << bit) != 0))
+                    .unwrap_or_else(|| self.clone())
+            }
+            And(expr1, expr2) => match (expr1.simplify(cpu_info), expr2.simplify(cpu_info)) {
+                (Constant(false), _) | (_, Constant(false)) => Constant(false),
+                (Constant(true), expr) | (expr, Constant(true)) => expr,
+                (expr1, expr2) => expr1 & expr2,
+            },
+            Or(expr1, expr2) => match (expr1.simplify(cpu_info), expr2.simplify(cpu_info)) {
+                (Constant(true), _) | (_, Constant(true)) => Constant(true),
+                (Constant(false), expr) | (expr, Constant(false)) => expr,
+                (expr1, expr2) => expr1 | expr2,
+            },
+            Not(expr) => match expr.simplify(cpu_info) {
+                Constant(b) => Constant(!b),
+                expr => !expr,
+            },
+        }
+    }
+
+    /// Evaluate the expression into a [FeatureStatus].
+    ///
+    /// If the expression cannot be decided, the status lists the
+    /// missing CPUID leaves and MSRs.
+    pub fn status(&self, cpu_info: &dyn CpuInformation) -> FeatureStatus {
+        match self.simplify(cpu_info) {
+            BoolExpression::Constant(true) => FeatureStatus::Present,
+            BoolExpression::Constant(false) => FeatureStatus::Absent,
+            residual => FeatureStatus::Unknown {
+                missing: residual.data_refs(),
+            },
+        }
+    }
+
+    /// All CPUID leaves and MSRs the expression looks at.
+    ///
+    /// The result is sorted and free of duplicates.
+    pub fn data_refs(&self) -> Vec<DataRef> {
+        fn collect(expr: &BoolExpression, refs: &mut Vec<DataRef>) {
+            match expr {
+                BoolExpression::Constant(_) => {}
+                BoolExpression::CpuidBitSet(query, _, _) => refs.push(DataRef::Cpuid(*query)),
+                BoolExpression::MsrBitSet(index, _) => refs.push(DataRef::Msr(*index)),
+                BoolExpression::And(expr1, expr2) | BoolExpression::Or(expr1, expr2) => {
+                    collect(expr1, refs);
+                    collect(expr2, refs);
+                }
+                BoolExpression::Not(expr) => collect(expr, refs),
+            }
+        }
+
+        let mut refs = vec![];
+        collect(self, &mut refs);
+        refs.sort();
+        refs.dedup();
+        refs
+    }
+}
+
+/// Format a number for display in an expression.
+///
+/// Small numbers are easier to read in decimal, everything else is
+/// shown in hex.
+fn fmt_number(f: &mut std::fmt::Formatter, n: u32) -> std::fmt::Result {
+    if n < 10 {
+        write!(f, "{}", n)
+    } else {
+        write!(f, "{:#X}", n)
+    }
+}
+
+impl BoolExpression {
+    /// Format a sub-expression and add parentheses if it binds
+    /// weaker than its parent.
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter, parent: u8) -> std::fmt::Result {
+        if self.precedence() < parent {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+
+    /// The binding strength of the expression's top-level operator.
+    fn precedence(&self) -> u8 {
+        match self {
+            BoolExpression::Or(_, _) => 0,
+            BoolExpression::And(_, _) => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// Expressions are rendered in the same syntax that is used in
+/// feature definition files, e.g. `MSR 0x48B bit 46 AND NOT
+/// CPUID(7,0).EBX[2]`.
+impl std::fmt::Display for BoolExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BoolExpression::Constant(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            BoolExpression::CpuidBitSet(query, reg, bit) => {
+                write!(f, "CPUID(")?;
+                fmt_number(f, query.leaf)?;
+                write!(f, ",")?;
+                fmt_number(f, query.subleaf)?;
+                write!(f, ").{}[{}]", reg, bit)
+            }
+            BoolExpression::MsrBitSet(index, bit) => {
+                write!(f, "MSR ")?;
+                fmt_number(f, *index)?;
+                write!(f, " bit {}", bit)
             }
             BoolExpression::And(expr1, expr2) => {
-                Some(expr1.evaluate(cpu_info)? && expr2.evaluate(cpu_info)?)
+                expr1.fmt_operand(f, 1)?;
+                write!(f, " AND ")?;
+                expr2.fmt_operand(f, 2)
             }
             BoolExpression::Or(expr1, expr2) => {
-                Some(expr1.evaluate(cpu_info)? || expr2.evaluate(cpu_info)?)
+                expr1.fmt_operand(f, 0)?;
+                write!(f, " OR ")?;
+                expr2.fmt_operand(f, 1)
+            }
+            BoolExpression::Not(expr) => {
+                write!(f, "NOT ")?;
+                expr.fmt_operand(f, 2)
             }
-            BoolExpression::Not(expr) => Some(!expr.evaluate(cpu_info)?),
         }
     }
 }
@@ -66,21 +201,316 @@ impl Not for BoolExpression {
     }
 }
 
+/// The result of checking a [Feature] against CPU information.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeatureStatus {
+    Present,
+    Absent,
+
+    /// The available data is not sufficient to decide. `missing`
+    /// lists the CPUID leaves and MSRs that would be needed.
+    Unknown {
+        missing: Vec<DataRef>,
+    },
+
+    /// The feature belongs to a different CPU vendor.
+    NotApplicable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Feature {
     pub name: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+    #[serde(rename = "expression")]
     expr: BoolExpression,
+
+    /// Names of features that must be present for this feature to be
+    /// useful.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
+    /// The vendor whose CPUs can have this feature. `None` if the
+    /// feature is not vendor-specific.
+    #[serde(default)]
+    pub vendor: Option<Vendor>,
+
+    /// Whether the feature restricts the CPU, e.g. a lock or a
+    /// disabled capability. Losing it doesn't take anything away.
+    #[serde(default)]
+    pub restriction: bool,
+
+    /// Whether the feature describes how firmware or the OS set up the
+    /// CPU, e.g. x2APIC mode, rather than what the CPU can do.
+    #[serde(default)]
+    pub host_state: bool,
+}
+
+/// The category of features that don't specify one.
+pub const DEFAULT_CATEGORY: &str = "Other";
+
+fn default_category() -> String {
+    DEFAULT_CATEGORY.to_owned()
 }
 
 impl Feature {
-    pub fn new(name: &str, expr: BoolExpression) -> Self {
+    pub fn new(category: &str, name: &str, expr: BoolExpression) -> Self {
         Self {
             expr,
             name: name.to_owned(),
+            category: category.to_owned(),
+            depends_on: vec![],
+            vendor: None,
+            restriction: false,
+            host_state: false,
         }
     }
 
-    pub fn is_present(&self, cpu_info: &dyn CpuInformation) -> Option<bool> {
-        self.expr.evaluate(cpu_info)
+    /// Restrict the feature to CPUs of the given vendor. On other
+    /// vendors' CPUs, it is reported as not applicable.
+    pub fn vendor(mut self, vendor: Vendor) -> Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
+    /// Mark the feature as a restriction of the CPU rather than a
+    /// capability.
+    pub fn restriction(mut self) -> Self {
+        self.restriction = true;
+        self
+    }
+
+    /// Mark the feature as state that firmware or the OS configured.
+    pub fn host_state(mut self) -> Self {
+        self.host_state = true;
+        self
+    }
+
+    /// Declare that this feature is only useful if the named features
+    /// are present as well.
+    pub fn depends_on(mut self, names: &[&str]) -> Self {
+        self.depends_on
+            .extend(names.iter().map(|name| name.to_string()));
+        self
+    }
+
+    /// The condition that decides whether the feature is present.
+    pub fn expression(&self) -> &BoolExpression {
+        &self.expr
+    }
+
+    pub fn is_present(&self, cpu_info: &dyn CpuInformation) -> FeatureStatus {
+        if let (Some(wanted), Some(actual)) = (self.vendor, cpu_info.vendor()) {
+            if wanted != actual {
+                return FeatureStatus::NotApplicable;
+            }
+        }
+
+        self.expr.status(cpu_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidRegister::*;
+    use crate::cpu_information::CpuidResult;
+    use BoolExpression::*;
+
+    #[test]
+    fn leaves_are_displayed() {
+        assert_eq!(MsrBitSet(0x48b, 46).to_string(), "MSR 0x48B bit 46");
+        assert_eq!(MsrBitSet(0x3a, 0).to_string(), "MSR 0x3A bit 0");
+        assert_eq!(
+            CpuidBitSet(7.into(), Ebx, 2).to_string(),
+            "CPUID(7,0).EBX[2]"
+        );
+        assert_eq!(
+            CpuidBitSet(
+                CpuidQuery {
+                    leaf: 0x8000_000a,
+                    subleaf: 0
+                },
+                Edx,
+                0
+            )
+            .to_string(),
+            "CPUID(0x8000000A,0).EDX[0]"
+        );
+    }
+
+    /// A CPU that only knows a handful of values.
+    struct TestCpu;
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            match query.leaf {
+                0 => Some(CpuidResult {
+                    eax: 7,
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                }),
+                1 => Some(CpuidResult {
+                    eax: 0,
+                    ebx: 0,
+                    ecx: 1 << 5,
+                    edx: 0,
+                }),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            match index {
+                0x3a => Some(0b101),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn known_values_are_folded() {
+        assert_eq!(
+            CpuidBitSet(1.into(), Ecx, 5).simplify(&TestCpu),
+            Constant(true)
+        );
+        assert_eq!(
+            CpuidBitSet(1.into(), Ecx, 6).simplify(&TestCpu),
+            Constant(false)
+        );
+        assert_eq!(MsrBitSet(0x3a, 2).simplify(&TestCpu), Constant(true));
+        assert_eq!(MsrBitSet(0x3a, 1).simplify(&TestCpu), Constant(false));
+
+        // Leaves beyond the maximum leaf are known to be zero.
+        assert_eq!(
+            CpuidBitSet(0x12.into(), Eax, 0).simplify(&TestCpu),
+            Constant(false)
+        );
+    }
+
+    #[test]
+    fn residual_contains_only_missing_data() {
+        let known_true = || MsrBitSet(0x3a, 0);
+        let known_false = || MsrBitSet(0x3a, 1);
+        let unknown = || MsrBitSet(0x48b, 33);
+        let unknown_leaf = || CpuidBitSet(7.into(), Ebx, 2);
+
+        assert_eq!((known_true() & unknown()).simplify(&TestCpu), unknown());
+        assert_eq!(
+            (known_false() & unknown()).simplify(&TestCpu),
+            Constant(false)
+        );
+        assert_eq!(
+            (known_true() | unknown()).simplify(&TestCpu),
+            Constant(true)
+        );
+        assert_eq!((known_false() | unknown()).simplify(&TestCpu), unknown());
+        assert_eq!(
+            (!known_false() & !unknown() & unknown_leaf()).simplify(&TestCpu),
+            !unknown() & unknown_leaf()
+        );
+    }
+
+    #[test]
+    fn unknown_status_lists_missing_data() {
+        let feature = Feature::new(
+            DEFAULT_CATEGORY,
+            "Test",
+            MsrBitSet(0x3a, 0)
+                & (MsrBitSet(0x48b, 33) | MsrBitSet(0x48b, 39))
+                & CpuidBitSet(7.into(), Ebx, 2),
+        );
+
+        assert_eq!(
+            feature.is_present(&TestCpu),
+            FeatureStatus::Unknown {
+                missing: vec![DataRef::Cpuid(7.into()), DataRef::Msr(0x48b)]
+            }
+        );
+
+        assert_eq!(
+            Feature::new(DEFAULT_CATEGORY, "Test", MsrBitSet(0x3a, 0)).is_present(&TestCpu),
+            FeatureStatus::Present
+        );
+        assert_eq!(
+            Feature::new(
+                DEFAULT_CATEGORY,
+                "Test",
+                MsrBitSet(0x3a, 1) & MsrBitSet(0x48b, 33)
+            )
+            .is_present(&TestCpu),
+            FeatureStatus::Absent
+        );
+    }
+
+    #[test]
+    fn features_of_other_vendors_are_not_applicable() {
+        // Without a known vendor, vendor-specific features are evaluated.
+        let feature = || Feature::new(DEFAULT_CATEGORY, "Test", MsrBitSet(0x3a, 0));
+
+        assert_eq!(
+            feature().vendor(Vendor::Amd).is_present(&TestCpu),
+            FeatureStatus::Present
+        );
+
+        struct IntelCpu;
+
+        impl CpuInformation for IntelCpu {
+            fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+                match query.leaf {
+                    0 => Some(CpuidResult {
+                        eax: 0,
+                        ebx: 0x756E6547,
+                        ecx: 0x6C65746E,
+                        edx: 0x49656E69,
+                    }),
+                    _ => None,
+                }
+            }
+
+            fn rdmsr(&self, _index: u32) -> Option<u64> {
+                Some(1)
+            }
+        }
+
+        assert_eq!(IntelCpu.vendor(), Some(Vendor::Intel));
+        assert_eq!(
+            feature().vendor(Vendor::Intel).is_present(&IntelCpu),
+            FeatureStatus::Present
+        );
+        assert_eq!(
+            feature().vendor(Vendor::Amd).is_present(&IntelCpu),
+            FeatureStatus::NotApplicable
+        );
+    }
+
+    #[test]
+    fn operators_are_displayed_with_minimal_parentheses() {
+        let a = || MsrBitSet(0x48b, 46);
+        let b = || CpuidBitSet(7.into(), Ebx, 2);
+        let c = || MsrBitSet(0x3a, 0);
+
+        assert_eq!(
+            (a() & !b()).to_string(),
+            "MSR 0x48B bit 46 AND NOT CPUID(7,0).EBX[2]"
+        );
+        assert_eq!(
+            (a() | b() & c()).to_string(),
+            "MSR 0x48B bit 46 OR CPUID(7,0).EBX[2] AND MSR 0x3A bit 0"
+        );
+        assert_eq!(
+            ((a() | b()) & c()).to_string(),
+            "(MSR 0x48B bit 46 OR CPUID(7,0).EBX[2]) AND MSR 0x3A bit 0"
+        );
+        assert_eq!(
+            (a() & (b() & c())).to_string(),
+            "MSR 0x48B bit 46 AND (CPUID(7,0).EBX[2] AND MSR 0x3A bit 0)"
+        );
+        assert_eq!(
+            (!(a() | b())).to_string(),
+            "NOT (MSR 0x48B bit 46 OR CPUID(7,0).EBX[2])"
+        );
     }
 }
@@ -0,0 +1,310 @@
+//! # Derive CPU Frequencies
+//!
+//! CPUID leaf 0x15 relates the TSC to the core crystal clock: the TSC
+//! runs at `crystal * EBX / EAX`. ECX holds the crystal frequency in
+//! Hz, but many CPUs leave it zero. Like Linux, this then derives the
+//! crystal frequency from the base frequency, which runs at the same
+//! rate as the TSC on these CPUs.
+//!
+//! CPUID leaf 0x16 enumerates the base, maximum and bus frequencies
+//! in MHz. These are the marketing values, not measurements. CPUs
+//! without the leaf may still have the base frequency in
+//! MSR_PLATFORM_INFO.
+//!
+//! See the Intel SDM Vol. 2A, CPUID, and Vol. 3B, Section 19.7.3.
+
+use regex::Regex;
+use std::fmt;
+
+use crate::cpu_information::{CpuInformation, CpuidResult};
+use crate::power;
+use crate::report::Section;
+
+/// The CPUID leaf with the TSC and crystal clock ratio.
+pub const CPUID_TSC: u32 = 0x15;
+
+/// The CPUID leaf with the base, maximum and bus frequencies.
+pub const CPUID_FREQUENCY: u32 = 0x16;
+
+/// The frequencies that a dump enumerates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Frequencies {
+    /// The TSC frequency in Hz.
+    pub tsc: Option<u64>,
+
+    /// The core crystal clock in Hz.
+    pub crystal: Option<u64>,
+
+    /// Whether the crystal clock was derived from the base frequency
+    /// instead of being enumerated.
+    pub crystal_derived: bool,
+
+    /// The TSC to crystal clock ratio as numerator and denominator.
+    pub tsc_ratio: Option<(u32, u32)>,
+
+    /// The base frequency in MHz.
+    pub base: Option<u64>,
+
+    /// Whether the base frequency was taken from the brand string.
+    pub base_from_brand: bool,
+
+    /// The maximum frequency in MHz.
+    pub max: Option<u64>,
+
+    /// The bus (reference) frequency in MHz.
+    pub bus: Option<u64>,
+}
+
+impl Frequencies {
+    /// Whether nothing is known.
+    pub fn is_empty(&self) -> bool {
+        *self == Frequencies::default()
+    }
+}
+
+/// Format a frequency in Hz as MHz.
+fn mhz(hz: u64) -> String {
+    if hz.is_multiple_of(1_000_000) {
+        format!("{} MHz", hz / 1_000_000)
+    } else {
+        format!("{:.3} MHz", hz as f64 / 1e6)
+    }
+}
+
+/// A one-line summary for the report header.
+impl fmt::Display for Frequencies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = [
+            self.base.map(|b| format!("base {} MHz", b)),
+            self.max.map(|m| format!("maximum {} MHz", m)),
+            self.bus.map(|b| format!("bus {} MHz", b)),
+            self.tsc.map(|t| format!("TSC {}", mhz(t))),
+        ]
+        .iter()
+        .flatten()
+        .cloned()
+        .collect();
+
+        write!(f, "Frequencies: {}", parts.join(", "))
+    }
+}
+
+/// A CPUID leaf if the CPU implements it.
+fn leaf(cpu_info: &dyn CpuInformation, leaf: u32) -> Option<CpuidResult> {
+    cpu_info
+        .cpuid(leaf.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(leaf.into()))
+}
+
+/// The frequency in the brand string in MHz.
+fn brand_frequency(brand: &str) -> Option<u64> {
+    lazy_static! {
+        static ref FREQUENCY_RE: Regex =
+            Regex::new(r"@\s*([0-9]+(?:\.[0-9]+)?)\s*([GM])Hz").expect("a valid regex");
+    }
+
+    let captures = FREQUENCY_RE.captures(brand)?;
+    let value: f64 = captures[1].parse().ok()?;
+    let scale = if &captures[2] == "G" { 1000.0 } else { 1.0 };
+
+    Some((value * scale).round() as u64).filter(|mhz| *mhz != 0)
+}
+
+/// Collect the frequencies from CPUID leaves 0x15 and 0x16,
+/// MSR_PLATFORM_INFO and the brand string.
+pub fn frequencies(cpu_info: &dyn CpuInformation) -> Frequencies {
+    let nonzero = |v: u32| Some(u64::from(v)).filter(|v| *v != 0);
+    let mut frequencies = Frequencies::default();
+
+    if let Some(r) = leaf(cpu_info, CPUID_FREQUENCY) {
+        frequencies.base = nonzero(r.eax & 0xffff);
+        frequencies.max = nonzero(r.ebx & 0xffff);
+        frequencies.bus = nonzero(r.ecx & 0xffff);
+    }
+    if frequencies.base.is_none() {
+        frequencies.base = power::base_frequency(cpu_info);
+    }
+    if frequencies.base.is_none() {
+        frequencies.base = cpu_info
+            .model_name()
+            .and_then(|brand| brand_frequency(&brand));
+        frequencies.base_from_brand = frequencies.base.is_some();
+    }
+
+    // The ratio is not enumerated if either value is zero.
+    if let Some(r) = leaf(cpu_info, CPUID_TSC).filter(|r| r.eax != 0 && r.ebx != 0) {
+        frequencies.tsc_ratio = Some((r.ebx, r.eax));
+
+        frequencies.crystal = nonzero(r.ecx).or_else(|| {
+            frequencies.crystal_derived = true;
+            frequencies
+                .base
+                .map(|base| base * 1_000_000 * u64::from(r.eax) / u64::from(r.ebx))
+        });
+        frequencies.tsc = frequencies
+            .crystal
+            .map(|crystal| crystal * u64::from(r.ebx) / u64::from(r.eax));
+    }
+
+    frequencies
+}
+
+/// Show the frequencies and how they were derived.
+fn frequency_section(frequencies: &Frequencies) -> Section {
+    let mut section = Section::new(&format!(
+        "Frequencies (CPUID {:#x} and {:#x})",
+        CPUID_TSC, CPUID_FREQUENCY
+    ));
+
+    if let Some((numerator, denominator)) = frequencies.tsc_ratio {
+        section.row(
+            "TSC/crystal clock ratio",
+            format!("{}/{}", numerator, denominator),
+        );
+    }
+    if let Some(crystal) = frequencies.crystal {
+        section.row(
+            "Crystal clock",
+            if frequencies.crystal_derived {
+                format!("{} (derived from base frequency)", mhz(crystal))
+            } else {
+                mhz(crystal)
+            },
+        );
+    }
+    if let Some(tsc) = frequencies.tsc {
+        section.row("TSC frequency", mhz(tsc));
+    }
+    if let Some(base) = frequencies.base {
+        section.row(
+            "Base frequency",
+            if frequencies.base_from_brand {
+                format!("{} MHz (from brand string)", base)
+            } else {
+                format!("{} MHz", base)
+            },
+        );
+    }
+    if let Some(max) = frequencies.max {
+        section.row("Maximum frequency", format!("{} MHz", max));
+    }
+    if let Some(bus) = frequencies.bus {
+        section.row("Bus frequency", format!("{} MHz", bus));
+    }
+
+    section
+}
+
+/// Decode the frequency information that is available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let frequencies = frequencies(cpu_info);
+
+    if frequencies.is_empty() {
+        vec![]
+    } else {
+        vec![frequency_section(&frequencies)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidQuery;
+
+    struct TestCpu {
+        leaf15: CpuidResult,
+        leaf16: CpuidResult,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            match query.leaf {
+                0 => Some(CpuidResult {
+                    eax: CPUID_FREQUENCY,
+                    ..self.leaf15
+                }),
+                CPUID_TSC => Some(self.leaf15),
+                CPUID_FREQUENCY => Some(self.leaf16),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            None
+        }
+    }
+
+    fn result(eax: u32, ebx: u32, ecx: u32) -> CpuidResult {
+        CpuidResult {
+            eax,
+            ebx,
+            ecx,
+            edx: 0,
+        }
+    }
+
+    #[test]
+    fn enumerated_crystal_clock_is_used() {
+        // An Ice Lake CPU with a 38.4 MHz crystal.
+        let cpu = TestCpu {
+            leaf15: result(2, 78, 38_400_000),
+            leaf16: result(1500, 3900, 100),
+        };
+        let frequencies = frequencies(&cpu);
+
+        assert_eq!(frequencies.crystal, Some(38_400_000));
+        assert!(!frequencies.crystal_derived);
+        assert_eq!(frequencies.tsc, Some(1_497_600_000));
+        assert_eq!(frequencies.max, Some(3900));
+        assert_eq!(
+            frequencies.to_string(),
+            "Frequencies: base 1500 MHz, maximum 3900 MHz, bus 100 MHz, TSC 1497.600 MHz"
+        );
+    }
+
+    #[test]
+    fn crystal_clock_is_derived_from_base_frequency() {
+        // A Kaby Lake CPU, which doesn't enumerate its 24 MHz crystal.
+        let cpu = TestCpu {
+            leaf15: result(2, 300, 0),
+            leaf16: result(3600, 4200, 100),
+        };
+        let section = frequency_section(&frequencies(&cpu));
+
+        assert_eq!(section.get("TSC/crystal clock ratio"), Some("300/2"));
+        assert_eq!(
+            section.get("Crystal clock"),
+            Some("24 MHz (derived from base frequency)")
+        );
+        assert_eq!(section.get("TSC frequency"), Some("3600 MHz"));
+        assert_eq!(section.get("Bus frequency"), Some("100 MHz"));
+    }
+
+    #[test]
+    fn brand_frequency_is_parsed() {
+        assert_eq!(
+            brand_frequency("Intel(R) Core(TM) i7-3770 CPU @ 3.40GHz"),
+            Some(3400)
+        );
+        assert_eq!(brand_frequency("Intel(R) Pentium(R) 4 CPU 2.80GHz"), None);
+        assert_eq!(
+            brand_frequency("Intel(R) Pentium(R) III CPU @ 933MHz\0\0"),
+            Some(933)
+        );
+        assert_eq!(
+            brand_frequency("Intel(R) Celeron(R) CPU N3060 @ 1.60GHz"),
+            Some(1600)
+        );
+        assert_eq!(brand_frequency("AMD Ryzen 7 5800X 8-Core Processor"), None);
+    }
+
+    #[test]
+    fn missing_ratio_is_not_reported() {
+        let cpu = TestCpu {
+            leaf15: result(0, 0, 0),
+            leaf16: result(0, 0, 0),
+        };
+
+        assert!(frequencies(&cpu).is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+//! # Hybrid CPUs
+//!
+//! Hybrid CPUs combine performance cores (P-cores) and efficient cores
+//! (E-cores) in one package. CPUID leaf 7 EDX bit 15 marks such CPUs
+//! and leaf 0x1A tells which type of core a logical CPU is.
+//!
+//! Operating systems and hypervisors expect all CPUs to enumerate the
+//! same features. On early hybrid CPUs, this was not the case: the
+//! P-cores of Alder Lake could run AVX-512, the E-cores could not. If
+//! the dump contains all logical CPUs, we compare the feature bits of
+//! the core types to find such differences.
+//!
+//! See the Intel SDM Vol. 2A, CPUID—CPU Identification.
+
+use std::collections::BTreeMap as Map;
+
+use crate::cpu_information::CpuidRegister::{self, *};
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::features::Bit;
+use crate::report::Section;
+use crate::structured_features;
+
+/// The CPUID leaf with the core type.
+pub const CPUID_HYBRID_INFORMATION: u32 = 0x1a;
+
+/// The bit in CPUID leaf 7 EDX that marks hybrid CPUs.
+pub const HYBRID: Bit = 15;
+
+/// The registers with feature bits that are compared between core
+/// types.
+pub const FEATURE_REGISTERS: &[(u32, u32, CpuidRegister)] = &[
+    (0x1, 0, Ecx),
+    (0x1, 0, Edx),
+    (0x7, 0, Ebx),
+    (0x7, 0, Ecx),
+    (0x7, 0, Edx),
+    (0x7, 1, Eax),
+    (0x7, 1, Edx),
+    (0xd, 1, Eax),
+    (0x8000_0001, 0, Ecx),
+    (0x8000_0001, 0, Edx),
+];
+
+/// The type of a core in a hybrid CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CoreType {
+    Atom,
+    Core,
+    Reserved(u32),
+}
+
+impl From<u32> for CoreType {
+    fn from(value: u32) -> Self {
+        match value {
+            0x20 => CoreType::Atom,
+            0x40 => CoreType::Core,
+            other => CoreType::Reserved(other),
+        }
+    }
+}
+
+impl std::fmt::Display for CoreType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CoreType::Atom => f.write_str("E-core"),
+            CoreType::Core => f.write_str("P-core"),
+            CoreType::Reserved(value) => write!(f, "Core type {:#x}", value),
+        }
+    }
+}
+
+/// Whether the CPU claims to be a hybrid CPU.
+pub fn is_hybrid(cpu_info: &dyn CpuInformation) -> bool {
+    cpu_info.is_cpuid_query_valid(7.into())
+        && cpu_info
+            .cpuid(7.into())
+            .is_some_and(|r| (r.edx >> HYBRID) & 1 == 1)
+}
+
+/// The core type of a logical CPU.
+///
+/// The validity of leaf 0x1A must be checked on the boot CPU.
+fn core_type(cpu_info: &dyn CpuInformation) -> Option<CoreType> {
+    cpu_info
+        .cpuid(CPUID_HYBRID_INFORMATION.into())
+        .map(|r| (r.eax >> 24).into())
+        .filter(|t| *t != CoreType::Reserved(0))
+}
+
+/// A feature bit whose value depends on the core type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub name: String,
+
+    /// For each core type, whether the bit is set on all (`Some(true)`),
+    /// none (`Some(false)`) or only some (`None`) of its CPUs.
+    pub values: Map<CoreType, Option<bool>>,
+}
+
+/// A name for a feature bit.
+fn bit_name(leaf: u32, subleaf: u32, register: CpuidRegister, bit: Bit) -> String {
+    let location = format!("CPUID {:#x}.{} {}[{}]", leaf, subleaf, register, bit);
+
+    match (leaf == 7)
+        .then(|| structured_features::flag_name(subleaf, register, bit))
+        .flatten()
+    {
+        Some(name) => format!("{} ({})", location, name),
+        None => location,
+    }
+}
+
+/// Compare the feature bits of all logical CPUs by core type.
+pub fn divergences(cpus: &[(CoreType, &dyn CpuInformation)]) -> Vec<Divergence> {
+    let mut result = vec![];
+
+    for &(leaf, subleaf, register) in FEATURE_REGISTERS {
+        let query = CpuidQuery { leaf, subleaf };
+
+        // The values of the register, grouped by core type.
+        let mut by_type: Map<CoreType, Vec<u32>> = Map::new();
+        for (core_type, cpu) in cpus {
+            if let Some(r) = cpu.cpuid(query) {
+                by_type.entry(*core_type).or_default().push(r.get(register));
+            }
+        }
+
+        for bit in 0..32 {
+            let values: Map<CoreType, Option<bool>> = by_type
+                .iter()
+                .map(|(core_type, values)| {
+                    let set = values.iter().filter(|v| (*v >> bit) & 1 == 1).count();
+                    let value = match set {
+                        0 => Some(false),
+                        n if n == values.len() => Some(true),
+                        _ => None,
+                    };
+
+                    (*core_type, value)
+                })
+                .collect();
+
+            let mut distinct = values.values().collect::<Vec<_>>();
+            distinct.dedup();
+
+            if distinct.len() > 1 || distinct.contains(&&None) {
+                result.push(Divergence {
+                    name: bit_name(leaf, subleaf, register, bit),
+                    values,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// Describe the hybrid configuration and feature differences.
+pub fn section(cpu_info: &dyn CpuInformation) -> Option<Section> {
+    if !is_hybrid(cpu_info) || !cpu_info.is_cpuid_query_valid(CPUID_HYBRID_INFORMATION.into()) {
+        return None;
+    }
+
+    let mut section = Section::new(&format!(
+        "Hybrid CPU (CPUID {:#x})",
+        CPUID_HYBRID_INFORMATION
+    ));
+
+    if let Some(r) = cpu_info.cpuid(CPUID_HYBRID_INFORMATION.into()) {
+        section.row("Boot CPU core type", CoreType::from(r.eax >> 24));
+        section.row("Native model ID", format!("{:#x}", r.eax & 0xff_ffff));
+    }
+
+    let logical_cpus = cpu_info.logical_cpus();
+    let cpus: Vec<(CoreType, &dyn CpuInformation)> = logical_cpus
+        .iter()
+        .filter_map(|&cpu| Some((core_type(cpu)?, cpu)))
+        .collect();
+
+    if cpus.is_empty() {
+        section.row("Per-CPU data", "not in dump");
+        return Some(section);
+    }
+
+    let mut counts: Map<CoreType, usize> = Map::new();
+    for (core_type, _) in &cpus {
+        *counts.entry(*core_type).or_default() += 1;
+    }
+    for (core_type, count) in counts {
+        section.row(&format!("{}s", core_type), count);
+    }
+
+    let divergences = divergences(&cpus);
+    if divergences.is_empty() {
+        section.row("Feature differences", "none");
+    }
+
+    for divergence in divergences {
+        let values = divergence
+            .values
+            .iter()
+            .map(|(core_type, value)| {
+                let value = match value {
+                    Some(true) => "Y",
+                    Some(false) => "N",
+                    None => "mixed",
+                };
+
+                format!("{}: {}", core_type, value)
+            })
+            .collect::<Vec<_>>();
+
+        section.row(&divergence.name, values.join(", "));
+    }
+
+    Some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidResult;
+
+    /// A logical CPU of an Alder Lake with AVX-512 on the P-cores.
+    struct TestCpu {
+        core_type: u32,
+        avx512: bool,
+        others: Vec<TestCpu>,
+    }
+
+    impl TestCpu {
+        fn new(others: Vec<TestCpu>) -> Self {
+            TestCpu {
+                core_type: 0x40,
+                avx512: true,
+                others,
+            }
+        }
+
+        fn logical(core_type: u32, avx512: bool) -> Self {
+            TestCpu {
+                core_type,
+                avx512,
+                others: vec![],
+            }
+        }
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let result = |eax, ebx, edx| CpuidResult {
+                eax,
+                ebx,
+                ecx: 0,
+                edx,
+            };
+
+            match (query.leaf, query.subleaf) {
+                (0x0, 0) => Some(result(0x20, 0, 0)),
+                (0x7, 0) => Some(result(0, if self.avx512 { 1 << 16 } else { 0 }, 1 << 15)),
+                (0x1a, 0) => Some(result((self.core_type << 24) | 1, 0, 0)),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            None
+        }
+
+        fn logical_cpus(&self) -> Vec<&dyn CpuInformation> {
+            self.others
+                .iter()
+                .map(|cpu| cpu as &dyn CpuInformation)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn core_types_are_counted() {
+        let cpu = TestCpu::new(vec![
+            TestCpu::logical(0x40, true),
+            TestCpu::logical(0x40, true),
+            TestCpu::logical(0x20, true),
+        ]);
+        let section = section(&cpu).expect("hybrid CPU");
+
+        assert_eq!(section.get("Boot CPU core type"), Some("P-core"));
+        assert_eq!(section.get("Native model ID"), Some("0x1"));
+        assert_eq!(section.get("P-cores"), Some("2"));
+        assert_eq!(section.get("E-cores"), Some("1"));
+        assert_eq!(section.get("Feature differences"), Some("none"));
+    }
+
+    #[test]
+    fn avx512_asymmetry_is_reported() {
+        let cpu = TestCpu::new(vec![
+            TestCpu::logical(0x40, true),
+            TestCpu::logical(0x20, false),
+            TestCpu::logical(0x20, false),
+        ]);
+        let section = section(&cpu).expect("hybrid CPU");
+
+        assert_eq!(
+            section.get("CPUID 0x7.0 EBX[16] (AVX512F)"),
+            Some("E-core: N, P-core: Y")
+        );
+        assert_eq!(section.rows.len(), 5);
+    }
+
+    #[test]
+    fn differences_within_a_core_type_are_reported() {
+        let cpu = TestCpu::logical(0x40, true);
+        let other = TestCpu::logical(0x40, false);
+        let divergences = divergences(&[(CoreType::Core, &cpu), (CoreType::Core, &other)]);
+
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].values.get(&CoreType::Core), Some(&None));
+    }
+
+    #[test]
+    fn missing_per_cpu_data_is_reported() {
+        let section = section(&TestCpu::new(vec![])).expect("hybrid CPU");
+
+        assert_eq!(section.get("Per-CPU data"), Some("not in dump"));
+    }
+
+    #[test]
+    fn non_hybrid_cpus_have_no_section() {
+        struct NonHybrid;
+
+        impl CpuInformation for NonHybrid {
+            fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+                (query.leaf == 0).then_some(CpuidResult {
+                    eax: 0x20,
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                })
+            }
+
+            fn rdmsr(&self, _index: u32) -> Option<u64> {
+                None
+            }
+        }
+
+        assert_eq!(section(&NonHybrid), None);
+    }
+}
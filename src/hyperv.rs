@@ -0,0 +1,239 @@
+//! # Decode Hyper-V Enlightenments
+//!
+//! Hyper-V describes the enlightenments it offers to guests in CPUID
+//! leaves 0x40000003 to 0x4000000A. Other hypervisors, such as KVM,
+//! emulate these leaves to run Windows guests efficiently.
+//!
+//! See the Hyper-V Top Level Functional Specification, Section 2.4.
+
+use crate::cpu_information::{CpuInformation, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// Partition privileges in EAX of leaf 0x40000003.
+const PRIVILEGES: &[(Bit, &str)] = &[
+    (0, "VP runtime MSR"),
+    (1, "Partition reference counter"),
+    (2, "SynIC MSRs"),
+    (3, "Synthetic timer MSRs"),
+    (4, "APIC MSRs"),
+    (5, "Hypercall MSRs"),
+    (6, "VP index MSR"),
+    (7, "Reset MSR"),
+    (8, "Statistics MSRs"),
+    (9, "Reference TSC page"),
+    (10, "Guest idle MSR"),
+    (11, "Frequency MSRs"),
+    (12, "Debug MSRs"),
+    (13, "Reenlightenment controls"),
+];
+
+/// Features in EDX of leaf 0x40000003.
+const FEATURES: &[(Bit, &str)] = &[
+    (1, "Guest debugging"),
+    (2, "Performance monitor"),
+    (3, "Physical CPU dynamic partitioning events"),
+    (4, "Hypercall input via XMM registers"),
+    (5, "Virtual guest idle state"),
+    (6, "Hypervisor sleep state"),
+    (7, "NUMA distance query"),
+    (8, "Timer frequency details"),
+    (9, "Synthetic machine check injection"),
+    (10, "Guest crash MSRs"),
+    (11, "Debug MSRs"),
+    (12, "NPIEP"),
+    (13, "Disable hypervisor"),
+    (14, "Extended GVA ranges for flush"),
+    (15, "Hypercall output via XMM registers"),
+    (17, "SINT polling mode"),
+    (18, "Hypercall MSR lock"),
+    (19, "Direct synthetic timers"),
+];
+
+/// Implementation recommendations in EAX of leaf 0x40000004.
+const RECOMMENDATIONS: &[(Bit, &str)] = &[
+    (0, "Hypercall for address space switch"),
+    (1, "Hypercall for local TLB flush"),
+    (2, "Hypercall for remote TLB flush"),
+    (3, "MSRs for APIC access"),
+    (4, "MSR for system reset"),
+    (5, "Relaxed timing"),
+    (6, "DMA remapping"),
+    (7, "Interrupt remapping"),
+    (8, "x2APIC MSRs"),
+    (9, "Deprecate AutoEOI"),
+    (10, "Synthetic cluster IPI hypercall"),
+    (11, "Extended processor masks"),
+    (12, "Nested partition"),
+    (13, "INT for MBEC system calls"),
+    (14, "Enlightened VMCS"),
+    (15, "Synced timeline"),
+    (17, "Direct local flush entire"),
+    (18, "No non-architectural core sharing"),
+];
+
+/// Hardware features used by the hypervisor in EAX of leaf
+/// 0x40000006.
+const HARDWARE_FEATURES: &[(Bit, &str)] = &[
+    (0, "APIC overlay assist"),
+    (1, "MSR bitmaps"),
+    (2, "Architectural performance counters"),
+    (3, "Second-level address translation"),
+    (4, "DMA remapping"),
+    (5, "Interrupt remapping"),
+    (6, "Memory patrol scrubber"),
+    (7, "DMA protection"),
+    (8, "HPET"),
+    (9, "Volatile synthetic timers"),
+];
+
+/// Nested virtualization optimizations in EAX of leaf 0x4000000A.
+const NESTED_FEATURES: &[(Bit, &str)] = &[
+    (17, "Direct virtual flush hypercalls"),
+    (18, "Flush guest physical address space hypercalls"),
+    (19, "Enlightened MSR bitmap"),
+    (20, "Combined virtualization exceptions"),
+    (22, "Enlightened TLB"),
+];
+
+fn flags_section(title: &str, leaf: u32, value: u32, flags: &[(Bit, &str)]) -> Section {
+    let mut section = Section::new(&format!("Hyper-V {} (CPUID {:#x})", title, leaf));
+
+    for (bit, name) in flags {
+        section.flag(name, value.into(), *bit);
+    }
+
+    section
+}
+
+/// Decode leaf 0x40000004.
+fn recommendations_section(r: CpuidResult) -> Section {
+    let mut section = flags_section("recommendations", 0x4000_0004, r.eax, RECOMMENDATIONS);
+
+    section.row(
+        "Spinlock retries",
+        if r.ebx == 0xffff_ffff {
+            "never notify".to_owned()
+        } else {
+            r.ebx.to_string()
+        },
+    );
+
+    section
+}
+
+/// Decode leaf 0x40000005.
+fn limits_section(r: CpuidResult) -> Section {
+    let mut section = Section::new("Hyper-V implementation limits (CPUID 0x40000005)");
+
+    section.row("Maximum virtual processors", r.eax);
+    section.row("Maximum logical processors", r.ebx);
+    section.row("Maximum remapped interrupt vectors", r.ecx);
+
+    section
+}
+
+/// Decode leaf 0x4000000A.
+fn nested_section(r: CpuidResult) -> Section {
+    let mut section = flags_section("nested features", 0x4000_000a, r.eax, NESTED_FEATURES);
+
+    let (low, high) = (r.eax & 0xff, (r.eax >> 8) & 0xff);
+    section.row(
+        "Enlightened VMCS versions",
+        if low == 0 && high == 0 {
+            "none".to_owned()
+        } else {
+            format!("{} to {}", low, high)
+        },
+    );
+
+    section
+}
+
+/// Decode the Hyper-V leaves that are available.
+///
+/// The caller must make sure that the hypervisor is Hyper-V
+/// compatible.
+pub fn sections(cpu_info: &dyn CpuInformation, max_leaf: u32) -> Vec<Section> {
+    let leaf = |leaf: u32| {
+        Some(leaf)
+            .filter(|l| *l <= max_leaf)
+            .and_then(|l| cpu_info.cpuid(l.into()))
+    };
+    let mut sections = vec![];
+
+    if let Some(r) = leaf(0x4000_0003) {
+        sections.push(flags_section("privileges", 0x4000_0003, r.eax, PRIVILEGES));
+        sections.push(flags_section("features", 0x4000_0003, r.edx, FEATURES));
+    }
+    if let Some(r) = leaf(0x4000_0004) {
+        sections.push(recommendations_section(r));
+    }
+    if let Some(r) = leaf(0x4000_0005) {
+        sections.push(limits_section(r));
+    }
+    if let Some(r) = leaf(0x4000_0006) {
+        sections.push(flags_section(
+            "hardware features",
+            0x4000_0006,
+            r.eax,
+            HARDWARE_FEATURES,
+        ));
+    }
+    if let Some(r) = leaf(0x4000_000a) {
+        sections.push(nested_section(r));
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    fn result(eax: u32, ebx: u32) -> CpuidResult {
+        CpuidResult {
+            eax,
+            ebx,
+            ecx: 0,
+            edx: 0,
+        }
+    }
+
+    #[test]
+    fn recommendations_are_decoded() {
+        let section = recommendations_section(result(0x0002_4e24, 0xfff));
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Hypercall for remote TLB flush"), "Y");
+        assert_eq!(row("Relaxed timing"), "Y");
+        assert_eq!(row("Enlightened VMCS"), "Y");
+        assert_eq!(row("Nested partition"), "N");
+        assert_eq!(row("Spinlock retries"), "4095");
+        assert_eq!(
+            recommendations_section(result(0, 0xffff_ffff)).get("Spinlock retries"),
+            Some("never notify")
+        );
+    }
+
+    #[test]
+    fn nested_features_are_decoded() {
+        let section = nested_section(result(0x000a_0101, 0));
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Enlightened VMCS versions"), "1 to 1");
+        assert_eq!(row("Enlightened MSR bitmap"), "Y");
+        assert_eq!(row("Direct virtual flush hypercalls"), "Y");
+        assert_eq!(row("Enlightened TLB"), "N");
+    }
+
+    #[test]
+    fn flags_are_sorted_and_unique() {
+        assert_sorted_unique(PRIVILEGES);
+        assert_sorted_unique(FEATURES);
+        assert_sorted_unique(RECOMMENDATIONS);
+        assert_sorted_unique(HARDWARE_FEATURES);
+        assert_sorted_unique(NESTED_FEATURES);
+    }
+}
@@ -0,0 +1,192 @@
+//! # Identify the Hypervisor
+//!
+//! Inside a virtual machine, CPUID leaves 0x40000000 and up belong
+//! to the hypervisor. Leaf 0x40000000 returns the highest hypervisor
+//! leaf in EAX and a 12-byte signature in EBX, ECX and EDX that
+//! identifies the hypervisor.
+//!
+//! These leaves only mean something if CPUID leaf 1 sets the
+//! hypervisor bit. On bare metal, Intel CPUs return the data of the
+//! highest basic leaf for them instead.
+
+use std::fmt;
+
+use crate::cpu_information::CpuInformation;
+use crate::cpu_information::CpuidRegister::Ecx;
+use crate::features::BoolExpression::CpuidBitSet;
+use crate::features::FeatureStatus;
+use crate::report::Section;
+use crate::{hyperv, kvm, vmware, xen};
+
+/// The first hypervisor CPUID leaf.
+pub const CPUID_HYPERVISOR_BASE: u32 = 0x4000_0000;
+
+/// A known hypervisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hypervisor {
+    Kvm,
+    HyperV,
+    Vmware,
+    Xen,
+    VirtualBox,
+    QemuTcg,
+    Parallels,
+    Bhyve,
+    Acrn,
+}
+
+/// Hypervisor signatures in leaf 0x40000000.
+const SIGNATURES: &[(&[u8; 12], Hypervisor)] = &[
+    (b"KVMKVMKVM\0\0\0", Hypervisor::Kvm),
+    (b"Microsoft Hv", Hypervisor::HyperV),
+    (b"VMwareVMware", Hypervisor::Vmware),
+    (b"XenVMMXenVMM", Hypervisor::Xen),
+    (b"VBoxVBoxVBox", Hypervisor::VirtualBox),
+    (b"TCGTCGTCGTCG", Hypervisor::QemuTcg),
+    (b" prl hyperv ", Hypervisor::Parallels),
+    (b" lrpepyh  vr", Hypervisor::Parallels),
+    (b"bhyve bhyve ", Hypervisor::Bhyve),
+    (b"ACRNACRNACRN", Hypervisor::Acrn),
+];
+
+impl fmt::Display for Hypervisor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Hypervisor::Kvm => "KVM",
+            Hypervisor::HyperV => "Hyper-V",
+            Hypervisor::Vmware => "VMware",
+            Hypervisor::Xen => "Xen",
+            Hypervisor::VirtualBox => "VirtualBox",
+            Hypervisor::QemuTcg => "QEMU TCG",
+            Hypervisor::Parallels => "Parallels",
+            Hypervisor::Bhyve => "bhyve",
+            Hypervisor::Acrn => "ACRN",
+        })
+    }
+}
+
+/// Whether CPUID leaf 1 says that the CPU runs under a hypervisor.
+pub fn is_present(cpu_info: &dyn CpuInformation) -> bool {
+    CpuidBitSet(1.into(), Ecx, 31).status(cpu_info) == FeatureStatus::Present
+}
+
+/// The raw signature of leaf 0x40000000 and the highest hypervisor
+/// leaf, if there is a hypervisor.
+fn signature(cpu_info: &dyn CpuInformation) -> Option<([u8; 12], u32)> {
+    if !is_present(cpu_info) {
+        return None;
+    }
+
+    let r = cpu_info.cpuid(CPUID_HYPERVISOR_BASE.into())?;
+
+    let mut signature = [0; 12];
+    for (chunk, dword) in signature.chunks_mut(4).zip(&[r.ebx, r.ecx, r.edx]) {
+        chunk.copy_from_slice(&dword.to_le_bytes());
+    }
+
+    Some((signature, r.eax))
+}
+
+/// Identify the hypervisor from its CPUID signature.
+pub fn identify(cpu_info: &dyn CpuInformation) -> Option<Hypervisor> {
+    let (signature, _) = signature(cpu_info)?;
+
+    SIGNATURES
+        .iter()
+        .find(|(s, _)| **s == signature)
+        .map(|(_, hypervisor)| *hypervisor)
+}
+
+/// Decode the hypervisor leaves that are available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let (signature, max_leaf) = match signature(cpu_info) {
+        Some(s) if s.0 != [0; 12] => s,
+        _ => return vec![],
+    };
+
+    let mut section = Section::new(&format!("Hypervisor (CPUID {:#x})", CPUID_HYPERVISOR_BASE));
+    section.row(
+        "Signature",
+        format!("{:?}", String::from_utf8_lossy(&signature)),
+    );
+    section.row(
+        "Hypervisor",
+        identify(cpu_info)
+            .map(|h| h.to_string())
+            .unwrap_or_else(|| "unknown".to_owned()),
+    );
+    section.row("Maximum hypervisor leaf", format!("{:#x}", max_leaf));
+
+    let mut sections = vec![section];
+    match identify(cpu_info) {
+        Some(Hypervisor::HyperV) => sections.extend(hyperv::sections(cpu_info, max_leaf)),
+        Some(Hypervisor::Kvm) => sections.extend(kvm::sections(cpu_info, max_leaf)),
+        Some(Hypervisor::Vmware) => sections.extend(vmware::sections(cpu_info, max_leaf)),
+        Some(Hypervisor::Xen) => sections.extend(xen::sections(cpu_info, max_leaf)),
+        _ => {}
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::{CpuidQuery, CpuidResult};
+
+    /// A CPU with a signature in leaf 0x40000000 and the hypervisor
+    /// bit in leaf 1.
+    struct TestCpu(&'static [u8; 12], bool);
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let dword = |i: usize| {
+                u32::from_le_bytes([self.0[i], self.0[i + 1], self.0[i + 2], self.0[i + 3]])
+            };
+            let result = |eax, ebx, ecx, edx| CpuidResult { eax, ebx, ecx, edx };
+
+            match query.leaf {
+                0 => Some(result(1, 0, 0, 0)),
+                1 => Some(result(0, 0, u32::from(self.1) << 31, 0)),
+                CPUID_HYPERVISOR_BASE => Some(result(0x4000_0001, dword(0), dword(4), dword(8))),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            None
+        }
+    }
+
+    #[test]
+    fn hypervisors_are_identified() {
+        assert_eq!(
+            identify(&TestCpu(b"KVMKVMKVM\0\0\0", true)),
+            Some(Hypervisor::Kvm)
+        );
+        assert_eq!(
+            identify(&TestCpu(b"Microsoft Hv", true)),
+            Some(Hypervisor::HyperV)
+        );
+        assert_eq!(identify(&TestCpu(b"SomethingNew", true)), None);
+    }
+
+    #[test]
+    fn hypervisor_leaf_is_decoded() {
+        let sections = sections(&TestCpu(b"KVMKVMKVM\0\0\0", true));
+        let row = |name: &str| sections[0].get(name).expect("row exists");
+
+        assert_eq!(row("Signature"), "\"KVMKVMKVM\\0\\0\\0\"");
+        assert_eq!(row("Hypervisor"), "KVM");
+        assert_eq!(row("Maximum hypervisor leaf"), "0x40000001");
+    }
+
+    #[test]
+    fn bare_metal_has_no_hypervisor_leaves() {
+        // Intel CPUs return the highest basic leaf for 0x40000000.
+        let cpu = TestCpu(b"GenuineIntel", false);
+
+        assert_eq!(identify(&cpu), None);
+        assert!(sections(&cpu).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! # Decode Key Locker
+//!
+//! Key Locker lets software encrypt with AES keys it never sees in
+//! the clear: LOADIWKEY loads an internal wrapping key (IWKEY), and
+//! the AES Key Locker instructions work on handles wrapped with it.
+//! CPUID leaf 7 ECX bit 23 enumerates Key Locker, CPUID leaf 0x19
+//! describes the supported restrictions and instructions.
+//!
+//! Hypervisors have to intercept LOADIWKEY to manage the IWKEY of each
+//! guest, which the "LOADIWKEY exiting" VM-execution control does.
+//!
+//! See the Intel Key Locker Specification.
+
+use crate::cpu_information::{CpuInformation, CpuidResult};
+use crate::features::{Bit, FeatureStatus};
+use crate::report::Section;
+use crate::vmx::tertiary_control;
+
+/// The CPUID leaf with Key Locker information.
+pub const CPUID_KEY_LOCKER: u32 = 0x19;
+
+/// The bit in CPUID leaf 7 ECX that enumerates Key Locker.
+pub const KEY_LOCKER: Bit = 23;
+
+/// The supported key handle restrictions in EAX.
+const RESTRICTIONS: &[(Bit, &str)] = &[
+    (0, "CPL0-only restriction"),
+    (1, "No-encrypt restriction"),
+    (2, "No-decrypt restriction"),
+];
+
+/// The features in EBX.
+const FEATURES: &[(Bit, &str)] = &[
+    (0, "AESKLE"),
+    (2, "Wide Key Locker instructions"),
+    (4, "IWKEY backup"),
+];
+
+/// The LOADIWKEY parameters in ECX.
+const LOADIWKEY_PARAMETERS: &[(Bit, &str)] =
+    &[(0, "LOADIWKEY NoBackup"), (1, "LOADIWKEY random IWKEY")];
+
+/// Decode CPUID leaf 0x19.
+fn key_locker_section(r: CpuidResult, loadiwkey_exiting: FeatureStatus) -> Section {
+    let mut section = Section::new(&format!("Key Locker (CPUID {:#x})", CPUID_KEY_LOCKER));
+
+    for (bit, name) in FEATURES {
+        section.flag(name, r.ebx.into(), *bit);
+    }
+    for (bit, name) in RESTRICTIONS {
+        section.flag(name, r.eax.into(), *bit);
+    }
+    for (bit, name) in LOADIWKEY_PARAMETERS {
+        section.flag(name, r.ecx.into(), *bit);
+    }
+
+    section.row(
+        "LOADIWKEY exiting",
+        match loadiwkey_exiting {
+            FeatureStatus::Present => "Y",
+            FeatureStatus::Absent => "N",
+            _ => "unknown",
+        },
+    );
+
+    section
+}
+
+/// Decode Key Locker information, if the CPU supports Key Locker.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let supported = cpu_info.is_cpuid_query_valid(7.into())
+        && cpu_info
+            .cpuid(7.into())
+            .is_some_and(|r| (r.ecx >> KEY_LOCKER) & 1 == 1);
+
+    cpu_info
+        .cpuid(CPUID_KEY_LOCKER.into())
+        .filter(|_| supported && cpu_info.is_cpuid_query_valid(CPUID_KEY_LOCKER.into()))
+        .map(|r| key_locker_section(r, tertiary_control(0).status(cpu_info)))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn leaf_is_decoded() {
+        // A Tiger Lake CPU with Key Locker enabled.
+        let section = key_locker_section(
+            CpuidResult {
+                eax: 0x7,
+                ebx: 0x15,
+                ecx: 0x3,
+                edx: 0,
+            },
+            FeatureStatus::Absent,
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("AESKLE"), "Y");
+        assert_eq!(row("Wide Key Locker instructions"), "Y");
+        assert_eq!(row("IWKEY backup"), "Y");
+        assert_eq!(row("CPL0-only restriction"), "Y");
+        assert_eq!(row("LOADIWKEY random IWKEY"), "Y");
+        assert_eq!(row("LOADIWKEY exiting"), "N");
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(RESTRICTIONS);
+        assert_sorted_unique(FEATURES);
+        assert_sorted_unique(LOADIWKEY_PARAMETERS);
+    }
+}
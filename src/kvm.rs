@@ -0,0 +1,96 @@
+//! # Decode KVM Paravirtual Features
+//!
+//! KVM lists its paravirtual features in EAX of CPUID leaf
+//! 0x40000001 and hints for the guest in EDX.
+//!
+//! See `Documentation/virt/kvm/x86/cpuid.rst` in the Linux kernel.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The KVM feature leaf.
+pub const CPUID_KVM_FEATURES: u32 = 0x4000_0001;
+
+/// Paravirtual features in EAX of leaf 0x40000001.
+const FEATURES: &[(Bit, &str)] = &[
+    (0, "kvmclock"),
+    (1, "No I/O delay"),
+    (2, "MMU operations"),
+    (3, "kvmclock (new MSRs)"),
+    (4, "Async page faults"),
+    (5, "Steal time"),
+    (6, "PV EOI"),
+    (7, "PV unhalt"),
+    (9, "PV TLB flush"),
+    (10, "Async page faults as VM exits"),
+    (11, "PV send IPI"),
+    (12, "Poll control"),
+    (13, "PV sched yield"),
+    (14, "Async page faults as interrupts"),
+    (15, "Extended MSI destination ID"),
+    (16, "Map GPA range hypercall"),
+    (17, "Migration control"),
+    (24, "Stable kvmclock"),
+];
+
+/// Hints in EDX of leaf 0x40000001.
+const HINTS: &[(Bit, &str)] = &[(0, "Dedicated physical CPUs")];
+
+/// Decode leaf 0x40000001.
+fn features_section(eax: u32, edx: u32) -> Section {
+    let mut section = Section::new(&format!(
+        "KVM paravirtual features (CPUID {:#x})",
+        CPUID_KVM_FEATURES
+    ));
+
+    for (bit, name) in FEATURES {
+        section.flag(name, eax.into(), *bit);
+    }
+    for (bit, name) in HINTS {
+        section.flag(name, edx.into(), *bit);
+    }
+
+    section
+}
+
+/// Decode the KVM leaves that are available.
+///
+/// The caller must make sure that the hypervisor is KVM.
+pub fn sections(cpu_info: &dyn CpuInformation, max_leaf: u32) -> Vec<Section> {
+    Some(CPUID_KVM_FEATURES)
+        .filter(|l| *l <= max_leaf)
+        .and_then(|l| cpu_info.cpuid(l.into()))
+        .map(|r| features_section(r.eax, r.edx))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn features_are_decoded() {
+        // A typical QEMU/KVM guest.
+        let section = features_section(0x0100_7afb, 0);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("kvmclock"), "Y");
+        assert_eq!(row("MMU operations"), "N");
+        assert_eq!(row("Async page faults"), "Y");
+        assert_eq!(row("PV EOI"), "Y");
+        assert_eq!(row("PV TLB flush"), "Y");
+        assert_eq!(row("PV sched yield"), "Y");
+        assert_eq!(row("Async page faults as interrupts"), "Y");
+        assert_eq!(row("Stable kvmclock"), "Y");
+        assert_eq!(row("Dedicated physical CPUs"), "N");
+    }
+
+    #[test]
+    fn flags_are_sorted_and_unique() {
+        assert_sorted_unique(FEATURES);
+        assert_sorted_unique(HINTS);
+    }
+}
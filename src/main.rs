@@ -3,49 +3,433 @@ extern crate lazy_static;
 
 use aida_parse::AidaCpuidDump;
 use cpu_information::CpuInformation;
-use features::Feature;
+use feature_file::{load_features, merge_features};
+use features::{Feature, FeatureStatus};
 use std::error;
 use std::io;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+mod advice;
 mod aida_parse;
+mod amx;
+mod audit;
+mod avx10;
+mod avx512;
+mod baseline;
+mod cache;
+mod catalog;
+mod compare;
+mod consistency;
 mod cpu_information;
+mod diff;
+mod expression_parse;
+mod feature_file;
 mod features;
+mod frequency;
+mod hybrid;
+mod hyperv;
+mod hypervisor;
+mod key_locker;
+mod kvm;
+mod mca;
+mod microarchitecture;
+mod migrate;
+mod msr;
+mod mtrr;
+mod mwait;
+mod packages;
+mod pmu;
+mod policy;
+mod power;
+mod processor_trace;
+mod profile;
+mod rdt;
+mod report;
+mod score;
+mod sev;
+mod sgx;
+mod speculation;
+mod structured_features;
+mod svm;
+#[cfg(test)]
+mod test_support;
+mod topology;
+mod vmware;
+mod vmx;
+mod vulnerabilities;
+mod xen;
+mod xsave;
 
 type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
 
-fn tristate_to_char(tristate: Option<bool>) -> char {
-    match tristate {
-        Some(b) => {
-            if b {
-                'Y'
-            } else {
-                'N'
+fn status_to_char(status: &FeatureStatus) -> char {
+    match status {
+        FeatureStatus::Present => 'Y',
+        FeatureStatus::Absent => 'N',
+        FeatureStatus::Unknown { .. } => '?',
+        FeatureStatus::NotApplicable => '-',
+    }
+}
+
+/// A one-line summary of the results in a category.
+fn category_summary(category: &str, statuses: &[FeatureStatus]) -> String {
+    let count = |wanted: fn(&FeatureStatus) -> bool| statuses.iter().filter(|s| wanted(s)).count();
+
+    let applicable = statuses.len() - count(|s| *s == FeatureStatus::NotApplicable);
+    if applicable == 0 {
+        return format!("{} (not applicable)", category);
+    }
+
+    let mut summary = format!(
+        "{} ({} of {} present",
+        category,
+        count(|s| *s == FeatureStatus::Present),
+        applicable
+    );
+
+    let unknown = count(|s| matches!(s, FeatureStatus::Unknown { .. }));
+    if unknown > 0 {
+        summary += &format!(", {} unknown", unknown);
+    }
+    summary + ")"
+}
+
+/// Print the result line for a single feature.
+///
+/// Features that are moot because a prerequisite is absent are
+/// marked as such. With `explain`, this also prints the feature's
+/// expression, the results of its prerequisites and, for undecided
+/// features, what blocks the decision. Names are padded to `width`.
+fn print_feature(
+    feature: &Feature,
+    status: &FeatureStatus,
+    catalog: &[Feature],
+    cpu_info: &dyn CpuInformation,
+    explain: bool,
+    width: usize,
+) {
+    print!(
+        "  {:width$}: {}",
+        feature.name,
+        status_to_char(status),
+        width = width
+    );
+    if let FeatureStatus::Unknown { missing } = status {
+        print!(" ({})", cpu_information::missing_data(missing));
+    }
+
+    let absent = catalog::absent_prerequisites(feature, catalog, cpu_info);
+    if !absent.is_empty() {
+        print!(" (moot, requires {})", absent.join(", "));
+    }
+    println!();
+
+    if explain {
+        println!("  {:width$}  {}", "", feature.expression(), width = width);
+
+        for prerequisite in catalog
+            .iter()
+            .filter(|f| feature.depends_on.contains(&f.name))
+        {
+            println!(
+                "  {:width$}  requires {}: {}",
+                "",
+                prerequisite.name,
+                status_to_char(&prerequisite.is_present(cpu_info)),
+                width = width
+            );
+        }
+
+        if let FeatureStatus::Unknown { .. } = status {
+            println!(
+                "  {:width$}  blocked on: {}",
+                "",
+                feature.expression().simplify(cpu_info),
+                width = width
+            );
+        }
+    }
+}
+
+/// A subcommand that works on dumps from files.
+#[derive(Debug)]
+enum Command {
+    /// Compare two dumps.
+    Diff(PathBuf, PathBuf),
+
+    /// Check what guests lose when migrating from the first host to
+    /// the second.
+    MigrateCheck(PathBuf, PathBuf),
+
+    /// Compute the common baseline of the dumps in a directory.
+    Baseline(PathBuf),
+}
+
+/// The names of the subcommands.
+const SUBCOMMANDS: &[&str] = &["diff", "migrate-check", "baseline"];
+
+/// The options that only apply to the report on a dump from stdin.
+const REPORT_OPTIONS: &[&str] = &[
+    "--explain",
+    "--decode",
+    "--vulnerabilities",
+    "--audit",
+    "--host",
+    "--advise",
+    "--score",
+    "--xsave-size",
+    "--policy",
+    "--profile",
+];
+
+/// Command line options.
+#[derive(Debug, Default)]
+struct Options {
+    /// Feature definition files that are merged into the built-in
+    /// catalog.
+    features_files: Vec<PathBuf>,
+
+    /// Show the expression behind each feature.
+    explain: bool,
+
+    /// Show decoded virtualization capabilities after the feature
+    /// list.
+    decode: bool,
+
+    /// Estimate the exposure to speculative execution
+    /// vulnerabilities.
+    vulnerabilities: bool,
+
+    /// A dump of the host. With this, the dump on stdin is treated
+    /// as coming from a guest on this host.
+    host: Option<PathBuf>,
+
+    /// Recommend what a hypervisor on this host should enable.
+    advise: bool,
+
+    /// Check reserved bits in the dump.
+    audit: bool,
+
+    /// Compute a virtualization score and grade.
+    score: bool,
+
+    /// Compute the XSAVE area size for this feature mask.
+    xsave_size: Option<u64>,
+
+    /// Policies to check the host against.
+    policies: Vec<PathBuf>,
+
+    /// Readiness profiles to check after the feature list.
+    profiles: Vec<&'static profile::Profile>,
+
+    /// A subcommand to run instead of reading a dump from stdin.
+    command: Option<Command>,
+
+    /// Print the result of the subcommand as JSON.
+    json: bool,
+}
+
+fn parse_args() -> Result<Options> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1).peekable();
+
+    if let Some(command) = args.next_if(|arg| SUBCOMMANDS.contains(&arg.as_str())) {
+        let mut path = |what: &str| {
+            args.next()
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("{} requires {}", command, what))
+        };
+
+        options.command = Some(match command.as_str() {
+            "diff" => Command::Diff(path("two dumps")?, path("two dumps")?),
+            "migrate-check" => Command::MigrateCheck(path("two dumps")?, path("two dumps")?),
+            _ => Command::Baseline(path("a directory")?),
+        });
+    }
+
+    while let Some(arg) = args.next() {
+        if options.command.is_some() && REPORT_OPTIONS.contains(&arg.as_str()) {
+            return Err(format!("{} can't be used with a subcommand", arg).into());
+        }
+
+        match arg.as_str() {
+            "--features-file" => options
+                .features_files
+                .push(args.next().ok_or("--features-file requires a path")?.into()),
+            "--explain" => options.explain = true,
+            "--decode" => options.decode = true,
+            "--vulnerabilities" => options.vulnerabilities = true,
+            "--audit" => options.audit = true,
+            "--host" => options.host = Some(args.next().ok_or("--host requires a path")?.into()),
+            "--advise" => options.advise = true,
+            "--score" => options.score = true,
+            "--json" => options.json = true,
+            "--xsave-size" => {
+                let mask = args.next().ok_or("--xsave-size requires a mask")?;
+
+                options.xsave_size = Some(
+                    u64::from_str_radix(mask.trim_start_matches("0x"), 16)
+                        .map_err(|_| format!("Invalid XSAVE feature mask: {}", mask))?,
+                )
             }
+            "--policy" => options
+                .policies
+                .push(args.next().ok_or("--policy requires a path")?.into()),
+            "--profile" => {
+                let name = args.next().ok_or("--profile requires a name")?;
+                let known = profile::PROFILES
+                    .iter()
+                    .map(|p| p.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                options.profiles.push(profile::find(&name).ok_or_else(|| {
+                    format!("Unknown profile: {} (known profiles: {})", name, known)
+                })?)
+            }
+            _ => return Err(format!("Unknown argument: {}", arg).into()),
         }
-        None => '?',
     }
+
+    if options.json && options.command.is_none() {
+        return Err(format!("--json requires one of {}", SUBCOMMANDS.join(", ")).into());
+    }
+
+    Ok(options)
 }
 
-fn features() -> Vec<Feature> {
-    use cpu_information::CpuidRegister::*;
-    use features::BoolExpression::*;
-    vec![
-        Feature::new("AVX", CpuidBitSet(1.into(), Ecx, 28)),
-        Feature::new("MMX", CpuidBitSet(1.into(), Edx, 23)),
-        Feature::new("SHA", CpuidBitSet(7.into(), Ebx, 29)),
-        Feature::new("ENCLV", CpuidBitSet(0x12.into(), Eax, 5)),
-        Feature::new("EPT", MsrBitSet(0x48b, 32 + 1)),
-        Feature::new("Unrestricted Guest", MsrBitSet(0x48b, 32 + 7)),
-        Feature::new("VMCS Shadowing", MsrBitSet(0x48b, 46)),
-        Feature::new("APIC-register virtualization", MsrBitSet(0x48b, 40)),
-        Feature::new("Virtual-interrupt delivery", MsrBitSet(0x48b, 41)),
-        Feature::new("VMX preemption timer", MsrBitSet(0x48b, 32 + 6)),
-        Feature::new("Process posted interrupts", MsrBitSet(0x48b, 32 + 7)),
-    ]
+/// Read and parse a dump from a file.
+fn load_dump(path: &Path) -> Result<AidaCpuidDump> {
+    let input = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read dump {}: {}", path.display(), e))?;
+
+    Ok(AidaCpuidDump::from_str(&input)?)
+}
+
+/// Compare two dumps and return whether they are the same.
+fn print_diff(catalog: &[Feature], a: &Path, b: &Path, json: bool) -> Result<bool> {
+    let diff = diff::Diff::new(catalog, &load_dump(a)?, &load_dump(b)?);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else if diff.is_empty() {
+        println!("The dumps do not differ.");
+    } else {
+        for section in diff.sections(&a.display().to_string(), &b.display().to_string()) {
+            println!("{}", section);
+        }
+    }
+
+    Ok(diff.is_empty())
 }
-fn main() -> Result<()> {
+
+/// Compute the common baseline of all dumps in a directory.
+fn print_baseline(catalog: &[Feature], directory: &Path, json: bool) -> Result<()> {
+    let mut paths = std::fs::read_dir(directory)
+        .map_err(|e| format!("Failed to read directory {}: {}", directory.display(), e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+
+    let dumps = paths
+        .iter()
+        .map(|path| load_dump(path))
+        .collect::<Result<Vec<_>>>()?;
+    let hosts: Vec<(String, &dyn CpuInformation)> = paths
+        .iter()
+        .zip(&dumps)
+        .map(|(path, dump)| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+            (name.into_owned(), dump as &dyn CpuInformation)
+        })
+        .collect();
+
+    let vendor = match hosts.first() {
+        Some((_, host)) => host.vendor(),
+        None => return Err(format!("No dumps in {}", directory.display()).into()),
+    };
+    if let Some((name, _)) = hosts.iter().find(|(_, host)| host.vendor() != vendor) {
+        return Err(format!("{} has a different CPU vendor than the other dumps", name).into());
+    }
+
+    let baseline = baseline::Baseline::new(catalog, &hosts);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&baseline)?);
+    } else {
+        for section in baseline.sections() {
+            println!("{}", section);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check a live migration and return whether it is safe for guests.
+fn print_migrate_check(
+    catalog: &[Feature],
+    source: &Path,
+    destination: &Path,
+    json: bool,
+) -> Result<bool> {
+    let check =
+        migrate::MigrationCheck::new(catalog, &load_dump(source)?, &load_dump(destination)?);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&check)?);
+    } else {
+        println!(
+            "{}",
+            check.section(
+                &source.display().to_string(),
+                &destination.display().to_string()
+            )
+        );
+    }
+
+    Ok(check.is_safe())
+}
+
+/// Load the built-in catalog and the feature files.
+fn load_catalog(options: &Options) -> Result<Vec<Feature>> {
+    let mut catalog = catalog::features();
+    for path in &options.features_files {
+        merge_features(&mut catalog, load_features(path)?);
+    }
+
+    Ok(catalog)
+}
+
+/// Run a subcommand and return whether its check passed.
+fn run_subcommand(options: &Options) -> Result<bool> {
+    let catalog = load_catalog(options)?;
+
+    match &options.command {
+        Some(Command::Diff(a, b)) => print_diff(&catalog, a, b, options.json),
+        Some(Command::MigrateCheck(source, destination)) => {
+            print_migrate_check(&catalog, source, destination, options.json)
+        }
+        Some(Command::Baseline(directory)) => {
+            print_baseline(&catalog, directory, options.json).map(|_| true)
+        }
+        None => Err("No subcommand given".into()),
+    }
+}
+
+/// Print the report on the dump from stdin and return whether the
+/// CPU complies with all policies.
+fn report(options: &Options) -> Result<bool> {
+    let catalog = load_catalog(options)?;
+
+    let policies = options
+        .policies
+        .iter()
+        .map(|path| policy::load_policy(path))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
     let mut input_bytes = Vec::new();
     io::stdin().read_to_end(&mut input_bytes)?;
 
@@ -56,18 +440,168 @@ fn main() -> Result<()> {
     let unknown = "Unknown".to_owned();
 
     println!(
-        "{} {}\n",
+        "{} {}",
         aida_result.vendor_name().unwrap_or_else(|| unknown.clone()),
         aida_result.model_name().unwrap_or(unknown),
     );
+    if let Some(description) = microarchitecture::describe(&aida_result) {
+        println!("{}", description);
+    }
+    if let Some(summary) = topology::summary(&aida_result) {
+        println!("{}", summary);
+    }
+    let frequencies = frequency::frequencies(&aida_result);
+    if !frequencies.is_empty() {
+        println!("{}", frequencies);
+    }
+    if let Some(revision) = aida_result.microcode_revision() {
+        println!("Microcode revision {:#x}", revision);
+    }
+    if hypervisor::is_present(&aida_result) {
+        let hypervisor = hypervisor::identify(&aida_result)
+            .map(|h| format!(" ({})", h))
+            .unwrap_or_default();
 
-    for feature in features().into_iter() {
         println!(
-            "{:30}: {}",
-            feature.name,
-            tristate_to_char(feature.is_present(&aida_result)),
+            "Warning: This dump was taken inside a virtual machine{}. MSR values and VMX\n\
+             capabilities show what the hypervisor emulates, not the bare-metal CPU.",
+            hypervisor
         );
     }
+    for warning in consistency::warnings(&aida_result)
+        .into_iter()
+        .chain(packages::warnings(&aida_result))
+    {
+        println!("Warning: {}", warning);
+    }
+    println!();
 
-    Ok(())
+    let width = catalog.iter().map(|f| f.name.len()).max().unwrap_or(0);
+    for (category, features) in catalog::by_category(&catalog) {
+        let statuses: Vec<FeatureStatus> = features
+            .iter()
+            .map(|f| f.is_present(&aida_result))
+            .collect();
+
+        println!("{}", category_summary(category, &statuses));
+
+        for (feature, status) in features.iter().zip(statuses.iter()) {
+            print_feature(
+                feature,
+                status,
+                &catalog,
+                &aida_result,
+                options.explain,
+                width,
+            );
+        }
+
+        println!();
+    }
+
+    for profile in &options.profiles {
+        println!("{}", profile::section(profile, &catalog, &aida_result));
+    }
+
+    let mut compliant = true;
+    for policy in &policies {
+        let compliance = policy::check(policy, &catalog, &aida_result);
+
+        println!("{}", compliance.section());
+        compliant &= compliance.is_compliant();
+    }
+
+    if options.score {
+        // The last policy with a score section overrides the built-in
+        // weights.
+        let scoring = policies
+            .iter()
+            .rev()
+            .find_map(|p| p.score.clone())
+            .unwrap_or_default();
+
+        println!("{}", scoring.section(&catalog, &aida_result));
+    }
+
+    if let Some(mask) = options.xsave_size {
+        println!("{}", xsave::size_section(&aida_result, mask));
+    }
+
+    if let Some(path) = &options.host {
+        let host = load_dump(path)?;
+        println!("{}", compare::section(&catalog, &host, &aida_result));
+    }
+
+    if options.advise {
+        println!("{}", advice::section(&catalog, &aida_result));
+    }
+
+    if options.vulnerabilities {
+        match vulnerabilities::section(&aida_result) {
+            Some(section) => println!("{}", section),
+            None => println!("Vulnerabilities: unknown CPU vendor\n"),
+        }
+    }
+
+    if options.audit {
+        let section = audit::section(&aida_result);
+
+        if section.rows.is_empty() {
+            println!("{}\n  none\n", section.title);
+        } else {
+            println!("{}", section);
+        }
+    }
+
+    if options.decode {
+        for section in vmx::sections(&aida_result)
+            .into_iter()
+            .chain(structured_features::sections(&aida_result))
+            .chain(msr::sections(&aida_result))
+            .chain(mtrr::sections(&aida_result))
+            .chain(mca::sections(&aida_result))
+            .chain(topology::sections(&aida_result))
+            .chain(hybrid::section(&aida_result))
+            .chain(packages::section(&aida_result))
+            .chain(cache::sections(&aida_result))
+            .chain(xsave::sections(&aida_result))
+            .chain(mwait::sections(&aida_result))
+            .chain(svm::sections(&aida_result))
+            .chain(sev::sections(&aida_result))
+            .chain(speculation::sections(&aida_result))
+            .chain(frequency::sections(&aida_result))
+            .chain(power::sections(&aida_result))
+            .chain(processor_trace::sections(&aida_result))
+            .chain(pmu::sections(&aida_result))
+            .chain(sgx::sections(&aida_result))
+            .chain(rdt::sections(&aida_result))
+            .chain(amx::sections(&aida_result))
+            .chain(avx512::sections(&aida_result))
+            .chain(avx10::sections(&aida_result))
+            .chain(key_locker::sections(&aida_result))
+            .chain(hypervisor::sections(&aida_result))
+        {
+            println!("{}", section);
+        }
+    }
+
+    Ok(compliant)
+}
+
+fn main() {
+    let result = parse_args().and_then(|options| match options.command {
+        Some(_) => run_subcommand(&options),
+        None => report(&options),
+    });
+
+    // Like diff(1), exit with 1 if a check fails and with 2 on errors,
+    // so that scripts can tell the two apart.
+    std::process::exit(match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            2
+        }
+    });
 }
@@ -0,0 +1,161 @@
+//! # Decode the Machine-Check Architecture
+//!
+//! IA32_MCG_CAP enumerates the number of error-reporting banks and
+//! the optional parts of the machine-check architecture, such as
+//! corrected machine-check interrupts (CMCI) and software error
+//! recovery. Each bank has a control MSR that selects which errors it
+//! reports and a status MSR that holds the last error it logged. On
+//! CPUs with CMCI, a second control MSR enables the interrupt for
+//! corrected errors.
+//!
+//! See the Intel SDM Vol. 3B, Chapter 16 and the AMD APM Vol. 2,
+//! Chapter 9.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::Bit;
+use crate::msr;
+use crate::report::Section;
+
+/// The machine-check capabilities.
+pub const IA32_MCG_CAP: u32 = 0x179;
+
+/// The IA32_MCG_CAP bit that enumerates CMCI.
+pub const MCG_CMCI_P: Bit = 10;
+
+/// The control MSR of bank 0. Each bank has four MSRs: control,
+/// status, address and miscellaneous information.
+pub const IA32_MC0_CTL: u32 = 0x400;
+
+/// The CMCI control MSR of bank 0.
+pub const IA32_MC0_CTL2: u32 = 0x280;
+
+/// The MSRs of one bank that are in the dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Bank {
+    ctl: Option<u64>,
+    status: Option<u64>,
+    ctl2: Option<u64>,
+}
+
+impl Bank {
+    fn read(cpu_info: &dyn CpuInformation, bank: u32) -> Bank {
+        Bank {
+            ctl: cpu_info.rdmsr(IA32_MC0_CTL + 4 * bank),
+            status: cpu_info.rdmsr(IA32_MC0_CTL + 4 * bank + 1),
+            ctl2: cpu_info.rdmsr(IA32_MC0_CTL2 + bank),
+        }
+    }
+
+    /// A one-line summary, or `None` if the dump has none of the
+    /// bank's MSRs.
+    fn summary(&self) -> Option<String> {
+        if *self == Bank::default() {
+            return None;
+        }
+
+        let mut parts = vec![match self.ctl {
+            Some(u64::MAX) => "all errors enabled".to_owned(),
+            Some(0) => "disabled".to_owned(),
+            Some(ctl) => format!("errors {:#x} enabled", ctl),
+            None => "control unknown".to_owned(),
+        }];
+
+        // CMCI_EN and the corrected error threshold.
+        if let Some(ctl2) = self.ctl2.filter(|c| c & (1 << 30) != 0) {
+            parts.push(format!("CMCI threshold {}", ctl2 & 0x7fff));
+        }
+
+        // The VAL bit of the status MSR.
+        if self.status.is_some_and(|s| s & (1 << 63) != 0) {
+            parts.push("error logged".to_owned());
+        }
+
+        Some(parts.join(", "))
+    }
+}
+
+/// Summarize the banks that are in the dump.
+fn banks_section(banks: &[Bank]) -> Section {
+    let mut section = Section::new(&format!("Machine-check banks (MSR {:#x})", IA32_MC0_CTL));
+
+    for (i, bank) in banks.iter().enumerate() {
+        if let Some(summary) = bank.summary() {
+            section.row(&format!("Bank {}", i), summary);
+        }
+    }
+
+    section
+}
+
+/// Decode the machine-check MSRs that are in the dump.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let capabilities = match cpu_info.rdmsr(IA32_MCG_CAP) {
+        Some(capabilities) => capabilities,
+        None => return vec![],
+    };
+    let mut sections: Vec<Section> = msr::section(cpu_info, IA32_MCG_CAP).into_iter().collect();
+
+    let banks: Vec<Bank> = (0..(capabilities & 0xff) as u32)
+        .map(|i| Bank::read(cpu_info, i))
+        .collect();
+    let banks_section = banks_section(&banks);
+    if !banks_section.rows.is_empty() {
+        sections.push(banks_section);
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_are_decoded() {
+        // A Skylake server with 20 banks, CMCI, SER and LMCE.
+        let section = msr::find(IA32_MCG_CAP)
+            .expect("registered")
+            .section(0x0f00_0c14);
+
+        assert_eq!(section.get("Banks"), Some("20"));
+        assert_eq!(section.get("CMCI"), Some("Y"));
+        assert_eq!(section.get("Software error recovery"), Some("Y"));
+        assert_eq!(section.get("Local machine-check exceptions"), Some("Y"));
+        assert_eq!(section.get("MCG_CTL"), Some("N"));
+    }
+
+    #[test]
+    fn banks_are_summarized() {
+        let banks = [
+            Bank {
+                ctl: Some(u64::MAX),
+                status: Some(0),
+                ctl2: Some((1 << 30) | 1),
+            },
+            Bank::default(),
+            Bank {
+                ctl: Some(0x3f),
+                status: Some(0x9c00_0000_0000_0135),
+                ctl2: Some(0),
+            },
+            Bank {
+                ctl: Some(0),
+                status: None,
+                ctl2: None,
+            },
+        ];
+
+        let section = banks_section(&banks);
+
+        assert_eq!(
+            section.get("Bank 0"),
+            Some("all errors enabled, CMCI threshold 1")
+        );
+        assert_eq!(section.get("Bank 1"), None);
+        assert_eq!(
+            section.get("Bank 2"),
+            Some("errors 0x3f enabled, error logged")
+        );
+        assert_eq!(section.get("Bank 3"), Some("disabled"));
+    }
+}
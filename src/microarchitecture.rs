@@ -0,0 +1,289 @@
+//! # Identify the Microarchitecture
+//!
+//! CPUID leaf 1 EAX holds the processor signature: family, model and
+//! stepping. The displayed family and model combine the base fields
+//! with the extended fields, as described in the Intel SDM Vol. 2A
+//! (CPUID—CPU Identification) and the AMD APM Vol. 3 (Appendix E).
+//!
+//! The signature alone doesn't say much without knowing which
+//! microarchitecture it belongs to. The table below maps signatures to
+//! codenames. It follows the model lists in Linux
+//! (`arch/x86/include/asm/intel-family.h`) and AMD's revision guides.
+//! Where Intel reused a model number, the stepping tells the
+//! generations apart.
+
+use std::ops::RangeInclusive;
+
+use crate::cpu_information::{CpuInformation, Vendor};
+
+/// The processor signature from CPUID leaf 1 EAX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+impl Signature {
+    /// Decode CPUID leaf 1 EAX into the displayed family and model.
+    pub fn from_eax(eax: u32) -> Self {
+        let base_family = (eax >> 8) & 0xf;
+        let base_model = (eax >> 4) & 0xf;
+
+        let family = if base_family == 0xf {
+            base_family + ((eax >> 20) & 0xff)
+        } else {
+            base_family
+        };
+        let model = if base_family == 0x6 || base_family == 0xf {
+            ((eax >> 16) & 0xf) << 4 | base_model
+        } else {
+            base_model
+        };
+
+        Signature {
+            family,
+            model,
+            stepping: eax & 0xf,
+        }
+    }
+}
+
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Family {:#x}, model {:#x}, stepping {:#x}",
+            self.family, self.model, self.stepping
+        )
+    }
+}
+
+/// A range of signatures that belong to one microarchitecture.
+struct Entry {
+    vendor: Vendor,
+    family: u32,
+    models: RangeInclusive<u32>,
+    steppings: RangeInclusive<u32>,
+    name: &'static str,
+}
+
+const fn intel(model: u32, name: &'static str) -> Entry {
+    intel_steppings(model, 0, 0xf, name)
+}
+
+const fn intel_steppings(model: u32, first: u32, last: u32, name: &'static str) -> Entry {
+    Entry {
+        vendor: Vendor::Intel,
+        family: 0x6,
+        models: model..=model,
+        steppings: first..=last,
+        name,
+    }
+}
+
+const fn amd(family: u32, first: u32, last: u32, name: &'static str) -> Entry {
+    Entry {
+        vendor: Vendor::Amd,
+        family,
+        models: first..=last,
+        steppings: 0..=0xf,
+        name,
+    }
+}
+
+/// The known microarchitectures.
+const MICROARCHITECTURES: &[Entry] = &[
+    intel(0x0f, "Core"),
+    intel(0x16, "Core"),
+    intel(0x17, "Penryn"),
+    intel(0x1d, "Penryn"),
+    intel(0x1a, "Nehalem"),
+    intel(0x1e, "Nehalem"),
+    intel(0x1f, "Nehalem"),
+    intel(0x2e, "Nehalem"),
+    intel(0x25, "Westmere"),
+    intel(0x2c, "Westmere"),
+    intel(0x2f, "Westmere"),
+    intel(0x2a, "Sandy Bridge"),
+    intel(0x2d, "Sandy Bridge"),
+    intel(0x3a, "Ivy Bridge"),
+    intel(0x3e, "Ivy Bridge"),
+    intel(0x3c, "Haswell"),
+    intel(0x3f, "Haswell"),
+    intel(0x45, "Haswell"),
+    intel(0x46, "Haswell"),
+    intel(0x3d, "Broadwell"),
+    intel(0x47, "Broadwell"),
+    intel(0x4f, "Broadwell"),
+    intel(0x56, "Broadwell"),
+    intel(0x4e, "Skylake"),
+    intel(0x5e, "Skylake"),
+    intel_steppings(0x55, 0x0, 0x4, "Skylake"),
+    intel_steppings(0x55, 0x5, 0x7, "Cascade Lake"),
+    intel_steppings(0x55, 0xa, 0xb, "Cooper Lake"),
+    intel_steppings(0x8e, 0x9, 0x9, "Kaby Lake"),
+    intel_steppings(0x8e, 0xa, 0xa, "Coffee Lake"),
+    intel_steppings(0x8e, 0xb, 0xc, "Whiskey Lake"),
+    intel_steppings(0x9e, 0x9, 0x9, "Kaby Lake"),
+    intel_steppings(0x9e, 0xa, 0xd, "Coffee Lake"),
+    intel(0xa5, "Comet Lake"),
+    intel(0xa6, "Comet Lake"),
+    intel(0x66, "Cannon Lake"),
+    intel(0x7d, "Ice Lake"),
+    intel(0x7e, "Ice Lake"),
+    intel(0x6a, "Ice Lake"),
+    intel(0x6c, "Ice Lake"),
+    intel(0x8c, "Tiger Lake"),
+    intel(0x8d, "Tiger Lake"),
+    intel(0xa7, "Rocket Lake"),
+    intel(0x97, "Alder Lake"),
+    intel(0x9a, "Alder Lake"),
+    intel(0xb7, "Raptor Lake"),
+    intel(0xba, "Raptor Lake"),
+    intel(0xbf, "Raptor Lake"),
+    intel(0x8f, "Sapphire Rapids"),
+    intel(0xcf, "Emerald Rapids"),
+    intel(0xaa, "Meteor Lake"),
+    intel(0xac, "Meteor Lake"),
+    intel(0xbd, "Lunar Lake"),
+    intel(0xc5, "Arrow Lake"),
+    intel(0xc6, "Arrow Lake"),
+    intel(0xad, "Granite Rapids"),
+    intel(0xae, "Granite Rapids"),
+    intel(0xaf, "Sierra Forest"),
+    intel(0x37, "Silvermont"),
+    intel(0x4d, "Silvermont"),
+    intel(0x4c, "Airmont"),
+    intel(0x5c, "Goldmont"),
+    intel(0x5f, "Goldmont"),
+    intel(0x7a, "Goldmont Plus"),
+    intel(0x86, "Tremont"),
+    intel(0x96, "Tremont"),
+    intel(0x9c, "Tremont"),
+    intel(0xbe, "Gracemont"),
+    intel(0x57, "Knights Landing"),
+    intel(0x85, "Knights Mill"),
+    amd(0x10, 0x00, 0xff, "K10"),
+    amd(0x15, 0x00, 0x01, "Bulldozer"),
+    amd(0x15, 0x02, 0x1f, "Piledriver"),
+    amd(0x15, 0x30, 0x3f, "Steamroller"),
+    amd(0x15, 0x60, 0x7f, "Excavator"),
+    amd(0x16, 0x00, 0x0f, "Jaguar"),
+    amd(0x16, 0x30, 0x3f, "Puma"),
+    amd(0x17, 0x00, 0x07, "Zen"),
+    amd(0x17, 0x08, 0x0f, "Zen+"),
+    amd(0x17, 0x10, 0x17, "Zen"),
+    amd(0x17, 0x18, 0x1f, "Zen+"),
+    amd(0x17, 0x20, 0x2f, "Zen"),
+    amd(0x17, 0x30, 0xff, "Zen 2"),
+    amd(0x19, 0x00, 0x0f, "Zen 3"),
+    amd(0x19, 0x10, 0x1f, "Zen 4"),
+    amd(0x19, 0x20, 0x5f, "Zen 3"),
+    amd(0x19, 0x60, 0xaf, "Zen 4"),
+    amd(0x1a, 0x00, 0xff, "Zen 5"),
+];
+
+/// The processor signature of a CPU.
+pub fn signature(cpu_info: &dyn CpuInformation) -> Option<Signature> {
+    cpu_info
+        .cpuid(1.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(1.into()))
+        .map(|r| Signature::from_eax(r.eax))
+}
+
+/// Look up the microarchitecture of a signature.
+pub fn lookup(vendor: Vendor, signature: Signature) -> Option<&'static str> {
+    MICROARCHITECTURES
+        .iter()
+        .find(|e| {
+            e.vendor == vendor
+                && e.family == signature.family
+                && e.models.contains(&signature.model)
+                && e.steppings.contains(&signature.stepping)
+        })
+        .map(|e| e.name)
+}
+
+/// The signature of a CPU and, if known, its microarchitecture.
+pub fn describe(cpu_info: &dyn CpuInformation) -> Option<String> {
+    let signature = signature(cpu_info)?;
+
+    Some(match cpu_info.vendor().and_then(|v| lookup(v, signature)) {
+        Some(name) => format!("{} ({})", signature, name),
+        None => signature.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_are_decoded() {
+        assert_eq!(
+            Signature::from_eax(0x0009_06ed),
+            Signature {
+                family: 0x6,
+                model: 0x9e,
+                stepping: 0xd,
+            }
+        );
+        assert_eq!(
+            Signature::from_eax(0x00a6_0f12),
+            Signature {
+                family: 0x19,
+                model: 0x61,
+                stepping: 0x2,
+            }
+        );
+        // The extended model only applies to families 6 and 15.
+        assert_eq!(
+            Signature::from_eax(0x0001_0561),
+            Signature {
+                family: 0x5,
+                model: 0x6,
+                stepping: 0x1,
+            }
+        );
+    }
+
+    #[test]
+    fn microarchitectures_are_found() {
+        let intel = |eax| lookup(Vendor::Intel, Signature::from_eax(eax));
+        let amd = |eax| lookup(Vendor::Amd, Signature::from_eax(eax));
+
+        assert_eq!(intel(0x0009_06ed), Some("Coffee Lake"));
+        assert_eq!(intel(0x0009_06e9), Some("Kaby Lake"));
+        assert_eq!(intel(0x0005_0654), Some("Skylake"));
+        assert_eq!(intel(0x0005_0657), Some("Cascade Lake"));
+        assert_eq!(intel(0x0008_06f8), Some("Sapphire Rapids"));
+        assert_eq!(amd(0x0080_0f82), Some("Zen+"));
+        assert_eq!(amd(0x0083_0f10), Some("Zen 2"));
+        assert_eq!(amd(0x00a2_0f10), Some("Zen 3"));
+        assert_eq!(amd(0x00a6_0f12), Some("Zen 4"));
+        assert_eq!(amd(0x0009_06ed), None);
+        assert_eq!(intel(0x0000_0fff), None);
+    }
+
+    #[test]
+    fn entries_do_not_overlap() {
+        let overlaps = |a: &RangeInclusive<u32>, b: &RangeInclusive<u32>| {
+            a.start() <= b.end() && b.start() <= a.end()
+        };
+
+        for (i, a) in MICROARCHITECTURES.iter().enumerate() {
+            for b in &MICROARCHITECTURES[i + 1..] {
+                assert!(
+                    a.vendor != b.vendor
+                        || a.family != b.family
+                        || !overlaps(&a.models, &b.models)
+                        || !overlaps(&a.steppings, &b.steppings),
+                    "{} overlaps {}",
+                    a.name,
+                    b.name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! # Check Live-Migration Compatibility
+//!
+//! A guest that is live-migrated keeps the CPU model it booted with.
+//! If the destination host lacks a feature that the guest already
+//! uses, the guest faults or silently misbehaves after migration. This
+//! compares a source and a destination host and lists the features
+//! that would be lost, together with whom the loss affects:
+//!
+//! - Features that guests see in CPUID, such as AVX-512 or CET, can't
+//!   be taken away from a running guest.
+//! - VMX, EPT and SVM capabilities only matter to guests that run
+//!   their own hypervisor. Narrower EPT capabilities, e.g. no 1 GB
+//!   pages, break such nested guests.
+//! - Interrupt and time virtualization are used by the hypervisor on
+//!   the host. Losing them costs performance but is transparent to
+//!   guests, except for TSC scaling if the TSC frequencies differ.
+//!
+//! Features are only compared if they apply to the hosts' vendor.
+//! Restrictions, such as TSX being disabled or IA32_FEATURE_CONTROL
+//! being locked, are not compared, because losing them takes nothing
+//! away. Migrating between vendors is never safe.
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::catalog::{
+    EPT, FIRMWARE, INTERRUPT_VIRTUALIZATION, SVM, TIME_VIRTUALIZATION, VMX_CONTROLS, VM_ENTRY_EXIT,
+};
+use crate::cpu_information::{missing_data, CpuInformation, Vendor};
+use crate::features::{BoolExpression, Feature, FeatureStatus};
+use crate::frequency;
+use crate::report::Section;
+use crate::svm::svm_feature;
+use crate::vmx::secondary_control;
+use crate::xsave;
+
+/// Whom losing a feature affects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Impact {
+    /// Guests see the feature and may use it.
+    Guest,
+
+    /// Guests that run a hypervisor themselves may use the feature.
+    Nested,
+
+    /// Only the hypervisor on the host uses the feature.
+    Hypervisor,
+}
+
+impl fmt::Display for Impact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Impact::Guest => "lost for guests",
+            Impact::Nested => "lost for nested guests",
+            Impact::Hypervisor => "lost for the hypervisor",
+        })
+    }
+}
+
+/// The categories whose features guests don't see directly. All other
+/// categories, including those of custom features, are assumed to be
+/// visible to guests.
+const CATEGORY_IMPACTS: &[(&str, Impact)] = &[
+    (FIRMWARE, Impact::Hypervisor),
+    (VMX_CONTROLS, Impact::Nested),
+    (EPT, Impact::Nested),
+    (VM_ENTRY_EXIT, Impact::Nested),
+    (SVM, Impact::Nested),
+    (INTERRUPT_VIRTUALIZATION, Impact::Hypervisor),
+    (TIME_VIRTUALIZATION, Impact::Hypervisor),
+];
+
+/// Whom losing a feature of a category affects.
+fn category_impact(category: &str) -> Impact {
+    CATEGORY_IMPACTS
+        .iter()
+        .find(|(c, _)| *c == category)
+        .map_or(Impact::Guest, |(_, impact)| *impact)
+}
+
+/// Something the source host has and the destination host doesn't.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Loss {
+    pub category: String,
+    pub name: String,
+    pub impact: Impact,
+
+    /// The values on both hosts, if the loss is not a single feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Loss {
+    fn new(category: &str, name: &str, impact: Impact, detail: Option<String>) -> Loss {
+        Loss {
+            category: category.to_owned(),
+            name: name.to_owned(),
+            impact,
+            detail,
+        }
+    }
+}
+
+/// The result of comparing a source and a destination host.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct MigrationCheck {
+    pub losses: Vec<Loss>,
+
+    /// Features the source host has that can't be decided for the
+    /// destination, with the missing data as detail. They may be lost.
+    pub undecided: Vec<Loss>,
+}
+
+/// The expression for TSC scaling on a vendor's CPUs.
+fn tsc_scaling(vendor: Vendor) -> BoolExpression {
+    match vendor {
+        Vendor::Intel => secondary_control(25),
+        Vendor::Amd => svm_feature(4),
+    }
+}
+
+impl MigrationCheck {
+    /// Find what guests would lose when migrating from `source` to
+    /// `destination`.
+    pub fn new(
+        catalog: &[Feature],
+        source: &dyn CpuInformation,
+        destination: &dyn CpuInformation,
+    ) -> MigrationCheck {
+        let (source_vendor, destination_vendor) = (source.vendor(), destination.vendor());
+        if source_vendor != destination_vendor {
+            let name = |v: Option<Vendor>| v.map_or("unknown".to_owned(), |v| v.to_string());
+
+            return MigrationCheck {
+                undecided: vec![],
+                losses: vec![Loss::new(
+                    "Vendor",
+                    "CPU vendor",
+                    Impact::Guest,
+                    Some(format!(
+                        "{} -> {}",
+                        name(source_vendor),
+                        name(destination_vendor)
+                    )),
+                )],
+            };
+        }
+
+        let mut losses = vec![];
+        let mut undecided = vec![];
+        for feature in catalog.iter().filter(|f| !f.restriction) {
+            if feature.is_present(source) != FeatureStatus::Present {
+                continue;
+            }
+
+            let impact = category_impact(&feature.category);
+            match feature.is_present(destination) {
+                FeatureStatus::Absent => {
+                    losses.push(Loss::new(&feature.category, &feature.name, impact, None))
+                }
+                FeatureStatus::Unknown { missing } => undecided.push(Loss::new(
+                    &feature.category,
+                    &feature.name,
+                    impact,
+                    Some(missing_data(&missing)),
+                )),
+                _ => (),
+            }
+        }
+
+        // Guests can't use more state components than the destination
+        // supports. Most of them also show up as lost features, but
+        // the mask covers the ones the catalog doesn't know.
+        if let (Some(source_mask), Some(destination_mask)) = (
+            xsave::supported_mask(source),
+            xsave::supported_mask(destination),
+        ) {
+            let lost = source_mask & !destination_mask;
+
+            for index in (0..u64::BITS).filter(|i| lost & (1 << i) != 0) {
+                losses.push(Loss::new(
+                    "XSAVE",
+                    &xsave::component_name(index),
+                    Impact::Guest,
+                    None,
+                ));
+            }
+        }
+
+        // Guest page tables may point beyond the destination's
+        // physical address width.
+        if let (Some(source_bits), Some(destination_bits)) = (
+            source.physical_address_bits(),
+            destination.physical_address_bits(),
+        ) {
+            if destination_bits < source_bits {
+                losses.push(Loss::new(
+                    "Address width",
+                    "Physical address bits",
+                    Impact::Guest,
+                    Some(format!("{} -> {}", source_bits, destination_bits)),
+                ));
+            }
+        }
+
+        // Without scaling, the guest TSC runs at the destination's
+        // frequency after migration.
+        let (source_tsc, destination_tsc) = (
+            frequency::frequencies(source).tsc,
+            frequency::frequencies(destination).tsc,
+        );
+        if let (Some(vendor), Some(source_tsc), Some(destination_tsc)) =
+            (destination_vendor, source_tsc, destination_tsc)
+        {
+            if source_tsc != destination_tsc
+                && tsc_scaling(vendor).status(destination) == FeatureStatus::Absent
+            {
+                losses.push(Loss::new(
+                    TIME_VIRTUALIZATION,
+                    "TSC frequency",
+                    Impact::Guest,
+                    Some(format!(
+                        "{} Hz -> {} Hz without TSC scaling",
+                        source_tsc, destination_tsc
+                    )),
+                ));
+            }
+        }
+
+        MigrationCheck { losses, undecided }
+    }
+
+    /// Whether running guests can be migrated without losing
+    /// features. Losses that only affect nested guests or the
+    /// hypervisor don't count, but features that guests may lose
+    /// because the destination dump lacks data do.
+    pub fn is_safe(&self) -> bool {
+        self.losses
+            .iter()
+            .chain(&self.undecided)
+            .all(|l| l.impact != Impact::Guest)
+    }
+
+    /// Show the losses, with the hosts labelled `source` and
+    /// `destination`.
+    pub fn section(&self, source: &str, destination: &str) -> Section {
+        let mut section = Section::new(&format!("Migration from {} to {}", source, destination));
+
+        for loss in &self.losses {
+            let name = format!("{}: {}", loss.category, loss.name);
+
+            match &loss.detail {
+                Some(detail) => section.row(&name, format!("{} ({})", loss.impact, detail)),
+                None => section.row(&name, loss.impact),
+            }
+        }
+        for loss in &self.undecided {
+            section.row(
+                &format!("{}: {}", loss.category, loss.name),
+                format!(
+                    "undecided, may be {} ({})",
+                    loss.impact,
+                    loss.detail.as_deref().unwrap_or_default()
+                ),
+            );
+        }
+
+        let guests_lose = self.losses.iter().any(|l| l.impact == Impact::Guest);
+        section.row(
+            "Verdict",
+            if guests_lose {
+                "guests lose features"
+            } else if !self.is_safe() {
+                "unknown, the destination dump lacks data"
+            } else {
+                "safe for guests"
+            },
+        );
+
+        section
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog;
+    use crate::catalog::{AVX512, CET, CONFIDENTIAL_COMPUTING, MEMORY_PROTECTION, TSX};
+    use crate::cpu_information::{CpuidQuery, CpuidRegister, CpuidResult};
+    use crate::features::BoolExpression::{CpuidBitSet, MsrBitSet};
+    use crate::speculation::{IA32_ARCH_CAPABILITIES, IA32_TSX_CTRL};
+
+    struct TestCpu {
+        vendor: &'static [u8; 12],
+        leaf7_ebx: u32,
+        address_bits: u32,
+        msr: Option<u64>,
+        arch_capabilities: u64,
+        tsx_ctrl: u64,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let dword = |i: usize| {
+                u32::from_le_bytes([
+                    self.vendor[i],
+                    self.vendor[i + 1],
+                    self.vendor[i + 2],
+                    self.vendor[i + 3],
+                ])
+            };
+
+            match query.leaf {
+                0 => Some(CpuidResult {
+                    eax: 7,
+                    ebx: dword(0),
+                    ecx: dword(8),
+                    edx: dword(4),
+                }),
+                7 => Some(CpuidResult {
+                    eax: 0,
+                    ebx: self.leaf7_ebx,
+                    ecx: 0,
+                    edx: 0,
+                }),
+                0x8000_0000 => Some(CpuidResult {
+                    eax: 0x8000_0008,
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                }),
+                0x8000_0008 => Some(CpuidResult {
+                    eax: self.address_bits,
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                }),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            match index {
+                0x48c => self.msr,
+                IA32_ARCH_CAPABILITIES => Some(self.arch_capabilities),
+                IA32_TSX_CTRL => Some(self.tsx_ctrl),
+                _ => None,
+            }
+        }
+    }
+
+    fn intel(leaf7_ebx: u32, address_bits: u32, msr: u64) -> TestCpu {
+        TestCpu {
+            vendor: b"GenuineIntel",
+            leaf7_ebx,
+            address_bits,
+            msr: Some(msr),
+            arch_capabilities: 0,
+            tsx_ctrl: 0,
+        }
+    }
+
+    fn catalog() -> Vec<Feature> {
+        vec![
+            Feature::new(
+                AVX512,
+                "AVX512F",
+                CpuidBitSet(7.into(), CpuidRegister::Ebx, 16),
+            ),
+            Feature::new(
+                MEMORY_PROTECTION,
+                "SMEP",
+                CpuidBitSet(7.into(), CpuidRegister::Ebx, 7),
+            ),
+            Feature::new(EPT, "EPT 1GB pages", MsrBitSet(0x48c, 17)),
+            Feature::new(
+                CONFIDENTIAL_COMPUTING,
+                "AMD only",
+                CpuidBitSet(7.into(), CpuidRegister::Ebx, 0),
+            )
+            .vendor(Vendor::Amd),
+        ]
+    }
+
+    #[test]
+    fn same_host_is_safe() {
+        let host = intel(0x1_0081, 46, 1 << 17);
+        let check = MigrationCheck::new(&catalog(), &host, &host);
+
+        assert!(check.losses.is_empty());
+        assert!(check.is_safe());
+    }
+
+    #[test]
+    fn lost_features_are_classified() {
+        let source = intel(0x1_0081, 46, 1 << 17);
+        let destination = intel(0x80, 39, 0);
+        let check = MigrationCheck::new(&catalog(), &source, &destination);
+
+        assert_eq!(
+            check.losses,
+            vec![
+                Loss::new(AVX512, "AVX512F", Impact::Guest, None),
+                Loss::new(EPT, "EPT 1GB pages", Impact::Nested, None),
+                Loss::new(
+                    "Address width",
+                    "Physical address bits",
+                    Impact::Guest,
+                    Some("46 -> 39".to_owned())
+                ),
+            ]
+        );
+        assert!(!check.is_safe());
+
+        let section = check.section("old", "new");
+        assert_eq!(section.title, "Migration from old to new");
+        assert_eq!(
+            section.get("EPT: EPT 1GB pages"),
+            Some("lost for nested guests")
+        );
+        assert_eq!(
+            section.get("Address width: Physical address bits"),
+            Some("lost for guests (46 -> 39)")
+        );
+        assert_eq!(section.get("Verdict"), Some("guests lose features"));
+    }
+
+    #[test]
+    fn implausible_address_widths_are_ignored() {
+        let host = intel(0, 46, 0);
+        let garbage = intel(0, 0xff, 0);
+
+        assert!(MigrationCheck::new(&catalog(), &garbage, &host)
+            .losses
+            .is_empty());
+        assert!(MigrationCheck::new(&catalog(), &host, &intel(0, 0, 0))
+            .losses
+            .is_empty());
+    }
+
+    #[test]
+    fn nested_losses_are_safe_for_guests() {
+        let check = MigrationCheck::new(&catalog(), &intel(0, 46, 1 << 17), &intel(0, 46, 0));
+
+        assert_eq!(check.losses.len(), 1);
+        assert!(check.is_safe());
+    }
+
+    #[test]
+    fn undecided_features_are_not_safe() {
+        // The destination dump lacks the MSR.
+        let mut catalog = catalog();
+        catalog.push(Feature::new(MEMORY_PROTECTION, "Test", MsrBitSet(0x48c, 0)));
+        let destination = TestCpu {
+            msr: None,
+            ..intel(0x1_0081, 46, 0)
+        };
+        let check = MigrationCheck::new(&catalog, &intel(0x1_0081, 46, 1), &destination);
+
+        assert!(check.losses.is_empty());
+        assert_eq!(
+            check.undecided,
+            vec![Loss::new(
+                MEMORY_PROTECTION,
+                "Test",
+                Impact::Guest,
+                Some("missing MSR 0x48c".to_owned())
+            )]
+        );
+        assert!(!check.is_safe());
+
+        let section = check.section("old", "new");
+        assert_eq!(
+            section.get("Memory protection: Test"),
+            Some("undecided, may be lost for guests (missing MSR 0x48c)")
+        );
+        assert_eq!(
+            section.get("Verdict"),
+            Some("unknown, the destination dump lacks data")
+        );
+    }
+
+    #[test]
+    fn lost_restrictions_are_not_losses() {
+        let catalog = vec![Feature::new(TSX, "TSX disabled", MsrBitSet(0x48c, 0)).restriction()];
+        let check = MigrationCheck::new(&catalog, &intel(0, 46, 1), &intel(0, 46, 0));
+
+        assert!(check.losses.is_empty());
+        assert!(check.is_safe());
+
+        // From a host with RTM disabled to one where it's usable.
+        let disabled = TestCpu {
+            arch_capabilities: 1 << 7,
+            tsx_ctrl: 1,
+            ..intel(1 << 11, 46, 0)
+        };
+        let enabled = TestCpu {
+            arch_capabilities: 1 << 7,
+            ..intel(1 << 11, 46, 0)
+        };
+        let check = MigrationCheck::new(&catalog::features(), &disabled, &enabled);
+
+        assert_eq!(check.losses, vec![]);
+        assert!(check.is_safe());
+    }
+
+    #[test]
+    fn vendors_must_match() {
+        let amd = TestCpu {
+            vendor: b"AuthenticAMD",
+            ..intel(0x1_0081, 46, 0)
+        };
+        let check = MigrationCheck::new(&catalog(), &intel(0x1_0081, 46, 0), &amd);
+
+        assert_eq!(
+            check.losses,
+            vec![Loss::new(
+                "Vendor",
+                "CPU vendor",
+                Impact::Guest,
+                Some("Intel -> AMD".to_owned())
+            )]
+        );
+    }
+
+    #[test]
+    fn unknown_categories_are_visible_to_guests() {
+        assert_eq!(category_impact(CET), Impact::Guest);
+        assert_eq!(category_impact("Other"), Impact::Guest);
+        assert_eq!(category_impact(SVM), Impact::Nested);
+    }
+}
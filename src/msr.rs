@@ -0,0 +1,477 @@
+//! # MSR Registry
+//!
+//! Many MSRs are just a collection of named bits and bitfields. Instead
+//! of decoding each of them by hand, they are described here once and
+//! [Msr::section] turns a value into a report section.
+//!
+//! MSRs whose fields need interpretation beyond a number, such as the
+//! VMX capability MSRs, have their own decoders.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::Bit;
+use crate::mca::{IA32_MCG_CAP, MCG_CMCI_P};
+use crate::mtrr::IA32_MTRRCAP;
+use crate::mwait::MSR_PKG_CST_CONFIG_CONTROL;
+use crate::pmu::IA32_PERF_CAPABILITIES;
+use crate::power::{IA32_ENERGY_PERF_BIAS, MSR_PLATFORM_INFO};
+use crate::report::Section;
+use crate::sev::{SEV_STATUS, SEV_STATUS_FLAGS, SYSCFG, SYSCFG_FLAGS};
+use crate::sgx::{FEATURE_CONTROL_SGX_ENABLE, FEATURE_CONTROL_SGX_LC};
+use crate::speculation::{
+    ARCH_CAPABILITIES, IA32_ARCH_CAPABILITIES, IA32_MCU_OPT_CTRL, IA32_TSX_CTRL, MCU_OPT_CTRL,
+    MSR_TSX_FORCE_ABORT, TSX_CTRL, TSX_FORCE_ABORT,
+};
+use crate::svm::{VM_CR, VM_CR_LOCK, VM_CR_SVMDIS};
+use crate::vmx::IA32_FEATURE_CONTROL;
+
+/// The microcode revision.
+pub const IA32_BIOS_SIGN_ID: u32 = 0x8b;
+
+/// The local APIC base address and mode.
+pub const IA32_APIC_BASE: u32 = 0x1b;
+
+/// The IA32_APIC_BASE bit that enables x2APIC mode.
+pub const APIC_BASE_X2APIC_ENABLE: Bit = 10;
+
+/// The IA32_APIC_BASE bit that enables the APIC.
+pub const APIC_BASE_GLOBAL_ENABLE: Bit = 11;
+
+/// The extended feature enable register.
+pub const EFER: u32 = 0xc000_0080;
+
+/// The EFER bit that enables SVM.
+pub const EFER_SVME: Bit = 12;
+
+/// The page attribute table.
+pub const IA32_PAT: u32 = 0x277;
+
+/// The memory types of PAT entries and MTRRs.
+pub const MEMORY_TYPES: &[(u64, &str)] = &[
+    (0, "UC (uncacheable)"),
+    (1, "WC (write combining)"),
+    (4, "WT (write-through)"),
+    (5, "WP (write-protected)"),
+    (6, "WB (write-back)"),
+    (7, "UC- (uncached)"),
+];
+
+/// Miscellaneous processor features.
+pub const IA32_MISC_ENABLE: u32 = 0x1a0;
+
+/// The IA32_MISC_ENABLE bit that enables MONITOR and MWAIT.
+pub const MISC_ENABLE_MONITOR: Bit = 18;
+
+/// The IA32_MISC_ENABLE bit that limits the maximum CPUID leaf to 2.
+pub const MISC_ENABLE_LIMIT_CPUID: Bit = 22;
+
+/// The IA32_MISC_ENABLE bit that hides the NX bit.
+pub const MISC_ENABLE_XD_DISABLE: Bit = 34;
+
+/// The IA32_MISC_ENABLE bit that disables Turbo Boost.
+pub const MISC_ENABLE_TURBO_DISABLE: Bit = 38;
+
+/// How a field is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A single bit, shown as Y or N.
+    Flag,
+
+    /// An unsigned number.
+    Number,
+
+    /// A physical address whose low bits are not stored, shown in
+    /// hex without shifting the field down.
+    Address,
+
+    /// A value with names. Unnamed values are reserved.
+    Named(&'static [(u64, &'static str)]),
+}
+
+/// A field of one or more bits: first bit, width, format and name.
+pub type Field = (Bit, u8, Format, &'static str);
+
+/// The name of a value, or "reserved" if it has none.
+pub fn value_name(names: &[(u64, &str)], value: u64) -> String {
+    names
+        .iter()
+        .find(|(v, _)| *v == value)
+        .map(|(_, n)| n.to_string())
+        .unwrap_or_else(|| format!("reserved ({})", value))
+}
+
+/// The layout of a MSR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Msr {
+    pub index: u32,
+    pub title: &'static str,
+
+    /// Single-bit flags, shown as Y or N.
+    pub flags: &'static [(Bit, &'static str)],
+
+    /// Multi-bit fields.
+    pub fields: &'static [Field],
+}
+
+impl Msr {
+    /// Decode a value of this MSR.
+    ///
+    /// Rows are ordered by their first bit.
+    pub fn section(&self, value: u64) -> Section {
+        let mut section = Section::new(&format!("{} (MSR {:#x})", self.title, self.index));
+
+        let mut rows = self.fields();
+        rows.sort_by_key(|(bit, _, _, _)| *bit);
+
+        for (bit, width, format, name) in rows {
+            let field = (value >> bit) & ((1 << width) - 1);
+
+            match format {
+                Format::Flag => section.flag(name, value, bit),
+                Format::Number => section.row(name, field),
+                Format::Address => section.row(name, format!("{:#x}", field << bit)),
+                Format::Named(names) => section.row(name, value_name(names, field)),
+            }
+        }
+
+        section
+    }
+
+    /// The flags and fields of this MSR.
+    fn fields(&self) -> Vec<Field> {
+        let flags = self
+            .flags
+            .iter()
+            .map(|&(bit, name)| (bit, 1, Format::Flag, name));
+
+        flags.chain(self.fields.iter().copied()).collect()
+    }
+}
+
+/// The MSRs that are decoded from their layout.
+pub const REGISTRY: &[Msr] = &[
+    Msr {
+        index: IA32_FEATURE_CONTROL,
+        title: "Feature control",
+        flags: &[
+            (0, "Lock"),
+            (1, "VMX inside SMX"),
+            (2, "VMX outside SMX"),
+            (15, "SENTER global enable"),
+            (FEATURE_CONTROL_SGX_LC, "SGX launch control"),
+            (FEATURE_CONTROL_SGX_ENABLE, "SGX global enable"),
+            (20, "LMCE"),
+        ],
+        fields: &[(8, 7, Format::Number, "SENTER local function enables")],
+    },
+    Msr {
+        index: IA32_APIC_BASE,
+        title: "APIC base",
+        flags: &[
+            (8, "Bootstrap processor"),
+            (APIC_BASE_X2APIC_ENABLE, "x2APIC mode enabled"),
+            (APIC_BASE_GLOBAL_ENABLE, "APIC globally enabled"),
+        ],
+        fields: &[(12, 40, Format::Address, "APIC base address")],
+    },
+    Msr {
+        index: MSR_PLATFORM_INFO,
+        title: "Platform information",
+        flags: &[
+            (28, "Programmable ratio limits for turbo"),
+            (29, "Programmable TDP limits for turbo"),
+            (30, "Programmable TJ offset"),
+            (32, "Low power mode"),
+        ],
+        fields: &[
+            (8, 8, Format::Number, "Maximum non-turbo ratio"),
+            (33, 2, Format::Number, "Configurable TDP levels"),
+            (40, 8, Format::Number, "Maximum efficiency ratio"),
+            (48, 8, Format::Number, "Minimum operating ratio"),
+        ],
+    },
+    Msr {
+        index: IA32_MCG_CAP,
+        title: "Machine-check capabilities",
+        flags: &[
+            (8, "MCG_CTL"),
+            (9, "Extended state registers"),
+            (MCG_CMCI_P, "CMCI"),
+            (11, "Threshold-based error status"),
+            (24, "Software error recovery"),
+            (25, "Enhanced machine-check capability"),
+            (26, "Extended error logging"),
+            (27, "Local machine-check exceptions"),
+        ],
+        fields: &[
+            (0, 8, Format::Number, "Banks"),
+            (16, 8, Format::Number, "Extended state registers count"),
+        ],
+    },
+    Msr {
+        index: IA32_MISC_ENABLE,
+        title: "Miscellaneous features",
+        flags: &[
+            (0, "Fast strings"),
+            (3, "Automatic thermal control (TM1)"),
+            (7, "Performance monitoring available"),
+            (11, "BTS unavailable"),
+            (12, "PEBS unavailable"),
+            (16, "Enhanced SpeedStep"),
+            (MISC_ENABLE_MONITOR, "MONITOR/MWAIT enabled"),
+            (MISC_ENABLE_LIMIT_CPUID, "Limit CPUID maxval"),
+            (23, "xTPR messages disabled"),
+            (MISC_ENABLE_XD_DISABLE, "XD disabled"),
+            (MISC_ENABLE_TURBO_DISABLE, "Turbo mode disabled"),
+        ],
+        fields: &[],
+    },
+    Msr {
+        index: IA32_PERF_CAPABILITIES,
+        title: "Performance monitoring capabilities",
+        flags: &[
+            (6, "PEBS trap"),
+            (7, "PEBS saves architectural registers"),
+            (12, "Freeze while SMM"),
+            (13, "Full-width counter writes"),
+            (14, "PEBS baseline"),
+            (15, "Performance metrics"),
+            (16, "PEBS output to Intel PT"),
+        ],
+        fields: &[
+            (0, 6, Format::Number, "LBR format"),
+            (8, 4, Format::Number, "PEBS record format"),
+        ],
+    },
+    Msr {
+        index: IA32_ARCH_CAPABILITIES,
+        title: "Architectural capabilities",
+        flags: ARCH_CAPABILITIES,
+        fields: &[],
+    },
+    Msr {
+        index: IA32_MCU_OPT_CTRL,
+        title: "Microcode mitigation control",
+        flags: MCU_OPT_CTRL,
+        fields: &[],
+    },
+    Msr {
+        index: IA32_TSX_CTRL,
+        title: "TSX control",
+        flags: TSX_CTRL,
+        fields: &[],
+    },
+    Msr {
+        index: MSR_TSX_FORCE_ABORT,
+        title: "TSX force abort",
+        flags: TSX_FORCE_ABORT,
+        fields: &[],
+    },
+    Msr {
+        index: MSR_PKG_CST_CONFIG_CONTROL,
+        title: "Package C-state configuration",
+        flags: &[
+            (10, "I/O MWAIT redirection"),
+            (15, "Locked"),
+            (25, "C3 auto demotion"),
+            (26, "C1 auto demotion"),
+            (27, "C3 undemotion"),
+            (28, "C1 undemotion"),
+            (29, "Package C-state demotion"),
+            (30, "Package C-state undemotion"),
+        ],
+        fields: &[(0, 4, Format::Number, "Package C-state limit")],
+    },
+    Msr {
+        index: IA32_ENERGY_PERF_BIAS,
+        title: "Energy performance bias",
+        flags: &[],
+        fields: &[(
+            0,
+            4,
+            Format::Number,
+            "Bias (0 = performance, 15 = energy saving)",
+        )],
+    },
+    Msr {
+        index: IA32_MTRRCAP,
+        title: "MTRR capabilities",
+        flags: &[
+            (8, "Fixed ranges"),
+            (10, "Write combining"),
+            (11, "SMRR"),
+            (12, "PRMRR"),
+        ],
+        fields: &[(0, 8, Format::Number, "Variable ranges")],
+    },
+    Msr {
+        index: IA32_PAT,
+        title: "Page attribute table",
+        flags: &[],
+        fields: &[
+            (0, 3, Format::Named(MEMORY_TYPES), "PA0"),
+            (8, 3, Format::Named(MEMORY_TYPES), "PA1"),
+            (16, 3, Format::Named(MEMORY_TYPES), "PA2"),
+            (24, 3, Format::Named(MEMORY_TYPES), "PA3"),
+            (32, 3, Format::Named(MEMORY_TYPES), "PA4"),
+            (40, 3, Format::Named(MEMORY_TYPES), "PA5"),
+            (48, 3, Format::Named(MEMORY_TYPES), "PA6"),
+            (56, 3, Format::Named(MEMORY_TYPES), "PA7"),
+        ],
+    },
+    Msr {
+        index: EFER,
+        title: "Extended features",
+        flags: &[
+            (0, "SCE (SYSCALL)"),
+            (8, "LME (long mode enable)"),
+            (10, "LMA (long mode active)"),
+            (11, "NXE (no-execute)"),
+            (EFER_SVME, "SVME (SVM enabled)"),
+            (13, "LMSLE (long mode segment limits)"),
+            (14, "FFXSR (fast FXSAVE)"),
+            (15, "TCE (translation cache extension)"),
+        ],
+        fields: &[],
+    },
+    Msr {
+        index: SYSCFG,
+        title: "System configuration",
+        flags: SYSCFG_FLAGS,
+        fields: &[],
+    },
+    Msr {
+        index: VM_CR,
+        title: "VM control",
+        flags: &[
+            (0, "DPD"),
+            (1, "R_INIT"),
+            (2, "DIS_A20M"),
+            (VM_CR_LOCK, "LOCK"),
+            (VM_CR_SVMDIS, "SVMDIS"),
+        ],
+        fields: &[],
+    },
+    Msr {
+        index: SEV_STATUS,
+        title: "SEV status",
+        flags: SEV_STATUS_FLAGS,
+        fields: &[],
+    },
+];
+
+/// Look up the layout of a MSR.
+pub fn find(index: u32) -> Option<&'static Msr> {
+    REGISTRY.iter().find(|m| m.index == index)
+}
+
+/// Decode the registered MSRs that don't belong to a particular
+/// feature.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    [IA32_APIC_BASE, IA32_MISC_ENABLE, IA32_PAT, EFER]
+        .iter()
+        .filter_map(|&index| section(cpu_info, index))
+        .collect()
+}
+
+/// Decode a registered MSR, if the dump contains it.
+pub fn section(cpu_info: &dyn CpuInformation, index: u32) -> Option<Section> {
+    let msr = find(index)?;
+
+    cpu_info.rdmsr(index).map(|value| msr.section(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_and_fields_are_decoded_in_bit_order() {
+        let section = find(IA32_FEATURE_CONTROL)
+            .expect("registered")
+            .section(0x0004_7f05);
+
+        assert_eq!(section.title, "Feature control (MSR 0x3a)");
+        assert_eq!(section.rows[0], ("Lock".to_owned(), "Y".to_owned()));
+        assert_eq!(section.get("VMX inside SMX"), Some("N"));
+        assert_eq!(section.get("VMX outside SMX"), Some("Y"));
+        assert_eq!(section.get("SENTER local function enables"), Some("127"));
+        assert_eq!(section.get("SGX global enable"), Some("Y"));
+        assert_eq!(section.rows[3].0, "SENTER local function enables");
+    }
+
+    #[test]
+    fn apic_base_is_decoded() {
+        let section = find(IA32_APIC_BASE)
+            .expect("registered")
+            .section(0xfee0_0d00);
+
+        assert_eq!(section.get("Bootstrap processor"), Some("Y"));
+        assert_eq!(section.get("x2APIC mode enabled"), Some("Y"));
+        assert_eq!(section.get("APIC globally enabled"), Some("Y"));
+        assert_eq!(section.get("APIC base address"), Some("0xfee00000"));
+    }
+
+    #[test]
+    fn efer_is_decoded() {
+        let section = find(EFER).expect("registered").section(0x1d01);
+
+        assert_eq!(section.title, "Extended features (MSR 0xc0000080)");
+        assert_eq!(section.get("SCE (SYSCALL)"), Some("Y"));
+        assert_eq!(section.get("LMA (long mode active)"), Some("Y"));
+        assert_eq!(section.get("NXE (no-execute)"), Some("Y"));
+        assert_eq!(section.get("SVME (SVM enabled)"), Some("Y"));
+        assert_eq!(section.get("FFXSR (fast FXSAVE)"), Some("N"));
+    }
+
+    #[test]
+    fn pat_is_decoded() {
+        // The power-on default, with PA1 changed to WC as Linux does.
+        let section = find(IA32_PAT)
+            .expect("registered")
+            .section(0x0007_0406_0007_0106);
+
+        assert_eq!(section.get("PA0"), Some("WB (write-back)"));
+        assert_eq!(section.get("PA1"), Some("WC (write combining)"));
+        assert_eq!(section.get("PA2"), Some("UC- (uncached)"));
+        assert_eq!(section.get("PA3"), Some("UC (uncacheable)"));
+        assert_eq!(section.get("PA5"), Some("WT (write-through)"));
+        assert_eq!(section.rows.len(), 8);
+
+        let section = find(IA32_PAT).expect("registered").section(0x2);
+        assert_eq!(section.get("PA0"), Some("reserved (2)"));
+    }
+
+    #[test]
+    fn misc_enable_is_decoded() {
+        let section = find(IA32_MISC_ENABLE)
+            .expect("registered")
+            .section(0x0000_0040_0045_0089);
+
+        assert_eq!(section.get("Fast strings"), Some("Y"));
+        assert_eq!(section.get("Automatic thermal control (TM1)"), Some("Y"));
+        assert_eq!(section.get("MONITOR/MWAIT enabled"), Some("Y"));
+        assert_eq!(section.get("Limit CPUID maxval"), Some("Y"));
+        assert_eq!(section.get("XD disabled"), Some("N"));
+        assert_eq!(section.get("Turbo mode disabled"), Some("Y"));
+    }
+
+    #[test]
+    fn indices_are_unique() {
+        for (i, msr) in REGISTRY.iter().enumerate() {
+            assert!(REGISTRY[i + 1..].iter().all(|m| m.index != msr.index));
+        }
+    }
+
+    #[test]
+    fn fields_do_not_overlap() {
+        for msr in REGISTRY {
+            let mut used = 0u64;
+            for (bit, width, _, name) in msr.fields() {
+                let mask = ((1u64 << width) - 1) << bit;
+
+                assert_eq!(used & mask, 0, "{} overlaps in {}", name, msr.title);
+                used |= mask;
+            }
+        }
+    }
+}
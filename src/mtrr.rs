@@ -0,0 +1,285 @@
+//! # Decode Memory Type Range Registers
+//!
+//! MTRRs assign a memory type to ranges of physical memory. IA32_MTRRCAP
+//! enumerates how many variable ranges the CPU has and whether the
+//! fixed ranges, which cover the first megabyte in 88 pieces, exist.
+//! IA32_MTRR_DEF_TYPE enables MTRRs and sets the type of all memory
+//! that no range covers.
+//!
+//! Each variable range is a pair of MSRs. PHYSBASE holds the base
+//! address and type, PHYSMASK a mask and a valid bit. An address is in
+//! the range if `address & mask == base & mask`. Firmware practically
+//! always uses contiguous masks, which describe a naturally aligned,
+//! power-of-two sized block.
+//!
+//! See the Intel SDM Vol. 3A, Section 12.11 and the AMD APM Vol. 2,
+//! Section 7.7.
+
+use crate::cpu_information::CpuInformation;
+use crate::msr::{self, MEMORY_TYPES};
+use crate::report::Section;
+
+/// The MTRR capabilities.
+pub const IA32_MTRRCAP: u32 = 0xfe;
+
+/// The MTRR default type and enables.
+pub const IA32_MTRR_DEF_TYPE: u32 = 0x2ff;
+
+/// The first variable-range PHYSBASE MSR. PHYSMASK follows each
+/// PHYSBASE.
+pub const IA32_MTRR_PHYSBASE0: u32 = 0x200;
+
+/// The fixed-range MTRRs with the start of their first range and the
+/// size of each of their eight ranges.
+const FIXED_RANGES: &[(u32, u64, u64)] = &[
+    (0x250, 0x0_0000, 0x1_0000),
+    (0x258, 0x8_0000, 0x4000),
+    (0x259, 0xa_0000, 0x4000),
+    (0x268, 0xc_0000, 0x1000),
+    (0x269, 0xc_8000, 0x1000),
+    (0x26a, 0xd_0000, 0x1000),
+    (0x26b, 0xd_8000, 0x1000),
+    (0x26c, 0xe_0000, 0x1000),
+    (0x26d, 0xe_8000, 0x1000),
+    (0x26e, 0xf_0000, 0x1000),
+    (0x26f, 0xf_8000, 0x1000),
+];
+
+/// The physical address width of CPUs that don't enumerate it.
+const DEFAULT_PHYSICAL_ADDRESS_BITS: u32 = 36;
+
+/// The name of a memory type.
+fn memory_type(value: u64) -> String {
+    msr::value_name(MEMORY_TYPES, value)
+}
+
+/// A range of physical memory with one memory type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: u64,
+
+    /// The last address in the range.
+    pub end: u64,
+    pub memory_type: u64,
+}
+
+/// The fixed ranges, with neighbors of the same type merged.
+///
+/// `values` holds the fixed-range MTRRs in the order of
+/// [FIXED_RANGES].
+fn fixed_ranges(values: &[u64]) -> Vec<Range> {
+    let mut ranges: Vec<Range> = vec![];
+
+    for (&(_, first, size), value) in FIXED_RANGES.iter().zip(values) {
+        for i in 0..8 {
+            let start = first + i * size;
+            let memory_type = (value >> (i * 8)) & 0xff;
+
+            match ranges.last_mut() {
+                Some(last) if last.memory_type == memory_type && last.end + 1 == start => {
+                    last.end = start + size - 1
+                }
+                _ => ranges.push(Range {
+                    start,
+                    end: start + size - 1,
+                    memory_type,
+                }),
+            }
+        }
+    }
+
+    ranges
+}
+
+/// A variable range from its PHYSBASE and PHYSMASK MSRs.
+///
+/// Returns `None` if the mask is not contiguous, because it doesn't
+/// describe a single range then. The valid bit is not checked.
+fn variable_range(base: u64, mask: u64, address_bits: u32) -> Option<Range> {
+    let address_mask = ((1u64 << address_bits) - 1) & !0xfff;
+    let mask = mask & address_mask;
+    let size = (!mask & address_mask) + 0x1000;
+
+    (size.is_power_of_two() && mask == address_mask & !(size - 1)).then(|| Range {
+        start: base & mask,
+        end: (base & mask) + size - 1,
+        memory_type: base & 0xff,
+    })
+}
+
+/// Decode the MTRRs into a map of physical memory.
+///
+/// `fixed` holds the fixed-range MTRRs in the order of
+/// [FIXED_RANGES] and `variable` the PHYSBASE and PHYSMASK pairs.
+/// Variable ranges may overlap. The CPU then picks the type with
+/// the help of precedence rules, e.g. UC wins over WB, which this
+/// doesn't resolve.
+fn map_section(
+    def_type: u64,
+    fixed: Option<&[u64]>,
+    variable: &[(u64, u64)],
+    address_bits: u32,
+) -> Section {
+    let mut section = Section::new(&format!("MTRR memory map (MSR {:#x})", IA32_MTRR_DEF_TYPE));
+
+    section.flag("MTRRs enabled", def_type, 11);
+    section.flag("Fixed ranges enabled", def_type, 10);
+    section.row("Default type", memory_type(def_type & 0xff));
+
+    if let Some(fixed) = fixed.filter(|_| def_type & (1 << 10) != 0) {
+        for range in fixed_ranges(fixed) {
+            section.row(
+                &format!("{:#x}-{:#x}", range.start, range.end),
+                memory_type(range.memory_type),
+            );
+        }
+    }
+
+    for (i, &(base, mask)) in variable.iter().enumerate() {
+        if mask & (1 << 11) == 0 {
+            continue;
+        }
+
+        match variable_range(base, mask, address_bits) {
+            Some(range) => section.row(
+                &format!("{:#x}-{:#x}", range.start, range.end),
+                format!("{} (variable range {})", memory_type(range.memory_type), i),
+            ),
+            None => section.row(
+                &format!("Variable range {}", i),
+                format!(
+                    "{} at {:#x}, non-contiguous mask {:#x}",
+                    memory_type(base & 0xff),
+                    base & !0xfff,
+                    mask & !0xfff
+                ),
+            ),
+        }
+    }
+
+    section
+}
+
+/// Decode the MTRRs that are in the dump.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let capabilities = match cpu_info.rdmsr(IA32_MTRRCAP) {
+        Some(capabilities) => capabilities,
+        None => return vec![],
+    };
+    let mut sections: Vec<Section> = msr::section(cpu_info, IA32_MTRRCAP).into_iter().collect();
+
+    if let Some(def_type) = cpu_info.rdmsr(IA32_MTRR_DEF_TYPE) {
+        let fixed: Option<Vec<u64>> = FIXED_RANGES
+            .iter()
+            .map(|(index, _, _)| cpu_info.rdmsr(*index))
+            .collect();
+        let variable: Vec<(u64, u64)> = (0..(capabilities & 0xff) as u32)
+            .map_while(|i| {
+                let base = IA32_MTRR_PHYSBASE0 + 2 * i;
+
+                Some((cpu_info.rdmsr(base)?, cpu_info.rdmsr(base + 1)?))
+            })
+            .collect();
+        let address_bits = cpu_info
+            .physical_address_bits()
+            .unwrap_or(DEFAULT_PHYSICAL_ADDRESS_BITS);
+
+        sections.push(map_section(
+            def_type,
+            fixed.as_deref().filter(|_| capabilities & (1 << 8) != 0),
+            &variable,
+            address_bits,
+        ));
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_ranges_are_merged() {
+        // WB below 640 KiB, UC for the legacy video RAM, WP for the
+        // option ROMs and the BIOS.
+        let mut fixed = vec![0x0606_0606_0606_0606, 0x0606_0606_0606_0606, 0];
+        fixed.extend([0x0505_0505_0505_0505; 8]);
+
+        assert_eq!(
+            fixed_ranges(&fixed),
+            vec![
+                Range {
+                    start: 0,
+                    end: 0x9_ffff,
+                    memory_type: 6
+                },
+                Range {
+                    start: 0xa_0000,
+                    end: 0xb_ffff,
+                    memory_type: 0
+                },
+                Range {
+                    start: 0xc_0000,
+                    end: 0xf_ffff,
+                    memory_type: 5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn variable_ranges_are_decoded() {
+        // 2 GiB of WB at 0 and 256 MiB of UC at 0x80000000 on a CPU
+        // with 39 physical address bits.
+        assert_eq!(
+            variable_range(0x6, 0x7f_8000_0800, 39),
+            Some(Range {
+                start: 0,
+                end: 0x7fff_ffff,
+                memory_type: 6
+            })
+        );
+        assert_eq!(
+            variable_range(0x8000_0000, 0x7f_f000_0800, 39),
+            Some(Range {
+                start: 0x8000_0000,
+                end: 0x8fff_ffff,
+                memory_type: 0
+            })
+        );
+        assert_eq!(variable_range(0x6, 0x7f_8000_1800, 39), None);
+    }
+
+    #[test]
+    fn map_is_decoded() {
+        let mut fixed = vec![0x0606_0606_0606_0606, 0x0606_0606_0606_0606, 0];
+        fixed.extend([0x0505_0505_0505_0505; 8]);
+        let variable = [(0x6, 0x7f_8000_0800), (0x8000_0000, 0x7f_f000_0800), (0, 0)];
+
+        let section = map_section(0xc00, Some(&fixed), &variable, 39);
+
+        assert_eq!(section.get("MTRRs enabled"), Some("Y"));
+        assert_eq!(section.get("Default type"), Some("UC (uncacheable)"));
+        assert_eq!(section.get("0x0-0x9ffff"), Some("WB (write-back)"));
+        assert_eq!(section.get("0xc0000-0xfffff"), Some("WP (write-protected)"));
+        assert_eq!(
+            section.get("0x0-0x7fffffff"),
+            Some("WB (write-back) (variable range 0)")
+        );
+        assert_eq!(
+            section.get("0x80000000-0x8fffffff"),
+            Some("UC (uncacheable) (variable range 1)")
+        );
+        assert_eq!(section.rows.len(), 8);
+    }
+
+    #[test]
+    fn disabled_fixed_ranges_are_not_shown() {
+        let fixed = [0x0606_0606_0606_0606; 11];
+        let section = map_section(0x806, Some(&fixed), &[], 36);
+
+        assert_eq!(section.get("Default type"), Some("WB (write-back)"));
+        assert_eq!(section.get("0x0-0xfffff"), None);
+    }
+}
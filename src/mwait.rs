@@ -0,0 +1,219 @@
+//! # Decode MONITOR/MWAIT Parameters
+//!
+//! CPUID leaf 5 describes MONITOR and MWAIT: the range of monitor
+//! line sizes, whether MWAIT can wake up on masked interrupts, and
+//! how many sub-states each C-state has. Idle drivers use this to pick
+//! MWAIT hints, and hypervisors to decide whether to let guests
+//! execute MWAIT instead of exiting on it.
+//!
+//! WAITPKG adds user-level variants of these instructions: UMONITOR,
+//! UMWAIT and TPAUSE. The OS limits how long they may wait and whether
+//! they may enter the deeper C0.2 state in IA32_UMWAIT_CONTROL.
+//!
+//! Which package C-states MWAIT can actually reach is limited by
+//! MSR_PKG_CST_CONFIG_CONTROL. Firmware usually locks it, so the OS
+//! can't raise the limit. The encoding of the limit is model
+//! specific.
+//!
+//! See the Intel SDM Vol. 2A, CPUID—CPU Identification, and the AMD
+//! APM Vol. 3, Appendix E.
+
+use crate::cpu_information::{CpuInformation, CpuidResult};
+use crate::features::Bit;
+use crate::msr;
+use crate::report::Section;
+
+/// The CPUID leaf with MONITOR/MWAIT parameters.
+pub const CPUID_MWAIT: u32 = 0x5;
+
+/// The bit in CPUID leaf 1 ECX that enumerates MONITOR/MWAIT.
+const MONITOR: Bit = 3;
+
+/// The bit in CPUID leaf 7 ECX that enumerates WAITPKG.
+pub const WAITPKG: Bit = 5;
+
+/// The MSR that controls UMWAIT and TPAUSE.
+pub const IA32_UMWAIT_CONTROL: u32 = 0xe1;
+
+/// The package C-state configuration MSR.
+pub const MSR_PKG_CST_CONFIG_CONTROL: u32 = 0xe2;
+
+/// The MWAIT extensions in ECX.
+const EXTENSIONS: &[(Bit, &str)] = &[
+    (0, "MWAIT extensions enumerated"),
+    (1, "Interrupts break MWAIT even when disabled"),
+    (3, "Monitorless MWAIT"),
+];
+
+/// Decode CPUID leaf 5.
+fn mwait_section(r: CpuidResult) -> Section {
+    let mut section = Section::new(&format!("MONITOR/MWAIT (CPUID {:#x})", CPUID_MWAIT));
+
+    section.row(
+        "Smallest monitor line size",
+        format!("{} bytes", r.eax & 0xffff),
+    );
+    section.row(
+        "Largest monitor line size",
+        format!("{} bytes", r.ebx & 0xffff),
+    );
+
+    for (bit, name) in EXTENSIONS {
+        section.flag(name, r.ecx.into(), *bit);
+    }
+
+    // The sub-state counts are only valid with the extensions.
+    if r.ecx & 1 == 1 {
+        for state in 0..8 {
+            let substates = (r.edx >> (state * 4)) & 0xf;
+
+            if substates != 0 {
+                section.row(&format!("C{} sub-states", state), substates);
+            }
+        }
+    }
+
+    section
+}
+
+/// Decode IA32_UMWAIT_CONTROL.
+fn umwait_section(control: u64) -> Section {
+    let mut section = Section::new(&format!("User wait (MSR {:#x})", IA32_UMWAIT_CONTROL));
+    let max_time = control & 0xffff_fffc;
+
+    section.row("C0.2 allowed", if control & 1 == 0 { 'Y' } else { 'N' });
+    section.row(
+        "Maximum wait time",
+        if max_time == 0 {
+            "unlimited".to_owned()
+        } else {
+            format!("{} TSC ticks", max_time)
+        },
+    );
+
+    section
+}
+
+/// Decode MONITOR/MWAIT parameters, if the CPU supports MWAIT, the
+/// package C-state configuration and the user wait control, if the
+/// CPU supports WAITPKG.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let monitor = cpu_info
+        .cpuid(1.into())
+        .is_some_and(|r| (r.ecx >> MONITOR) & 1 == 1);
+    let waitpkg = cpu_info.is_cpuid_query_valid(7.into())
+        && cpu_info
+            .cpuid(7.into())
+            .is_some_and(|r| (r.ecx >> WAITPKG) & 1 == 1);
+
+    let mwait = cpu_info
+        .cpuid(CPUID_MWAIT.into())
+        .filter(|_| monitor && cpu_info.is_cpuid_query_valid(CPUID_MWAIT.into()))
+        .map(mwait_section);
+    let umwait = cpu_info
+        .rdmsr(IA32_UMWAIT_CONTROL)
+        .filter(|_| waitpkg)
+        .map(umwait_section);
+
+    mwait
+        .into_iter()
+        .chain(msr::section(cpu_info, MSR_PKG_CST_CONFIG_CONTROL))
+        .chain(umwait)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidQuery;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn package_cstate_configuration_is_decoded() {
+        // A locked configuration with C10 as the limit on Skylake.
+        let section = msr::find(MSR_PKG_CST_CONFIG_CONTROL)
+            .expect("registered")
+            .section(0x1e00_8408);
+
+        assert_eq!(section.get("Package C-state limit"), Some("8"));
+        assert_eq!(section.get("I/O MWAIT redirection"), Some("Y"));
+        assert_eq!(section.get("Locked"), Some("Y"));
+        assert_eq!(section.get("C3 auto demotion"), Some("Y"));
+        assert_eq!(section.get("C1 undemotion"), Some("Y"));
+        assert_eq!(section.get("Package C-state demotion"), Some("N"));
+    }
+
+    #[test]
+    fn leaf_is_decoded() {
+        // A Skylake client CPU.
+        let section = mwait_section(CpuidResult {
+            eax: 0x40,
+            ebx: 0x40,
+            ecx: 0x3,
+            edx: 0x1142_0120,
+        });
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Smallest monitor line size"), "64 bytes");
+        assert_eq!(row("Largest monitor line size"), "64 bytes");
+        assert_eq!(row("Interrupts break MWAIT even when disabled"), "Y");
+        assert_eq!(row("Monitorless MWAIT"), "N");
+        assert_eq!(row("C1 sub-states"), "2");
+        assert_eq!(row("C2 sub-states"), "1");
+        assert_eq!(row("C7 sub-states"), "1");
+        assert_eq!(section.get("C0 sub-states"), None);
+    }
+
+    #[test]
+    fn substates_need_extensions() {
+        let section = mwait_section(CpuidResult {
+            eax: 0x40,
+            ebx: 0x40,
+            ecx: 0,
+            edx: 0x1142_0120,
+        });
+
+        assert_eq!(section.get("C1 sub-states"), None);
+    }
+
+    #[test]
+    fn umwait_control_is_decoded() {
+        // Linux's default: C0.2 allowed, 100000 TSC ticks.
+        let section = umwait_section(0x0001_86a0);
+
+        assert_eq!(section.get("C0.2 allowed"), Some("Y"));
+        assert_eq!(section.get("Maximum wait time"), Some("100000 TSC ticks"));
+
+        let section = umwait_section(0x1);
+
+        assert_eq!(section.get("C0.2 allowed"), Some("N"));
+        assert_eq!(section.get("Maximum wait time"), Some("unlimited"));
+    }
+
+    #[test]
+    fn cpus_without_monitor_are_not_decoded() {
+        struct TestCpu;
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+                Some(CpuidResult {
+                    eax: if query.leaf == 0 { 0x16 } else { 0x40 },
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                })
+            }
+
+            fn rdmsr(&self, _index: u32) -> Option<u64> {
+                None
+            }
+        }
+
+        assert!(sections(&TestCpu).is_empty());
+    }
+
+    #[test]
+    fn extensions_are_sorted_and_unique() {
+        assert_sorted_unique(EXTENSIONS);
+    }
+}
@@ -0,0 +1,225 @@
+//! # Compare Packages
+//!
+//! In a system with several packages (sockets), all packages should
+//! run the same microcode, enumerate the same features and be
+//! configured the same way by firmware. If they are not, guests may
+//! see features come and go when they are scheduled on another
+//! package, and VMX may only work on some CPUs. If the dump contains
+//! all logical CPUs, we compare the first CPU of each package.
+//!
+//! AIDA dumps contain MSRs only once, so the MSRs can only be compared
+//! if the per-CPU data includes them.
+
+use std::collections::BTreeSet as Set;
+
+use crate::cpu_information::CpuidRegister::*;
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::hybrid::FEATURE_REGISTERS;
+use crate::msr::{self, IA32_BIOS_SIGN_ID, IA32_MISC_ENABLE};
+use crate::mwait::MSR_PKG_CST_CONFIG_CONTROL;
+use crate::power::{MSR_PLATFORM_INFO, MSR_TURBO_RATIO_LIMIT};
+use crate::report::Section;
+use crate::sev::SYSCFG;
+use crate::svm::VM_CR;
+use crate::topology;
+use crate::vmx::IA32_FEATURE_CONTROL;
+
+/// The MSRs that firmware should configure the same on all packages.
+const PACKAGE_MSRS: &[u32] = &[
+    IA32_BIOS_SIGN_ID,
+    IA32_FEATURE_CONTROL,
+    MSR_PLATFORM_INFO,
+    MSR_PKG_CST_CONFIG_CONTROL,
+    IA32_MISC_ENABLE,
+    MSR_TURBO_RATIO_LIMIT,
+    SYSCFG,
+    VM_CR,
+];
+
+/// Data whose value differs between packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub name: String,
+
+    /// The value of each package, if it is known.
+    pub values: Vec<(u32, Option<String>)>,
+}
+
+/// A name for a MSR.
+fn msr_name(index: u32) -> String {
+    match index {
+        IA32_BIOS_SIGN_ID => format!("MSR {:#x} (microcode revision)", index),
+        _ => match msr::find(index) {
+            Some(msr) => format!("MSR {:#x} ({})", index, msr.title),
+            None => format!("MSR {:#x}", index),
+        },
+    }
+}
+
+/// Compare data between packages.
+///
+/// Packages where the data is unknown don't count as different.
+pub fn mismatches(packages: &[(u32, &dyn CpuInformation)]) -> Vec<Mismatch> {
+    let cpuid = std::iter::once((1, 0, Eax))
+        .chain(FEATURE_REGISTERS.iter().copied())
+        .map(|(leaf, subleaf, register)| {
+            let query = CpuidQuery { leaf, subleaf };
+
+            (
+                format!("CPUID {:#x}.{} {}", leaf, subleaf, register),
+                packages
+                    .iter()
+                    .map(|(package, cpu)| {
+                        let value = cpu.cpuid(query).map(|r| format!("{:#x}", r.get(register)));
+
+                        (*package, value)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        });
+    let msrs = PACKAGE_MSRS.iter().map(|&index| {
+        (
+            msr_name(index),
+            packages
+                .iter()
+                .map(|(package, cpu)| (*package, cpu.rdmsr(index).map(|v| format!("{:#x}", v))))
+                .collect::<Vec<_>>(),
+        )
+    });
+
+    cpuid
+        .chain(msrs)
+        .filter(|(_, values)| {
+            values
+                .iter()
+                .filter_map(|(_, v)| v.as_ref())
+                .collect::<Set<_>>()
+                .len()
+                > 1
+        })
+        .map(|(name, values)| Mismatch { name, values })
+        .collect()
+}
+
+/// Warnings about packages that differ.
+pub fn warnings(cpu_info: &dyn CpuInformation) -> Vec<String> {
+    mismatches(&topology::packages(cpu_info))
+        .into_iter()
+        .map(|m| format!("Packages differ in {}", m.name))
+        .collect()
+}
+
+/// Compare the packages, if the dump contains more than one.
+pub fn section(cpu_info: &dyn CpuInformation) -> Option<Section> {
+    let packages = topology::packages(cpu_info);
+    if packages.len() < 2 {
+        return None;
+    }
+
+    let mut section = Section::new(&format!("Packages ({})", packages.len()));
+
+    if packages
+        .iter()
+        .all(|(_, cpu)| PACKAGE_MSRS.iter().all(|index| cpu.rdmsr(*index).is_none()))
+    {
+        section.row("Per-package MSRs", "not in dump");
+    }
+
+    let mismatches = mismatches(&packages);
+    if mismatches.is_empty() {
+        section.row("Differences", "none");
+    }
+
+    for mismatch in mismatches {
+        let values = mismatch
+            .values
+            .iter()
+            .map(|(package, value)| {
+                format!(
+                    "package {}: {}",
+                    package,
+                    value.as_deref().unwrap_or("unknown")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        section.row(&mismatch.name, values);
+    }
+
+    Some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidResult;
+
+    struct TestCpu {
+        signature: u32,
+        microcode: Option<u64>,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            (query.leaf == 1).then_some(CpuidResult {
+                eax: self.signature,
+                ebx: 0,
+                ecx: 0,
+                edx: 0,
+            })
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            self.microcode.filter(|_| index == IA32_BIOS_SIGN_ID)
+        }
+    }
+
+    #[test]
+    fn differences_are_found() {
+        let cpu0 = TestCpu {
+            signature: 0x50654,
+            microcode: Some(0x200_005e_0000_0000),
+        };
+        let cpu1 = TestCpu {
+            signature: 0x50657,
+            microcode: Some(0x500_002c_0000_0000),
+        };
+
+        let mismatches = mismatches(&[(0, &cpu0), (1, &cpu1)]);
+
+        assert_eq!(
+            mismatches,
+            vec![
+                Mismatch {
+                    name: "CPUID 0x1.0 EAX".to_owned(),
+                    values: vec![
+                        (0, Some("0x50654".to_owned())),
+                        (1, Some("0x50657".to_owned()))
+                    ],
+                },
+                Mismatch {
+                    name: "MSR 0x8b (microcode revision)".to_owned(),
+                    values: vec![
+                        (0, Some("0x200005e00000000".to_owned())),
+                        (1, Some("0x500002c00000000".to_owned()))
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_values_are_not_differences() {
+        let cpu0 = TestCpu {
+            signature: 0x50654,
+            microcode: Some(0x200_005e_0000_0000),
+        };
+        let cpu1 = TestCpu {
+            signature: 0x50654,
+            microcode: None,
+        };
+
+        assert!(mismatches(&[(0, &cpu0), (1, &cpu1)]).is_empty());
+    }
+}
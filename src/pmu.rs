@@ -0,0 +1,149 @@
+//! # Decode the Architectural Performance Monitoring Unit
+//!
+//! CPUID leaf 0xA enumerates the architectural PMU of Intel CPUs: its
+//! version, the number and width of the general-purpose and fixed
+//! counters, and which architectural events are available. EBX lists
+//! the events that are *not* available.
+//!
+//! IA32_PERF_CAPABILITIES describes the formats of LBR and PEBS
+//! records and whether counters can be written with their full width.
+//! It exists if CPUID leaf 1 enumerates PDCM.
+//!
+//! See the Intel SDM Vol. 3B, Chapters 20 and 21.
+
+use crate::cpu_information::{CpuInformation, CpuidResult};
+use crate::features::Bit;
+use crate::msr;
+use crate::report::Section;
+
+/// The CPUID leaf with architectural PMU information.
+pub const CPUID_ARCH_PERFMON: u32 = 0xa;
+
+/// The performance monitoring capabilities.
+pub const IA32_PERF_CAPABILITIES: u32 = 0x345;
+
+/// The architectural events in EBX of CPUID leaf 0xA. A set bit means
+/// the event is not available.
+const ARCH_EVENTS: &[(Bit, &str)] = &[
+    (0, "Core cycles"),
+    (1, "Instructions retired"),
+    (2, "Reference cycles"),
+    (3, "LLC references"),
+    (4, "LLC misses"),
+    (5, "Branch instructions retired"),
+    (6, "Branch mispredicts retired"),
+    (7, "Top-down slots"),
+];
+
+/// Decode CPUID leaf 0xA.
+fn arch_perfmon_section(r: CpuidResult) -> Section {
+    let mut section = Section::new(&format!(
+        "Architectural performance monitoring (CPUID {:#x})",
+        CPUID_ARCH_PERFMON
+    ));
+
+    section.row("Version", r.eax & 0xff);
+    section.row("General-purpose counters", (r.eax >> 8) & 0xff);
+    section.row("General-purpose counter width", (r.eax >> 16) & 0xff);
+
+    // Only the first EAX[31:24] bits of EBX are valid.
+    let events = (r.eax >> 24) & 0xff;
+    for (bit, name) in ARCH_EVENTS {
+        section.row(
+            name,
+            if u32::from(*bit) >= events {
+                "not enumerated"
+            } else if (r.ebx >> bit) & 1 == 0 {
+                "Y"
+            } else {
+                "N"
+            },
+        );
+    }
+
+    // Fixed counters are enumerated from version 2.
+    if r.eax & 0xff >= 2 {
+        section.row("Fixed counters", r.edx & 0x1f);
+        section.row("Fixed counter width", (r.edx >> 5) & 0xff);
+        section.flag("AnyThread deprecated", r.edx.into(), 15);
+    }
+
+    section
+}
+
+/// Decode the PMU information that is available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let query = CPUID_ARCH_PERFMON.into();
+    let mut sections: Vec<Section> = cpu_info
+        .cpuid(query)
+        .filter(|r| cpu_info.is_cpuid_query_valid(query) && r.eax & 0xff != 0)
+        .map(arch_perfmon_section)
+        .into_iter()
+        .collect();
+
+    sections.extend(msr::section(cpu_info, IA32_PERF_CAPABILITIES));
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn arch_perfmon_leaf_is_decoded() {
+        // A Skylake client CPU: version 4, 4 counters of 48 bits and
+        // 3 fixed counters.
+        let section = arch_perfmon_section(CpuidResult {
+            eax: 0x0730_0404,
+            ebx: 0,
+            ecx: 0,
+            edx: 0x0603,
+        });
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Version"), "4");
+        assert_eq!(row("General-purpose counters"), "4");
+        assert_eq!(row("General-purpose counter width"), "48");
+        assert_eq!(row("Branch mispredicts retired"), "Y");
+        assert_eq!(row("Top-down slots"), "not enumerated");
+        assert_eq!(row("Fixed counters"), "3");
+        assert_eq!(row("Fixed counter width"), "48");
+    }
+
+    #[test]
+    fn unavailable_events_are_reported() {
+        let section = arch_perfmon_section(CpuidResult {
+            eax: 0x0728_0201,
+            ebx: 0x4,
+            ecx: 0,
+            edx: 0,
+        });
+
+        assert_eq!(section.get("Reference cycles"), Some("N"));
+        assert_eq!(section.get("Core cycles"), Some("Y"));
+        assert_eq!(section.get("Fixed counters"), None);
+    }
+
+    #[test]
+    fn perf_capabilities_are_decoded() {
+        // An Ice Lake CPU with LBR format 5 and PEBS format 4.
+        let section = msr::find(IA32_PERF_CAPABILITIES)
+            .expect("registered")
+            .section(0xf4c5);
+
+        assert_eq!(section.get("LBR format"), Some("5"));
+        assert_eq!(section.get("PEBS trap"), Some("Y"));
+        assert_eq!(section.get("PEBS saves architectural registers"), Some("Y"));
+        assert_eq!(section.get("PEBS record format"), Some("4"));
+        assert_eq!(section.get("Full-width counter writes"), Some("Y"));
+        assert_eq!(section.get("PEBS baseline"), Some("Y"));
+        assert_eq!(section.get("PEBS output to Intel PT"), Some("N"));
+    }
+
+    #[test]
+    fn events_are_sorted_and_unique() {
+        assert_sorted_unique(ARCH_EVENTS);
+    }
+}
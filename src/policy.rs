@@ -0,0 +1,275 @@
+//! # Host Acceptance Policies
+//!
+//! A policy lists features that a host must have, should have and
+//! must not have. Policies are TOML files:
+//!
+//! ```toml
+//! name = "Virtualization hosts"
+//! required = ["VMX usable", "EPT"]
+//! recommended = ["APICv complete"]
+//! forbidden = ["RTM usable"]
+//! ```
+//!
+//! A host violates the policy if a required feature is not present
+//! or a forbidden feature is not absent. Features that cannot be
+//! decided count as violations, because compliance cannot be shown.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::cpu_information::CpuInformation;
+use crate::features::{Feature, FeatureStatus};
+use crate::report::Section;
+use crate::score::Scoring;
+
+#[derive(Debug)]
+pub enum LoadPolicyErrorKind {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+}
+
+#[derive(Debug)]
+pub struct LoadPolicyError {
+    path: PathBuf,
+    kind: LoadPolicyErrorKind,
+}
+
+impl std::fmt::Display for LoadPolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Failed to load policy from {}: ", self.path.display())?;
+
+        match &self.kind {
+            LoadPolicyErrorKind::Io(e) => write!(f, "{}", e),
+            LoadPolicyErrorKind::Toml(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadPolicyError {}
+
+/// Required, recommended and forbidden features.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default = "default_name")]
+    pub name: String,
+
+    #[serde(default)]
+    pub required: Vec<String>,
+
+    #[serde(default)]
+    pub recommended: Vec<String>,
+
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+
+    /// Weights and tiers for the virtualization score.
+    #[serde(default)]
+    pub score: Option<Scoring>,
+}
+
+fn default_name() -> String {
+    "Policy".to_owned()
+}
+
+/// Load a policy from a TOML file.
+pub fn load_policy(path: &Path) -> Result<Policy, LoadPolicyError> {
+    std::fs::read_to_string(path)
+        .map_err(LoadPolicyErrorKind::Io)
+        .and_then(|input| toml::from_str(&input).map_err(LoadPolicyErrorKind::Toml))
+        .map_err(|kind| LoadPolicyError {
+            path: path.to_owned(),
+            kind,
+        })
+}
+
+/// How a host fails to comply with a policy entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// A required feature is absent or unknown.
+    MissingRequired(FeatureStatus),
+
+    /// A forbidden feature is present or unknown.
+    PresentForbidden(FeatureStatus),
+
+    /// A recommended feature is absent or unknown.
+    MissingRecommended(FeatureStatus),
+}
+
+impl Finding {
+    /// Whether the finding violates the policy.
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, Finding::MissingRecommended(_))
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (status, what) = match self {
+            Finding::MissingRequired(s) => (s, "required"),
+            Finding::PresentForbidden(s) => (s, "forbidden"),
+            Finding::MissingRecommended(s) => (s, "recommended"),
+        };
+
+        let status = match status {
+            FeatureStatus::Present => "present",
+            FeatureStatus::Absent => "absent",
+            FeatureStatus::Unknown { .. } => "unknown",
+            FeatureStatus::NotApplicable => "not applicable",
+        };
+
+        write!(f, "{}, but {}", what, status)
+    }
+}
+
+/// The result of checking a host against a policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compliance {
+    pub policy: String,
+    pub findings: Vec<(String, Finding)>,
+}
+
+impl Compliance {
+    /// Whether the host complies with the policy.
+    pub fn is_compliant(&self) -> bool {
+        !self.findings.iter().any(|(_, f)| f.is_violation())
+    }
+
+    pub fn section(&self) -> Section {
+        let mut section = Section::new(&format!(
+            "Policy {}: {}",
+            self.policy,
+            if self.is_compliant() {
+                "compliant"
+            } else {
+                "VIOLATED"
+            }
+        ));
+
+        for (name, finding) in &self.findings {
+            section.row(name, finding);
+        }
+
+        section
+    }
+}
+
+/// Check a host against a policy.
+///
+/// Features that are not in the catalog are unknown.
+pub fn check(policy: &Policy, catalog: &[Feature], cpu_info: &dyn CpuInformation) -> Compliance {
+    let status = |name: &str| {
+        catalog
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| f.is_present(cpu_info))
+            .unwrap_or(FeatureStatus::Unknown { missing: vec![] })
+    };
+
+    let mut findings = vec![];
+
+    for name in &policy.required {
+        match status(name) {
+            FeatureStatus::Present => {}
+            s => findings.push((name.clone(), Finding::MissingRequired(s))),
+        }
+    }
+
+    for name in &policy.forbidden {
+        match status(name) {
+            FeatureStatus::Absent | FeatureStatus::NotApplicable => {}
+            s => findings.push((name.clone(), Finding::PresentForbidden(s))),
+        }
+    }
+
+    for name in &policy.recommended {
+        match status(name) {
+            FeatureStatus::Present => {}
+            s => findings.push((name.clone(), Finding::MissingRecommended(s))),
+        }
+    }
+
+    Compliance {
+        policy: policy.name.clone(),
+        findings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::BoolExpression::Constant;
+    use crate::features::DEFAULT_CATEGORY;
+    use crate::test_support::NoInformation;
+
+    fn catalog() -> Vec<Feature> {
+        vec![
+            Feature::new(DEFAULT_CATEGORY, "A", Constant(true)),
+            Feature::new(DEFAULT_CATEGORY, "B", Constant(false)),
+        ]
+    }
+
+    #[test]
+    fn policies_are_parsed() {
+        let policy: Policy = toml::from_str(
+            r#"
+required = ["A"]
+forbidden = ["B"]
+"#,
+        )
+        .expect("valid policy");
+
+        assert_eq!(policy.name, "Policy");
+        assert_eq!(policy.required, vec!["A"]);
+        assert!(policy.recommended.is_empty());
+        assert_eq!(policy.forbidden, vec!["B"]);
+
+        assert!(toml::from_str::<Policy>("require = [\"A\"]").is_err());
+    }
+
+    #[test]
+    fn compliant_hosts_pass() {
+        let policy = Policy {
+            name: "Test".to_owned(),
+            required: vec!["A".to_owned()],
+            recommended: vec!["B".to_owned()],
+            forbidden: vec!["B".to_owned()],
+            score: None,
+        };
+
+        let compliance = check(&policy, &catalog(), &NoInformation);
+
+        assert!(compliance.is_compliant());
+        assert_eq!(
+            compliance.findings,
+            vec![(
+                "B".to_owned(),
+                Finding::MissingRecommended(FeatureStatus::Absent)
+            )]
+        );
+    }
+
+    #[test]
+    fn violations_are_found() {
+        let policy = Policy {
+            name: "Test".to_owned(),
+            required: vec!["B".to_owned(), "C".to_owned()],
+            recommended: vec![],
+            forbidden: vec!["A".to_owned()],
+            score: None,
+        };
+
+        let compliance = check(&policy, &catalog(), &NoInformation);
+
+        assert!(!compliance.is_compliant());
+        assert_eq!(
+            compliance.section().rows,
+            vec![
+                ("B".to_owned(), "required, but absent".to_owned()),
+                ("C".to_owned(), "required, but unknown".to_owned()),
+                ("A".to_owned(), "forbidden, but present".to_owned()),
+            ]
+        );
+    }
+}
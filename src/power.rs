@@ -0,0 +1,832 @@
+//! # Decode Power Management
+//!
+//! CPUID leaf 6 enumerates the thermal and power management features
+//! of Intel and AMD CPUs, e.g. the digital thermal sensor, turbo and
+//! Intel's Hardware-Controlled Performance States (HWP). If the dump
+//! contains the HWP MSRs, they show whether HWP is enabled and which
+//! performance levels the CPU offers.
+//!
+//! AMD CPUs expose their frequency configuration in two ways. The
+//! P-state definition MSRs (0xC0010064 and up) list the hardware
+//! P-states with their frequencies. CPUs with Collaborative Processor
+//! Performance Control (CPPC) additionally report abstract
+//! performance levels in CPPC_CAP1.
+//!
+//! On Intel CPUs, MSR_PLATFORM_INFO holds the maximum non-turbo
+//! ratio. Multiplied with the 100 MHz bus clock of Sandy Bridge and
+//! later CPUs, it gives the base frequency.
+//!
+//! MSR_TURBO_RATIO_LIMIT lists the maximum turbo ratio for up to
+//! eight groups of active cores. On CPUs with turbo ratio groups,
+//! MSR_TURBO_RATIO_LIMIT_CORES holds the core count of each group.
+//! Older CPUs use that index for the ratios of more than eight cores
+//! instead, and each group stands for one more active core.
+//!
+//! Running Average Power Limit (RAPL) measures and limits the energy
+//! of several domains, such as the package, DRAM and the cores. Each
+//! domain has its own MSRs, and which ones exist depends on the SKU.
+//! The power, energy and time units of all domains are in a shared
+//! unit MSR.
+//!
+//! See the Intel SDM Vol. 3B, Chapter 15, Vol. 4, and the AMD PPR of the
+//! respective CPU family.
+
+use crate::cpu_information::{CpuInformation, CpuidResult};
+use crate::features::Bit;
+use crate::frequency;
+use crate::microarchitecture;
+use crate::msr;
+use crate::report::Section;
+
+/// The CPUID leaf with thermal and power management features.
+pub const CPUID_THERMAL_POWER: u32 = 0x6;
+
+/// The bit in CPUID leaf 6 EAX that enumerates HWP.
+pub const HWP: Bit = 7;
+
+/// The bit in CPUID leaf 6 EAX that enumerates HDC.
+pub const HDC: Bit = 13;
+
+/// The features in EAX of CPUID leaf 6.
+const THERMAL_POWER_FEATURES: &[(Bit, &str)] = &[
+    (0, "Digital temperature sensor"),
+    (1, "Turbo Boost"),
+    (2, "ARAT"),
+    (4, "Power limit notification"),
+    (5, "Clock modulation duty cycle extension"),
+    (6, "Package thermal management"),
+    (7, "HWP"),
+    (8, "HWP notification"),
+    (9, "HWP activity window"),
+    (10, "HWP energy performance preference"),
+    (11, "HWP package level request"),
+    (13, "HDC"),
+    (14, "Turbo Boost Max 3.0"),
+    (15, "HWP highest performance change"),
+    (16, "HWP PECI override"),
+    (17, "Flexible HWP"),
+    (18, "Fast HWP request MSR"),
+    (19, "Hardware feedback interface"),
+    (20, "HWP ignores idle logical processors"),
+    (23, "Thread Director"),
+];
+
+/// The features in ECX of CPUID leaf 6.
+const THERMAL_POWER_ECX_FEATURES: &[(Bit, &str)] =
+    &[(0, "APERF/MPERF"), (3, "Energy performance bias")];
+
+/// The MSR that enables HWP.
+pub const IA32_PM_ENABLE: u32 = 0x770;
+
+/// The HWP performance levels.
+pub const IA32_HWP_CAPABILITIES: u32 = 0x771;
+
+/// The HWP request of the logical processor.
+pub const IA32_HWP_REQUEST: u32 = 0x774;
+
+/// The energy performance bias hint.
+pub const IA32_ENERGY_PERF_BIAS: u32 = 0x1b0;
+
+/// The bit in CPUID leaf 6 ECX that enumerates the energy
+/// performance bias.
+pub const ENERGY_PERF_BIAS: Bit = 3;
+
+/// The actual performance clock counter.
+pub const IA32_APERF: u32 = 0xe8;
+
+/// The maximum performance clock counter.
+pub const IA32_MPERF: u32 = 0xe7;
+
+/// The productive performance counter of Intel CPUs. It has no CPUID
+/// bit, so only the dump tells whether it exists.
+pub const MSR_PPERF: u32 = 0x64e;
+
+/// The HWP status MSR with the feedback on guaranteed performance
+/// changes.
+pub const IA32_HWP_STATUS: u32 = 0x777;
+
+/// The bit in CPUID leaf 6 ECX that enumerates APERF and MPERF.
+pub const APERF_MPERF: Bit = 0;
+
+/// The bit in CPUID leaf 6 EAX that enumerates the hardware feedback
+/// interface.
+pub const HARDWARE_FEEDBACK: Bit = 19;
+
+/// The MSR that enables HDC for the package.
+pub const IA32_PKG_HDC_CTL: u32 = 0xdb0;
+
+/// The thermal status of the core.
+pub const IA32_THERM_STATUS: u32 = 0x19c;
+
+/// The Intel MSR with the non-turbo ratios.
+pub const MSR_PLATFORM_INFO: u32 = 0xce;
+
+/// The bus clock that ratios are multiplied with, in MHz.
+pub const BUS_CLOCK_MHZ: u64 = 100;
+
+/// The maximum turbo ratios by active core count.
+pub const MSR_TURBO_RATIO_LIMIT: u32 = 0x1ad;
+
+/// The core counts of the turbo ratio groups.
+pub const MSR_TURBO_RATIO_LIMIT_CORES: u32 = 0x1ae;
+
+/// The Intel RAPL unit MSR.
+pub const MSR_RAPL_POWER_UNIT: u32 = 0x606;
+
+/// The Intel package power information MSR with the thermal spec
+/// power.
+pub const MSR_PKG_POWER_INFO: u32 = 0x614;
+
+/// The AMD RAPL unit MSR.
+pub const AMD_RAPL_POWER_UNIT: u32 = 0xc001_0299;
+
+/// A RAPL domain and the MSRs that belong to it.
+type RaplDomain = (&'static str, &'static [u32]);
+
+/// The Intel RAPL domains.
+const INTEL_RAPL_DOMAINS: &[RaplDomain] = &[
+    ("Package", &[0x610, 0x611, 0x613, MSR_PKG_POWER_INFO]),
+    ("DRAM", &[0x618, 0x619, 0x61b, 0x61c]),
+    ("PP0 (cores)", &[0x638, 0x639, 0x63a, 0x63b]),
+    ("PP1 (graphics)", &[0x640, 0x641, 0x642]),
+    ("Platform (PSYS)", &[0x64d, 0x65c]),
+];
+
+/// The AMD RAPL domains.
+const AMD_RAPL_DOMAINS: &[RaplDomain] = &[("Core", &[0xc001_029a]), ("Package", &[0xc001_029b])];
+
+/// The P-state current limit MSR.
+pub const PSTATE_CURRENT_LIMIT: u32 = 0xc001_0061;
+
+/// The P-state status MSR.
+pub const PSTATE_STATUS: u32 = 0xc001_0063;
+
+/// The first P-state definition MSR.
+pub const PSTATE_DEF_BASE: u32 = 0xc001_0064;
+
+/// The number of P-state definition MSRs.
+pub const PSTATE_DEF_COUNT: u32 = 8;
+
+/// The CPPC capability MSR.
+pub const CPPC_CAP1: u32 = 0xc001_02b0;
+
+/// The CPPC enable MSR.
+pub const CPPC_ENABLE: u32 = 0xc001_02b1;
+
+/// Decode CPUID leaf 6.
+fn thermal_power_section(r: CpuidResult, therm_status: Option<u64>) -> Section {
+    let mut section = Section::new(&format!(
+        "Thermal and power management (CPUID {:#x})",
+        CPUID_THERMAL_POWER
+    ));
+
+    for (bit, name) in THERMAL_POWER_FEATURES {
+        section.flag(name, r.eax.into(), *bit);
+    }
+    for (bit, name) in THERMAL_POWER_ECX_FEATURES {
+        section.flag(name, r.ecx.into(), *bit);
+    }
+
+    section.row("Thermal interrupt thresholds", r.ebx & 0xf);
+    if (r.eax >> 23) & 1 == 1 {
+        section.row("Thread Director classes", (r.ecx >> 8) & 0xff);
+    }
+
+    // The readout is only valid if bit 31 is set.
+    if let Some(status) = therm_status.filter(|s| s & (1 << 31) != 0) {
+        section.row(
+            "Temperature",
+            format!("{} °C below TjMax", (status >> 16) & 0x7f),
+        );
+    }
+
+    section
+}
+
+/// The HWP activity window, which is encoded as a 7-bit mantissa and
+/// a 3-bit exponent of 10 in microseconds.
+fn activity_window(window: u64) -> String {
+    if window == 0 {
+        "chosen by hardware".to_owned()
+    } else {
+        format!("{} µs", (window & 0x7f) * 10u64.pow((window >> 7) as u32))
+    }
+}
+
+/// Report the frequency feedback interfaces.
+///
+/// `read` returns the value of a MSR if the dump contains it. With
+/// APERF, MPERF and the base frequency, this also shows the average
+/// frequency since the counters were reset.
+fn feedback_section(
+    leaf6: CpuidResult,
+    read: &dyn Fn(u32) -> Option<u64>,
+    base_frequency: Option<u64>,
+) -> Section {
+    let mut section = Section::new("Frequency feedback");
+    let enumerated = |set: bool, index: u32| match (set, read(index).is_some()) {
+        (false, _) => "N",
+        (true, true) => "Y",
+        (true, false) => "Y (not in dump)",
+    };
+
+    let aperf_mperf = (leaf6.ecx >> APERF_MPERF) & 1 == 1;
+    section.row("APERF/MPERF", enumerated(aperf_mperf, IA32_APERF));
+    section.row(
+        "PPERF",
+        if read(MSR_PPERF).is_some() {
+            "Y"
+        } else {
+            "not in dump"
+        },
+    );
+    section.row(
+        "HWP feedback (IA32_HWP_STATUS)",
+        enumerated((leaf6.eax >> HWP) & 1 == 1, IA32_HWP_STATUS),
+    );
+    section.flag(
+        "Hardware feedback interface",
+        leaf6.eax.into(),
+        HARDWARE_FEEDBACK,
+    );
+
+    if let (Some(aperf), Some(mperf), Some(base)) =
+        (read(IA32_APERF), read(IA32_MPERF), base_frequency)
+    {
+        let mhz = (u128::from(aperf) * u128::from(base)).checked_div(u128::from(mperf));
+
+        if let Some(mhz) = mhz.filter(|_| aperf_mperf) {
+            section.row("Average frequency since reset", format!("{} MHz", mhz));
+        }
+    }
+
+    section
+}
+
+/// Decode the HWP and HDC MSRs.
+fn hwp_section(
+    pm_enable: Option<u64>,
+    capabilities: Option<u64>,
+    request: Option<u64>,
+    hdc_ctl: Option<u64>,
+) -> Section {
+    let mut section = Section::new(&format!("HWP (MSR {:#x})", IA32_PM_ENABLE));
+
+    if let Some(pm_enable) = pm_enable {
+        section.flag("HWP enabled", pm_enable, 0);
+    }
+
+    if let Some(capabilities) = capabilities {
+        section.row("Highest performance", capabilities & 0xff);
+        section.row("Guaranteed performance", (capabilities >> 8) & 0xff);
+        section.row("Most efficient performance", (capabilities >> 16) & 0xff);
+        section.row("Lowest performance", (capabilities >> 24) & 0xff);
+    }
+
+    if let Some(request) = request {
+        section.row("Requested minimum performance", request & 0xff);
+        section.row("Requested maximum performance", (request >> 8) & 0xff);
+        section.row("Desired performance", (request >> 16) & 0xff);
+        section.row("Energy performance preference", (request >> 24) & 0xff);
+        section.row("Activity window", activity_window((request >> 32) & 0x3ff));
+        section.flag("Package-level request", request, 42);
+    }
+
+    if let Some(hdc_ctl) = hdc_ctl {
+        section.flag("HDC enabled", hdc_ctl, 0);
+    }
+
+    section
+}
+
+/// The frequency of a P-state definition in MHz.
+///
+/// This uses the encoding of family 17h to 19h CPUs, where the
+/// frequency is `200 MHz * FID / DID`. Returns `None` if the divisor
+/// is zero.
+fn pstate_frequency(def: u64) -> Option<u64> {
+    let fid = def & 0xff;
+    let did = (def >> 8) & 0x3f;
+
+    (did != 0).then(|| fid * 200 / did)
+}
+
+/// The CPU families whose P-state definitions `pstate_frequency`
+/// can decode.
+const PSTATE_FID_DID_FAMILIES: std::ops::RangeInclusive<u32> = 0x17..=0x19;
+
+/// Decode the P-state MSRs.
+///
+/// The frequencies are only decoded for `family` 17h to 19h. Other
+/// families encode them differently, so their raw value is shown.
+fn pstate_section(
+    family: Option<u32>,
+    limit: Option<u64>,
+    status: Option<u64>,
+    defs: &[Option<u64>],
+) -> Section {
+    let mut section = Section::new(&format!("AMD P-states (MSR {:#x})", PSTATE_DEF_BASE));
+
+    if let Some(limit) = limit {
+        section.row("Current P-state limit", format!("P{}", limit & 0x7));
+        section.row("Maximum P-state", format!("P{}", (limit >> 4) & 0x7));
+    }
+
+    if let Some(status) = status {
+        section.row("Current P-state", format!("P{}", status & 0x7));
+    }
+
+    let decodable = family.is_some_and(|f| PSTATE_FID_DID_FAMILIES.contains(&f));
+    for (i, def) in defs.iter().enumerate() {
+        if let Some(def) = def.filter(|d| d & (1 << 63) != 0) {
+            section.row(
+                &format!("P{}", i),
+                match pstate_frequency(def).filter(|_| decodable) {
+                    Some(mhz) => format!("{} MHz", mhz),
+                    None if decodable => "invalid divisor".to_owned(),
+                    None => format!("{:#x}", def),
+                },
+            );
+        }
+    }
+
+    section
+}
+
+/// Decode CPPC_CAP1 and CPPC_ENABLE.
+fn cppc_section(capability: u64, enable: Option<u64>) -> Section {
+    let mut section = Section::new(&format!("AMD CPPC capabilities (MSR {:#x})", CPPC_CAP1));
+
+    section.row("Highest performance", (capability >> 24) & 0xff);
+    section.row("Nominal performance", (capability >> 16) & 0xff);
+    section.row("Lowest nonlinear performance", (capability >> 8) & 0xff);
+    section.row("Lowest performance", capability & 0xff);
+
+    if let Some(enable) = enable {
+        section.flag("CPPC enabled", enable, 0);
+    }
+
+    section
+}
+
+/// The base frequency in MHz from MSR_PLATFORM_INFO.
+///
+/// Returns `None` if the MSR is missing or the ratio is zero.
+pub fn base_frequency(cpu_info: &dyn CpuInformation) -> Option<u64> {
+    cpu_info
+        .rdmsr(MSR_PLATFORM_INFO)
+        .map(|info| (info >> 8) & 0xff)
+        .filter(|ratio| *ratio != 0)
+        .map(|ratio| ratio * BUS_CLOCK_MHZ)
+}
+
+/// The maximum turbo ratio for each active core count.
+///
+/// `cores` is only used if it looks like a list of core counts,
+/// i.e. its non-zero bytes are strictly increasing. Groups with a
+/// ratio of zero are skipped.
+fn turbo_ratios(limit: u64, cores: Option<u64>) -> Vec<(u64, u64)> {
+    let bytes = |value: u64| (0..8).map(move |i| (value >> (i * 8)) & 0xff);
+    let is_counts = |value: u64| {
+        let counts: Vec<u64> = bytes(value).take_while(|c| *c != 0).collect();
+
+        !counts.is_empty() && counts.windows(2).all(|w| w[0] < w[1])
+    };
+    let counts: Vec<u64> = match cores.filter(|c| is_counts(*c)) {
+        Some(cores) => bytes(cores).collect(),
+        None => (1..=8).collect(),
+    };
+
+    counts
+        .into_iter()
+        .zip(bytes(limit))
+        .filter(|(count, ratio)| *count != 0 && *ratio != 0)
+        .collect()
+}
+
+/// Decode MSR_TURBO_RATIO_LIMIT.
+fn turbo_section(limit: u64, cores: Option<u64>) -> Section {
+    let mut section = Section::new(&format!(
+        "Turbo ratio limits (MSR {:#x})",
+        MSR_TURBO_RATIO_LIMIT
+    ));
+
+    for (count, ratio) in turbo_ratios(limit, cores) {
+        let name = if count == 1 {
+            "1 active core".to_owned()
+        } else {
+            format!("{} active cores", count)
+        };
+
+        section.row(&name, format!("{} ({} MHz)", ratio, ratio * BUS_CLOCK_MHZ));
+    }
+
+    section
+}
+
+/// Format `1 / 2^exponent` of a unit with a metric prefix.
+fn rapl_unit(exponent: u64, unit: &str) -> String {
+    let value = 1.0 / (1u64 << exponent) as f64;
+
+    if value >= 1e-3 {
+        format!("{} m{}", value * 1e3, unit)
+    } else {
+        format!("{:.3} µ{}", value * 1e6, unit)
+    }
+}
+
+/// Decode the RAPL units and list the domains.
+///
+/// `domains` holds the name of each domain and whether any of its
+/// MSRs is in the dump.
+fn rapl_section(
+    index: u32,
+    units: u64,
+    domains: &[(&str, bool)],
+    power_info: Option<u64>,
+) -> Section {
+    let mut section = Section::new(&format!("RAPL (MSR {:#x})", index));
+
+    section.row("Power unit", rapl_unit(units & 0xf, "W"));
+    section.row("Energy unit", rapl_unit((units >> 8) & 0x1f, "J"));
+    section.row("Time unit", rapl_unit((units >> 16) & 0xf, "s"));
+
+    for (name, present) in domains {
+        section.row(
+            &format!("{} domain", name),
+            if *present { "Y" } else { "N" },
+        );
+    }
+
+    if let Some(info) = power_info {
+        let watts = (info & 0x7fff) as f64 / (1u64 << (units & 0xf)) as f64;
+
+        section.row("Package thermal spec power", format!("{} W", watts));
+    }
+
+    section
+}
+
+/// Decode all power management information that is available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let mut sections: Vec<Section> = msr::section(cpu_info, MSR_PLATFORM_INFO)
+        .into_iter()
+        .collect();
+
+    let leaf6 = cpu_info
+        .cpuid(CPUID_THERMAL_POWER.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(CPUID_THERMAL_POWER.into()));
+    if let Some(r) = leaf6 {
+        let digital_sensor = r.eax & 1 == 1;
+        let therm_status = cpu_info.rdmsr(IA32_THERM_STATUS).filter(|_| digital_sensor);
+
+        sections.push(thermal_power_section(r, therm_status));
+
+        // Only read MSRs that the CPU enumerates.
+        let hwp = (r.eax >> HWP) & 1 == 1;
+        let hdc = (r.eax >> HDC) & 1 == 1;
+        let hwp_msr = |index| cpu_info.rdmsr(index).filter(|_| hwp);
+        let msrs = (
+            hwp_msr(IA32_PM_ENABLE),
+            hwp_msr(IA32_HWP_CAPABILITIES),
+            hwp_msr(IA32_HWP_REQUEST),
+            cpu_info.rdmsr(IA32_PKG_HDC_CTL).filter(|_| hdc),
+        );
+
+        if msrs != (None, None, None, None) {
+            sections.push(hwp_section(msrs.0, msrs.1, msrs.2, msrs.3));
+        }
+
+        if (r.ecx >> ENERGY_PERF_BIAS) & 1 == 1 {
+            sections.extend(msr::section(cpu_info, IA32_ENERGY_PERF_BIAS));
+        }
+
+        sections.push(feedback_section(
+            r,
+            &|index| cpu_info.rdmsr(index),
+            frequency::frequencies(cpu_info).base,
+        ));
+    }
+
+    if let Some(limit) = cpu_info.rdmsr(MSR_TURBO_RATIO_LIMIT) {
+        sections.push(turbo_section(
+            limit,
+            cpu_info.rdmsr(MSR_TURBO_RATIO_LIMIT_CORES),
+        ));
+    }
+
+    for (index, domains) in [
+        (MSR_RAPL_POWER_UNIT, INTEL_RAPL_DOMAINS),
+        (AMD_RAPL_POWER_UNIT, AMD_RAPL_DOMAINS),
+    ] {
+        if let Some(units) = cpu_info.rdmsr(index) {
+            let present: Vec<(&str, bool)> = domains
+                .iter()
+                .map(|(name, msrs)| (*name, msrs.iter().any(|m| cpu_info.rdmsr(*m).is_some())))
+                .collect();
+            let power_info = cpu_info
+                .rdmsr(MSR_PKG_POWER_INFO)
+                .filter(|_| index == MSR_RAPL_POWER_UNIT);
+
+            sections.push(rapl_section(index, units, &present, power_info));
+        }
+    }
+
+    let defs: Vec<Option<u64>> = (0..PSTATE_DEF_COUNT)
+        .map(|i| cpu_info.rdmsr(PSTATE_DEF_BASE + i))
+        .collect();
+    if defs.iter().any(Option::is_some) {
+        sections.push(pstate_section(
+            microarchitecture::signature(cpu_info).map(|s| s.family),
+            cpu_info.rdmsr(PSTATE_CURRENT_LIMIT),
+            cpu_info.rdmsr(PSTATE_STATUS),
+            &defs,
+        ));
+    }
+
+    if let Some(capability) = cpu_info.rdmsr(CPPC_CAP1) {
+        sections.push(cppc_section(capability, cpu_info.rdmsr(CPPC_ENABLE)));
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidQuery;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn thermal_power_leaf_is_decoded() {
+        // A Skylake client CPU with HWP, 59 °C below TjMax.
+        let section = thermal_power_section(
+            CpuidResult {
+                eax: 0x0000_27f7,
+                ebx: 0x2,
+                ecx: 0x9,
+                edx: 0,
+            },
+            Some(0x883b_0000),
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Digital temperature sensor"), "Y");
+        assert_eq!(row("Turbo Boost"), "Y");
+        assert_eq!(row("ARAT"), "Y");
+        assert_eq!(row("HWP"), "Y");
+        assert_eq!(row("HWP energy performance preference"), "Y");
+        assert_eq!(row("HDC"), "Y");
+        assert_eq!(row("Turbo Boost Max 3.0"), "N");
+        assert_eq!(row("APERF/MPERF"), "Y");
+        assert_eq!(row("Energy performance bias"), "Y");
+        assert_eq!(row("Thermal interrupt thresholds"), "2");
+        assert_eq!(row("Temperature"), "59 °C below TjMax");
+        assert_eq!(section.get("Thread Director classes"), None);
+    }
+
+    #[test]
+    fn hwp_msrs_are_decoded() {
+        let request = 0x0000_0583_8000_2c01;
+        let section = hwp_section(Some(1), Some(0x0109_2a2c), Some(request), None);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("HWP enabled"), "Y");
+        assert_eq!(row("Highest performance"), "44");
+        assert_eq!(row("Guaranteed performance"), "42");
+        assert_eq!(row("Most efficient performance"), "9");
+        assert_eq!(row("Lowest performance"), "1");
+        assert_eq!(row("Requested minimum performance"), "1");
+        assert_eq!(row("Requested maximum performance"), "44");
+        assert_eq!(row("Energy performance preference"), "128");
+        assert_eq!(row("Activity window"), "3000 µs");
+        assert_eq!(row("Package-level request"), "Y");
+        assert_eq!(section.get("HDC enabled"), None);
+    }
+
+    #[test]
+    fn frequency_feedback_is_reported() {
+        let leaf6 = CpuidResult {
+            eax: 1 << HWP,
+            ebx: 0,
+            ecx: 1 << APERF_MPERF,
+            edx: 0,
+        };
+        let read = |index| match index {
+            IA32_APERF => Some(3_300_000),
+            IA32_MPERF => Some(3_000_000),
+            _ => None,
+        };
+
+        let section = feedback_section(leaf6, &read, Some(3000));
+
+        assert_eq!(section.get("APERF/MPERF"), Some("Y"));
+        assert_eq!(section.get("PPERF"), Some("not in dump"));
+        assert_eq!(
+            section.get("HWP feedback (IA32_HWP_STATUS)"),
+            Some("Y (not in dump)")
+        );
+        assert_eq!(section.get("Hardware feedback interface"), Some("N"));
+        assert_eq!(
+            section.get("Average frequency since reset"),
+            Some("3300 MHz")
+        );
+    }
+
+    #[test]
+    fn activity_window_is_decoded() {
+        assert_eq!(activity_window(0), "chosen by hardware");
+        assert_eq!(activity_window(0x03), "3 µs");
+        assert_eq!(activity_window(0x103), "300 µs");
+    }
+
+    #[test]
+    fn energy_performance_bias_is_decoded() {
+        let section = msr::find(IA32_ENERGY_PERF_BIAS)
+            .expect("registered")
+            .section(6);
+
+        assert_eq!(
+            section.get("Bias (0 = performance, 15 = energy saving)"),
+            Some("6")
+        );
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(THERMAL_POWER_FEATURES);
+        assert_sorted_unique(THERMAL_POWER_ECX_FEATURES);
+    }
+
+    #[test]
+    fn pstates_are_decoded() {
+        let section = pstate_section(
+            Some(0x19),
+            Some(0x20),
+            Some(0x1),
+            &[
+                // 3800 MHz
+                Some(0x8000_0000_4814_0898),
+                // 2800 MHz
+                Some(0x8000_0000_4414_0870),
+                // Disabled
+                Some(0x0000_0000_4414_0870),
+                None,
+            ],
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Current P-state limit"), "P0");
+        assert_eq!(row("Maximum P-state"), "P2");
+        assert_eq!(row("Current P-state"), "P1");
+        assert_eq!(row("P0"), "3800 MHz");
+        assert_eq!(row("P1"), "2800 MHz");
+        assert_eq!(section.get("P2"), None);
+    }
+
+    #[test]
+    fn other_families_show_raw_pstates() {
+        let def = Some(0x8000_0000_4814_0898);
+
+        for family in [Some(0x1a), Some(0x15), None] {
+            let section = pstate_section(family, None, None, &[def]);
+
+            assert_eq!(
+                section.get("P0"),
+                Some("0x8000000048140898"),
+                "{:?}",
+                family
+            );
+        }
+    }
+
+    #[test]
+    fn platform_info_is_decoded() {
+        // A Core i7-8700 with a base frequency of 3.2 GHz.
+        let info = 0x0804_0830_f081_2000;
+        let section = msr::find(MSR_PLATFORM_INFO)
+            .expect("registered")
+            .section(info);
+
+        assert_eq!(section.get("Maximum non-turbo ratio"), Some("32"));
+        assert_eq!(
+            section.get("Programmable ratio limits for turbo"),
+            Some("Y")
+        );
+        assert_eq!(section.get("Programmable TDP limits for turbo"), Some("Y"));
+        assert_eq!(section.get("Low power mode"), Some("N"));
+        assert_eq!(section.get("Configurable TDP levels"), Some("0"));
+        assert_eq!(section.get("Maximum efficiency ratio"), Some("8"));
+        assert_eq!(section.get("Minimum operating ratio"), Some("4"));
+
+        struct TestCpu(u64);
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, _query: CpuidQuery) -> Option<CpuidResult> {
+                None
+            }
+
+            fn rdmsr(&self, index: u32) -> Option<u64> {
+                (index == MSR_PLATFORM_INFO).then_some(self.0)
+            }
+        }
+
+        assert_eq!(base_frequency(&TestCpu(info)), Some(3200));
+        assert_eq!(base_frequency(&TestCpu(0)), None);
+    }
+
+    #[test]
+    fn turbo_ratios_are_decoded() {
+        // A Core i7-8700 with six cores.
+        let section = turbo_section(0x2b2b_2b2c_2d2e_2e2e, None);
+
+        assert_eq!(section.get("1 active core"), Some("46 (4600 MHz)"));
+        assert_eq!(section.get("4 active cores"), Some("45 (4500 MHz)"));
+        assert_eq!(section.get("6 active cores"), Some("43 (4300 MHz)"));
+        assert_eq!(section.rows.len(), 8);
+    }
+
+    #[test]
+    fn turbo_ratio_groups_are_decoded() {
+        let groups = Some(0x3c38_3020_1810_0402);
+
+        assert_eq!(
+            turbo_ratios(0x1818_191a_1b1c_1d1e, groups)[..3],
+            [(2, 30), (4, 29), (16, 28)]
+        );
+        assert_eq!(turbo_ratios(0x1e, groups), vec![(2, 30)]);
+    }
+
+    #[test]
+    fn legacy_ratios_above_eight_cores_are_not_core_counts() {
+        // MSR 0x1AE of a Haswell-EP CPU holds ratios for 9 to 16 cores.
+        assert_eq!(
+            turbo_ratios(0x1e1e, Some(0x1a1a_1a1a_1b1b_1b1b)),
+            vec![(1, 30), (2, 30)]
+        );
+    }
+
+    #[test]
+    fn rapl_is_decoded() {
+        // A Skylake client CPU with a 65 W TDP.
+        let section = rapl_section(
+            MSR_RAPL_POWER_UNIT,
+            0x000a_0e03,
+            &[("Package", true), ("DRAM", false)],
+            Some(0x208),
+        );
+
+        assert_eq!(section.get("Power unit"), Some("125 mW"));
+        assert_eq!(section.get("Energy unit"), Some("61.035 µJ"));
+        assert_eq!(section.get("Time unit"), Some("976.562 µs"));
+        assert_eq!(section.get("Package domain"), Some("Y"));
+        assert_eq!(section.get("DRAM domain"), Some("N"));
+        assert_eq!(section.get("Package thermal spec power"), Some("65 W"));
+    }
+
+    #[test]
+    fn psys_domain_needs_its_own_msrs() {
+        struct TestCpu(u32);
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, _query: CpuidQuery) -> Option<CpuidResult> {
+                None
+            }
+
+            fn rdmsr(&self, index: u32) -> Option<u64> {
+                [MSR_RAPL_POWER_UNIT, self.0]
+                    .contains(&index)
+                    .then_some(0x000a_0e03)
+            }
+        }
+
+        let psys = |cpu: &TestCpu| {
+            sections(cpu)
+                .into_iter()
+                .find(|s| s.title == "RAPL (MSR 0x606)")
+                .and_then(|s| s.get("Platform (PSYS) domain").map(str::to_owned))
+        };
+
+        // MSR_TURBO_ACTIVATION_RATIO
+        assert_eq!(psys(&TestCpu(0x64c)).as_deref(), Some("N"));
+        // MSR_PLATFORM_ENERGY_STATUS
+        assert_eq!(psys(&TestCpu(0x64d)).as_deref(), Some("Y"));
+    }
+
+    #[test]
+    fn pstate_frequency_rejects_zero_divisor() {
+        assert_eq!(pstate_frequency(0x8000_0000_0000_0098), None);
+    }
+
+    #[test]
+    fn cppc_capabilities_are_decoded() {
+        let section = cppc_section(0xa6_6c_31_11, Some(1));
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Highest performance"), "166");
+        assert_eq!(row("Nominal performance"), "108");
+        assert_eq!(row("Lowest nonlinear performance"), "49");
+        assert_eq!(row("Lowest performance"), "17");
+        assert_eq!(row("CPPC enabled"), "Y");
+    }
+}
@@ -0,0 +1,166 @@
+//! # Decode Intel Processor Trace Capabilities
+//!
+//! CPUID leaf 0x14 enumerates the optional parts of Intel Processor
+//! Trace (PT): the packet types and filters in subleaf 0 EBX, the
+//! output schemes in subleaf 0 ECX, and the number of address ranges
+//! and the supported encodings for MTC period, cycle threshold and PSB
+//! frequency in subleaf 1.
+//!
+//! See the Intel SDM Vol. 3C, Chapter 33.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The CPUID leaf with Processor Trace capabilities.
+pub const CPUID_PROCESSOR_TRACE: u32 = 0x14;
+
+/// The bit in CPUID leaf 7 EBX that enumerates Processor Trace.
+const INTEL_PT: Bit = 25;
+
+/// The trace features in subleaf 0 EBX.
+const TRACE_FEATURES: &[(Bit, &str)] = &[
+    (0, "CR3 filtering"),
+    (1, "Configurable PSB and cycle-accurate mode"),
+    (2, "IP filtering, TraceStop and MSR preservation"),
+    (3, "MTC timing packets"),
+    (4, "PTWRITE"),
+    (5, "Power event trace"),
+    (6, "PSB and PMI preservation"),
+    (7, "Event trace"),
+    (8, "TNT disable"),
+];
+
+/// The output schemes in subleaf 0 ECX.
+const OUTPUT_FEATURES: &[(Bit, &str)] = &[
+    (0, "ToPA output"),
+    (1, "ToPA tables with multiple output entries"),
+    (2, "Single-range output"),
+    (3, "Trace transport subsystem output"),
+    (31, "IP payloads are LIP"),
+];
+
+/// Format a bitmap of supported encodings as a list of values.
+fn encodings(bitmap: u32) -> String {
+    let values: Vec<String> = (0..16)
+        .filter(|bit| (bitmap >> bit) & 1 == 1)
+        .map(|bit| bit.to_string())
+        .collect();
+
+    if values.is_empty() {
+        "none".to_owned()
+    } else {
+        values.join(", ")
+    }
+}
+
+/// Decode CPUID leaf 0x14.
+fn trace_section(subleaf0: CpuidResult, subleaf1: Option<CpuidResult>) -> Section {
+    let mut section = Section::new(&format!(
+        "Processor Trace (CPUID {:#x})",
+        CPUID_PROCESSOR_TRACE
+    ));
+
+    for (bit, name) in TRACE_FEATURES {
+        section.flag(name, subleaf0.ebx.into(), *bit);
+    }
+    for (bit, name) in OUTPUT_FEATURES {
+        section.flag(name, subleaf0.ecx.into(), *bit);
+    }
+
+    if let Some(r) = subleaf1 {
+        section.row("Address ranges", r.eax & 0x7);
+        section.row("MTC period encodings", encodings(r.eax >> 16));
+        section.row("Cycle threshold encodings", encodings(r.ebx & 0xffff));
+        section.row("PSB frequency encodings", encodings(r.ebx >> 16));
+    }
+
+    section
+}
+
+/// Decode Processor Trace capabilities, if the CPU supports it.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let query = |subleaf| CpuidQuery {
+        leaf: CPUID_PROCESSOR_TRACE,
+        subleaf,
+    };
+    let supported = cpu_info.is_cpuid_query_valid(7.into())
+        && cpu_info
+            .cpuid(7.into())
+            .is_some_and(|r| (r.ebx >> INTEL_PT) & 1 == 1);
+
+    cpu_info
+        .cpuid(query(0))
+        .filter(|_| supported && cpu_info.is_cpuid_query_valid(query(0)))
+        .map(|r| {
+            let subleaf1 = (r.eax >= 1).then(|| cpu_info.cpuid(query(1))).flatten();
+
+            trace_section(r, subleaf1)
+        })
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn leaf_is_decoded() {
+        // A Skylake client CPU.
+        let section = trace_section(
+            CpuidResult {
+                eax: 1,
+                ebx: 0x5f,
+                ecx: 0x8000_0007,
+                edx: 0,
+            },
+            Some(CpuidResult {
+                eax: 0x0249_0002,
+                ebx: 0x003f_3fff,
+                ecx: 0,
+                edx: 0,
+            }),
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Configurable PSB and cycle-accurate mode"), "Y");
+        assert_eq!(row("PTWRITE"), "Y");
+        assert_eq!(row("Power event trace"), "N");
+        assert_eq!(row("ToPA output"), "Y");
+        assert_eq!(row("ToPA tables with multiple output entries"), "Y");
+        assert_eq!(row("Trace transport subsystem output"), "N");
+        assert_eq!(row("IP payloads are LIP"), "Y");
+        assert_eq!(row("Address ranges"), "2");
+        assert_eq!(row("MTC period encodings"), "0, 3, 6, 9");
+        assert_eq!(row("PSB frequency encodings"), "0, 1, 2, 3, 4, 5");
+    }
+
+    #[test]
+    fn subleaf_1_is_optional() {
+        let section = trace_section(
+            CpuidResult {
+                eax: 0,
+                ebx: 0,
+                ecx: 0,
+                edx: 0,
+            },
+            None,
+        );
+
+        assert_eq!(section.get("Address ranges"), None);
+    }
+
+    #[test]
+    fn encodings_are_listed() {
+        assert_eq!(encodings(0), "none");
+        assert_eq!(encodings(0x8001), "0, 15");
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(TRACE_FEATURES);
+        assert_sorted_unique(OUTPUT_FEATURES);
+    }
+}
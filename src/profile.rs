@@ -0,0 +1,338 @@
+//! # Readiness Profiles
+//!
+//! A profile is a named set of features that a particular use case
+//! needs. Checking a profile answers whether a CPU is suitable for
+//! that use case and, if not, which features are in the way.
+//!
+//! Each requirement lists one or more alternatives, so a profile can
+//! cover Intel and AMD CPUs alike: `&["EPT", "Nested paging"]` is met
+//! by either feature. Alternatives that don't apply to the CPU's
+//! vendor are ignored. If none of them applies, a required feature
+//! fails the profile, e.g. nested VMX on an AMD CPU, while a
+//! recommended one is skipped.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::{Feature, FeatureStatus};
+use crate::report::Section;
+
+/// Features of which at least one must be present.
+pub type Requirement = &'static [&'static str];
+
+/// A named set of required and recommended features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub name: &'static str,
+    pub description: &'static str,
+
+    /// Features without which the profile fails.
+    pub required: &'static [Requirement],
+
+    /// Features that are reported if missing, but don't fail the
+    /// profile.
+    pub recommended: &'static [Requirement],
+}
+
+const VIRTUALIZATION: &[Requirement] = &[&["VMX", "SVM"], &["VMX usable", "SVM usable"]];
+
+/// The built-in profiles.
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "nested-kvm",
+        description: "KVM nested VMX",
+        required: &[
+            &["VMX"],
+            &["VMX usable"],
+            &["EPT"],
+            &["EPT accessed and dirty flags"],
+            &["VPID"],
+            &["Unrestricted Guest"],
+            &["VMCS Shadowing"],
+            &["Virtual NMIs"],
+            &["VMX preemption timer"],
+            &["TPR shadow"],
+            &["APIC-register virtualization"],
+            &["Virtual-interrupt delivery"],
+            &["Process posted interrupts"],
+        ],
+        recommended: &[],
+    },
+    Profile {
+        name: "kvm",
+        description: "KVM",
+        required: VIRTUALIZATION,
+        recommended: &[
+            &["EPT", "Nested paging"],
+            &["VPID"],
+            &["Unrestricted Guest"],
+            &["APICv complete", "AVIC"],
+            &["VMX preemption timer"],
+            &["TSC scaling"],
+            &["Page-modification logging"],
+        ],
+    },
+    Profile {
+        name: "hyperv",
+        description: "Microsoft Hyper-V",
+        required: &[
+            &["VMX", "SVM"],
+            &["VMX usable", "SVM usable"],
+            &["EPT", "Nested paging"],
+        ],
+        recommended: &[
+            &["Unrestricted Guest"],
+            &["APICv complete", "AVIC"],
+            &["Mode-based execute control"],
+            &["VMCS Shadowing"],
+            &["TSC scaling"],
+        ],
+    },
+    Profile {
+        name: "esxi",
+        description: "VMware ESXi",
+        required: &[
+            &["VMX", "SVM"],
+            &["VMX usable", "SVM usable"],
+            &["EPT", "Nested paging"],
+        ],
+        recommended: &[
+            &["Unrestricted Guest"],
+            &["VPID"],
+            &["EPT 1GB pages"],
+            &["APICv complete", "AVIC"],
+            &["Process posted interrupts"],
+        ],
+    },
+    Profile {
+        name: "xen",
+        description: "Xen HVM guests",
+        required: VIRTUALIZATION,
+        recommended: &[
+            &["EPT", "Nested paging"],
+            &["VPID"],
+            &["Unrestricted Guest"],
+            &["APICv complete", "AVIC"],
+            &["Process posted interrupts"],
+            &["TSC scaling"],
+        ],
+    },
+    Profile {
+        name: "virtualbox",
+        description: "Oracle VirtualBox",
+        required: VIRTUALIZATION,
+        recommended: &[
+            &["EPT", "Nested paging"],
+            &["VPID"],
+            &["Unrestricted Guest"],
+        ],
+    },
+    Profile {
+        name: "bhyve",
+        description: "FreeBSD bhyve",
+        required: &[
+            &["VMX", "SVM"],
+            &["VMX usable", "SVM usable"],
+            &["EPT", "Nested paging"],
+        ],
+        recommended: &[
+            &["Unrestricted Guest"],
+            &["VPID"],
+            &["APICv complete", "AVIC"],
+            &["Process posted interrupts"],
+        ],
+    },
+];
+
+/// Look up a built-in profile by name.
+pub fn find(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|p| p.name == name)
+}
+
+/// A requirement that is not met.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    /// The alternatives that apply to the CPU, or all of them if none
+    /// applies.
+    pub name: String,
+    pub status: FeatureStatus,
+    pub required: bool,
+}
+
+/// Evaluate a requirement.
+///
+/// Returns the applicable alternatives and the combined status.
+/// Features that are missing from the catalog are unknown.
+fn evaluate(
+    requirement: Requirement,
+    catalog: &[Feature],
+    cpu_info: &dyn CpuInformation,
+) -> (Vec<&'static str>, FeatureStatus) {
+    let statuses: Vec<(&str, FeatureStatus)> = requirement
+        .iter()
+        .map(|&name| {
+            let status = catalog
+                .iter()
+                .find(|f| f.name == name)
+                .map(|f| f.is_present(cpu_info))
+                .unwrap_or(FeatureStatus::Unknown { missing: vec![] });
+
+            (name, status)
+        })
+        .filter(|(_, status)| *status != FeatureStatus::NotApplicable)
+        .collect();
+
+    let status = if statuses.is_empty() {
+        FeatureStatus::NotApplicable
+    } else if statuses.iter().any(|(_, s)| *s == FeatureStatus::Present) {
+        FeatureStatus::Present
+    } else if statuses.iter().all(|(_, s)| *s == FeatureStatus::Absent) {
+        FeatureStatus::Absent
+    } else {
+        FeatureStatus::Unknown { missing: vec![] }
+    };
+
+    (statuses.into_iter().map(|(name, _)| name).collect(), status)
+}
+
+/// The requirements of a profile that are not met.
+pub fn gaps(profile: &Profile, catalog: &[Feature], cpu_info: &dyn CpuInformation) -> Vec<Gap> {
+    let required = profile.required.iter().map(|r| (r, true));
+    let recommended = profile.recommended.iter().map(|r| (r, false));
+
+    required
+        .chain(recommended)
+        .filter_map(|(requirement, required)| {
+            let (names, status) = evaluate(requirement, catalog, cpu_info);
+
+            match status {
+                FeatureStatus::Present => None,
+                FeatureStatus::NotApplicable if !required => None,
+                FeatureStatus::NotApplicable => Some(Gap {
+                    name: requirement.join(" or "),
+                    status,
+                    required,
+                }),
+                status => Some(Gap {
+                    name: names.join(" or "),
+                    status,
+                    required,
+                }),
+            }
+        })
+        .collect()
+}
+
+/// A report section with the verdict for a profile and its gaps.
+pub fn section(profile: &Profile, catalog: &[Feature], cpu_info: &dyn CpuInformation) -> Section {
+    let gaps = gaps(profile, catalog, cpu_info);
+    let verdict = if gaps.iter().any(|g| g.required) {
+        "FAIL"
+    } else {
+        "PASS"
+    };
+
+    let mut section = Section::new(&format!(
+        "Profile {} ({}): {}",
+        profile.name, profile.description, verdict
+    ));
+
+    for gap in gaps {
+        let status = match gap.status {
+            FeatureStatus::Absent => "absent",
+            FeatureStatus::NotApplicable => "not applicable to this CPU",
+            _ => "unknown",
+        };
+
+        if gap.required {
+            section.row(&gap.name, status);
+        } else {
+            section.row(&gap.name, format!("{} (recommended)", status));
+        }
+    }
+
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog;
+    use crate::cpu_information::Vendor::*;
+    use crate::features::BoolExpression::*;
+    use crate::features::DEFAULT_CATEGORY;
+    use crate::test_support::{AmdCpu, IntelCpu};
+
+    #[test]
+    fn profiles_refer_to_existing_features() {
+        let features = catalog::features();
+
+        for profile in PROFILES {
+            for requirement in profile.required.iter().chain(profile.recommended) {
+                for name in *requirement {
+                    assert!(
+                        features.iter().any(|f| f.name == *name),
+                        "profile {} refers to unknown feature {}",
+                        profile.name,
+                        name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gaps_are_reported() {
+        let profile = Profile {
+            name: "test",
+            description: "Test",
+            required: &[&["A"], &["B"], &["C"]],
+            recommended: &[&["D", "E"]],
+        };
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "A", Constant(true)),
+            Feature::new(DEFAULT_CATEGORY, "B", Constant(false)),
+            Feature::new(DEFAULT_CATEGORY, "D", Constant(false)),
+            Feature::new(DEFAULT_CATEGORY, "E", Constant(false)),
+        ];
+
+        let section = section(&profile, &features, &IntelCpu);
+
+        assert_eq!(section.title, "Profile test (Test): FAIL");
+        assert_eq!(section.get("A"), None);
+        assert_eq!(section.get("B"), Some("absent"));
+        assert_eq!(section.get("C"), Some("unknown"));
+        assert_eq!(section.get("D or E"), Some("absent (recommended)"));
+    }
+
+    #[test]
+    fn alternatives_of_other_vendors_are_ignored() {
+        let profile = Profile {
+            name: "test",
+            description: "Test",
+            required: &[&["VMX", "SVM"]],
+            recommended: &[&["AVIC"]],
+        };
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "VMX", Constant(false)).vendor(Intel),
+            Feature::new(DEFAULT_CATEGORY, "SVM", Constant(true)).vendor(Amd),
+            Feature::new(DEFAULT_CATEGORY, "AVIC", Constant(true)).vendor(Amd),
+        ];
+
+        let section = section(&profile, &features, &IntelCpu);
+
+        assert_eq!(section.title, "Profile test (Test): FAIL");
+        assert_eq!(section.rows, vec![("VMX".to_owned(), "absent".to_owned())]);
+    }
+
+    #[test]
+    fn inapplicable_requirements_fail() {
+        let section = section(
+            find("nested-kvm").expect("built-in profile"),
+            &catalog::features(),
+            &AmdCpu,
+        );
+
+        assert_eq!(section.title, "Profile nested-kvm (KVM nested VMX): FAIL");
+        assert_eq!(section.get("VMX"), Some("not applicable to this CPU"));
+        assert_eq!(section.get("EPT"), Some("not applicable to this CPU"));
+    }
+}
@@ -0,0 +1,210 @@
+//! # Decode Resource Director Technology
+//!
+//! Resource Director Technology (RDT) monitors and limits how much
+//! shared cache and memory bandwidth each workload uses. AMD calls the
+//! same interface Platform QoS and enumerates it in the same leaves.
+//!
+//! CPUID leaf 0xF enumerates monitoring: Cache Monitoring Technology
+//! (CMT) and Memory Bandwidth Monitoring (MBM), which count per
+//! resource monitoring ID (RMID). CPUID leaf 0x10 enumerates
+//! allocation: Cache Allocation Technology (CAT) for L3 and L2, Code
+//! and Data Prioritization (CDP) and Memory Bandwidth Allocation
+//! (MBA), which limit per class of service (CLOS).
+//!
+//! See the Intel SDM Vol. 3B, Chapter 18.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The CPUID leaf with monitoring capabilities.
+pub const CPUID_RDT_MONITORING: u32 = 0xf;
+
+/// The CPUID leaf with allocation capabilities.
+pub const CPUID_RDT_ALLOCATION: u32 = 0x10;
+
+/// The bit in CPUID leaf 7 EBX that enumerates monitoring.
+const RDT_M: Bit = 12;
+
+/// The bit in CPUID leaf 7 EBX that enumerates allocation.
+const RDT_A: Bit = 15;
+
+/// The L3 monitoring events in subleaf 1 EDX of leaf 0xF.
+const L3_MONITORING_EVENTS: &[(Bit, &str)] = &[
+    (0, "L3 occupancy monitoring"),
+    (1, "Total memory bandwidth monitoring"),
+    (2, "Local memory bandwidth monitoring"),
+];
+
+/// The resources in subleaf 0 EBX of leaf 0x10.
+const ALLOCATION_RESOURCES: &[(Bit, &str)] = &[
+    (1, "L3 cache allocation"),
+    (2, "L2 cache allocation"),
+    (3, "Memory bandwidth allocation"),
+];
+
+fn query(leaf: u32, subleaf: u32) -> CpuidQuery {
+    CpuidQuery { leaf, subleaf }
+}
+
+/// Decode CPUID leaf 0xF.
+fn monitoring_section(subleaf0: CpuidResult, l3: Option<CpuidResult>) -> Section {
+    let mut section = Section::new(&format!(
+        "RDT monitoring (CPUID {:#x})",
+        CPUID_RDT_MONITORING
+    ));
+
+    section.row("Maximum RMID", subleaf0.ebx);
+    section.flag("L3 cache monitoring", subleaf0.edx.into(), 1);
+
+    if let Some(r) = l3.filter(|_| (subleaf0.edx >> 1) & 1 == 1) {
+        section.row("Maximum L3 RMID", r.ecx);
+        section.row("Counter conversion factor", format!("{} bytes", r.ebx));
+        section.row("Counter width", format!("{} bits", 24 + (r.eax & 0xff)));
+
+        for (bit, name) in L3_MONITORING_EVENTS {
+            section.flag(name, r.edx.into(), *bit);
+        }
+    }
+
+    section
+}
+
+/// Add the rows for a cache allocation subleaf.
+fn cache_allocation_rows(section: &mut Section, cache: &str, r: CpuidResult) {
+    section.row(
+        &format!("{} capacity mask length", cache),
+        format!("{} bits", (r.eax & 0x1f) + 1),
+    );
+    section.row(
+        &format!("{} shared capacity mask", cache),
+        format!("{:#x}", r.ebx),
+    );
+    section.flag(&format!("{} CDP", cache), r.ecx.into(), 2);
+    section.flag(
+        &format!("{} non-contiguous capacity masks", cache),
+        r.ecx.into(),
+        3,
+    );
+    section.row(
+        &format!("{} classes of service", cache),
+        (r.edx & 0xffff) + 1,
+    );
+}
+
+/// Decode CPUID leaf 0x10.
+fn allocation_section(
+    subleaf0: CpuidResult,
+    l3: Option<CpuidResult>,
+    l2: Option<CpuidResult>,
+    mba: Option<CpuidResult>,
+) -> Section {
+    let mut section = Section::new(&format!(
+        "RDT allocation (CPUID {:#x})",
+        CPUID_RDT_ALLOCATION
+    ));
+    let supports = |bit: u32| (subleaf0.ebx >> bit) & 1 == 1;
+
+    for (bit, name) in ALLOCATION_RESOURCES {
+        section.flag(name, subleaf0.ebx.into(), *bit);
+    }
+
+    if let Some(r) = l3.filter(|_| supports(1)) {
+        cache_allocation_rows(&mut section, "L3", r);
+    }
+    if let Some(r) = l2.filter(|_| supports(2)) {
+        cache_allocation_rows(&mut section, "L2", r);
+    }
+    if let Some(r) = mba.filter(|_| supports(3)) {
+        section.row("Maximum MBA throttling", (r.eax & 0xfff) + 1);
+        section.flag("Linear MBA response", r.ecx.into(), 2);
+        section.row("MBA classes of service", (r.edx & 0xffff) + 1);
+    }
+
+    section
+}
+
+/// Decode RDT monitoring and allocation, if the CPU supports them.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let leaf7_ebx = cpu_info
+        .cpuid(7.into())
+        .filter(|_| cpu_info.is_cpuid_query_valid(7.into()))
+        .map_or(0, |r| r.ebx);
+    let leaf = |leaf: u32, enumerated: Bit| {
+        cpu_info
+            .cpuid(leaf.into())
+            .filter(|_| (leaf7_ebx >> enumerated) & 1 == 1)
+            .filter(|_| cpu_info.is_cpuid_query_valid(leaf.into()))
+    };
+    let subleaf = |leaf, subleaf| cpu_info.cpuid(query(leaf, subleaf));
+
+    let monitoring = leaf(CPUID_RDT_MONITORING, RDT_M)
+        .map(|r| monitoring_section(r, subleaf(CPUID_RDT_MONITORING, 1)));
+    let allocation = leaf(CPUID_RDT_ALLOCATION, RDT_A).map(|r| {
+        allocation_section(
+            r,
+            subleaf(CPUID_RDT_ALLOCATION, 1),
+            subleaf(CPUID_RDT_ALLOCATION, 2),
+            subleaf(CPUID_RDT_ALLOCATION, 3),
+        )
+    });
+
+    monitoring.into_iter().chain(allocation).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    fn result(eax: u32, ebx: u32, ecx: u32, edx: u32) -> CpuidResult {
+        CpuidResult { eax, ebx, ecx, edx }
+    }
+
+    #[test]
+    fn monitoring_is_decoded() {
+        // A Skylake-SP.
+        let section =
+            monitoring_section(result(0, 0xdf, 0, 0x2), Some(result(0, 0xe000, 0xdf, 0x7)));
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Maximum RMID"), "223");
+        assert_eq!(row("L3 cache monitoring"), "Y");
+        assert_eq!(row("Maximum L3 RMID"), "223");
+        assert_eq!(row("Counter conversion factor"), "57344 bytes");
+        assert_eq!(row("Counter width"), "24 bits");
+        assert_eq!(row("Total memory bandwidth monitoring"), "Y");
+        assert_eq!(row("Local memory bandwidth monitoring"), "Y");
+    }
+
+    #[test]
+    fn allocation_is_decoded() {
+        // A Skylake-SP with L3 CAT, CDP and MBA.
+        let section = allocation_section(
+            result(0, 0xa, 0, 0),
+            Some(result(0xa, 0x600, 0x4, 0xf)),
+            None,
+            Some(result(0x59, 0, 0x4, 0x7)),
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("L3 cache allocation"), "Y");
+        assert_eq!(row("L2 cache allocation"), "N");
+        assert_eq!(row("Memory bandwidth allocation"), "Y");
+        assert_eq!(row("L3 capacity mask length"), "11 bits");
+        assert_eq!(row("L3 shared capacity mask"), "0x600");
+        assert_eq!(row("L3 CDP"), "Y");
+        assert_eq!(row("L3 non-contiguous capacity masks"), "N");
+        assert_eq!(row("L3 classes of service"), "16");
+        assert_eq!(row("Maximum MBA throttling"), "90");
+        assert_eq!(row("Linear MBA response"), "Y");
+        assert_eq!(row("MBA classes of service"), "8");
+        assert_eq!(section.get("L2 capacity mask length"), None);
+    }
+
+    #[test]
+    fn resources_are_sorted_and_unique() {
+        assert_sorted_unique(L3_MONITORING_EVENTS);
+        assert_sorted_unique(ALLOCATION_RESOURCES);
+    }
+}
@@ -0,0 +1,56 @@
+//! # Report Sections
+//!
+//! Decoded CPUID leaves and MSRs are presented as sections: a title
+//! followed by a list of named values.
+
+use std::fmt;
+
+use crate::features::Bit;
+
+/// A titled list of named values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub title: String,
+    pub rows: Vec<(String, String)>,
+}
+
+impl Section {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_owned(),
+            rows: vec![],
+        }
+    }
+
+    /// Append a row.
+    pub fn row(&mut self, name: &str, value: impl fmt::Display) {
+        self.rows.push((name.to_owned(), value.to_string()));
+    }
+
+    /// Look up the value of a row by name.
+    #[cfg(test)]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.rows
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Append a row that shows whether a bit is set as `Y` or `N`.
+    pub fn flag(&mut self, name: &str, value: u64, bit: Bit) {
+        assert!(u32::from(bit) < u64::BITS);
+
+        self.row(name, if value & (1 << bit) != 0 { 'Y' } else { 'N' });
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.title)?;
+
+        let width = self.rows.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+        self.rows.iter().try_for_each(|(name, value)| {
+            writeln!(f, "  {:width$} : {}", name, value, width = width)
+        })
+    }
+}
@@ -0,0 +1,213 @@
+//! # Virtualization Score
+//!
+//! Summarize the virtualization capabilities of a host as a single
+//! number and a grade. Each feature has a weight. The score is the
+//! share of the applicable weight whose features are present,
+//! scaled to 0–100. Features of the other vendor don't count, so
+//! Intel and AMD hosts are scored on the same scale.
+//!
+//! Policy files can replace the built-in weights and tiers:
+//!
+//! ```toml
+//! [score.weights]
+//! "APICv complete" = 3
+//! "EPT 1GB pages" = 1
+//!
+//! [[score.tier]]
+//! name = "A"
+//! min = 80
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::cpu_information::CpuInformation;
+use crate::features::{Feature, FeatureStatus};
+use crate::report::Section;
+
+/// A grade that a host gets if its score is at least `min`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tier {
+    pub name: String,
+    pub min: u32,
+}
+
+/// Feature weights and grade tiers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scoring {
+    pub weights: BTreeMap<String, u32>,
+
+    #[serde(default, rename = "tier")]
+    pub tiers: Vec<Tier>,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        let weights = [
+            ("EPT", 3),
+            ("Nested paging", 3),
+            ("VPID", 2),
+            ("Unrestricted Guest", 2),
+            ("EPT 1GB pages", 1),
+            ("EPT accessed and dirty flags", 1),
+            ("Page-modification logging", 1),
+            ("APICv complete", 3),
+            ("AVIC", 3),
+            ("x2AVIC", 1),
+            ("Process posted interrupts", 2),
+            ("IPI virtualization", 1),
+            ("Virtual NMI", 1),
+            ("VMCS Shadowing", 1),
+            ("VMX preemption timer", 1),
+            ("Decode assists", 1),
+            ("TSC scaling", 2),
+        ];
+        let tiers = [("A", 85), ("B", 60), ("C", 30), ("D", 0)];
+
+        Self {
+            weights: weights
+                .iter()
+                .map(|(name, weight)| (name.to_string(), *weight))
+                .collect(),
+            tiers: tiers
+                .iter()
+                .map(|(name, min)| Tier {
+                    name: name.to_string(),
+                    min: *min,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The result of scoring a host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Score {
+    /// The score from 0 to 100.
+    pub value: u32,
+
+    /// The best tier the score reaches, if any.
+    pub grade: Option<String>,
+
+    /// Weighted features that could not be decided.
+    pub unknown: Vec<String>,
+}
+
+impl Scoring {
+    pub fn score(&self, catalog: &[Feature], cpu_info: &dyn CpuInformation) -> Score {
+        // Weights come from policy files, so their sums can exceed
+        // u32.
+        let mut total: u64 = 0;
+        let mut present: u64 = 0;
+        let mut unknown = vec![];
+
+        for (name, weight) in &self.weights {
+            let status = catalog
+                .iter()
+                .find(|f| &f.name == name)
+                .map(|f| f.is_present(cpu_info))
+                .unwrap_or(FeatureStatus::Unknown { missing: vec![] });
+
+            match status {
+                FeatureStatus::NotApplicable => continue,
+                FeatureStatus::Present => present += u64::from(*weight),
+                FeatureStatus::Absent => {}
+                FeatureStatus::Unknown { .. } => unknown.push(name.clone()),
+            }
+            total += u64::from(*weight);
+        }
+
+        let value = (present * 100).checked_div(total).unwrap_or(0) as u32;
+        let grade = self
+            .tiers
+            .iter()
+            .filter(|t| value >= t.min)
+            .max_by_key(|t| t.min)
+            .map(|t| t.name.clone());
+
+        Score {
+            value,
+            grade,
+            unknown,
+        }
+    }
+
+    pub fn section(&self, catalog: &[Feature], cpu_info: &dyn CpuInformation) -> Section {
+        let score = self.score(catalog, cpu_info);
+        let mut section = Section::new("Virtualization score");
+
+        section.row("Score", format!("{} of 100", score.value));
+        section.row("Grade", score.grade.as_deref().unwrap_or("none"));
+        if !score.unknown.is_empty() {
+            section.row("Unknown (counted as absent)", score.unknown.join(", "));
+        }
+
+        section
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog;
+    use crate::cpu_information::Vendor::*;
+    use crate::features::BoolExpression::Constant;
+    use crate::features::DEFAULT_CATEGORY;
+    use crate::test_support::IntelCpu;
+
+    #[test]
+    fn default_weights_refer_to_existing_features() {
+        let features = catalog::features();
+
+        for name in Scoring::default().weights.keys() {
+            assert!(
+                features.iter().any(|f| &f.name == name),
+                "weight for unknown feature {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn hosts_are_scored() {
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "A", Constant(true)),
+            Feature::new(DEFAULT_CATEGORY, "B", Constant(false)),
+            Feature::new(DEFAULT_CATEGORY, "C", Constant(true)).vendor(Amd),
+        ];
+        let scoring: Scoring = toml::from_str(
+            r#"
+weights = { A = 3, B = 1, C = 5, D = 1 }
+tier = [ { name = "A", min = 70 }, { name = "B", min = 50 } ]
+"#,
+        )
+        .expect("valid scoring");
+
+        let score = scoring.score(&features, &IntelCpu);
+
+        // C doesn't apply, D is unknown: 3 of 5.
+        assert_eq!(score.value, 60);
+        assert_eq!(score.grade.as_deref(), Some("B"));
+        assert_eq!(score.unknown, vec!["D"]);
+    }
+
+    #[test]
+    fn large_weights_do_not_overflow() {
+        let features = vec![
+            Feature::new(DEFAULT_CATEGORY, "A", Constant(true)),
+            Feature::new(DEFAULT_CATEGORY, "B", Constant(true)),
+            Feature::new(DEFAULT_CATEGORY, "C", Constant(false)),
+        ];
+        let scoring: Scoring = toml::from_str(
+            r#"
+weights = { A = 4294967295, B = 4294967295, C = 4294967295 }
+"#,
+        )
+        .expect("valid scoring");
+
+        assert_eq!(scoring.score(&features, &IntelCpu).value, 66);
+    }
+}
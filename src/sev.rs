@@ -0,0 +1,194 @@
+//! # Decode AMD Secure Encrypted Virtualization
+//!
+//! CPUID leaf 0x8000001F enumerates AMD's memory encryption
+//! features. EAX holds one bit per feature, EBX the position of the
+//! C-bit in page table entries, ECX the number of encrypted guests
+//! that can run at the same time and EDX the minimum ASID of guests
+//! that use SEV without SEV-ES. Lower ASIDs are reserved for SEV-ES
+//! and SEV-SNP guests.
+//!
+//! Whether the host actually encrypts its memory is controlled by
+//! SYSCFG. Transparent SME (TSME) is configured in the memory
+//! controller by firmware and is not visible in CPUID or SYSCFG.
+//!
+//! Inside a guest, the SEV_STATUS MSR shows which of these features
+//! are active.
+//!
+//! See the AMD APM Vol. 2, Section 15.34 and Vol. 3, Appendix E.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::Bit;
+use crate::msr;
+use crate::report::Section;
+
+/// The CPUID leaf with memory encryption information.
+pub const CPUID_MEMORY_ENCRYPTION: u32 = 0x8000_001f;
+
+/// The system configuration MSR.
+pub const SYSCFG: u32 = 0xc001_0010;
+
+/// The SYSCFG bit that enables SME (MemEncryptionModEn).
+pub const SYSCFG_MEM_ENCRYPTION_MOD_EN: Bit = 23;
+
+/// The MSR that shows which SEV features are active in a guest.
+pub const SEV_STATUS: u32 = 0xc001_0131;
+
+/// The named feature bits in EAX of CPUID leaf 0x8000001F.
+pub const MEMORY_ENCRYPTION_FEATURES: &[(Bit, &str)] = &[
+    (0, "SME"),
+    (1, "SEV"),
+    (2, "Page flush MSR"),
+    (3, "SEV-ES"),
+    (4, "SEV-SNP"),
+    (5, "VMPL"),
+    (6, "RMPQUERY"),
+    (7, "VMPL supervisor shadow stack"),
+    (8, "Secure TSC"),
+    (9, "TSC_AUX virtualization"),
+    (10, "Hardware cache coherency"),
+    (11, "64-bit host required"),
+    (12, "Restricted injection"),
+    (13, "Alternate injection"),
+    (14, "Debug register swap"),
+    (15, "Prevent host IBS"),
+    (16, "Virtual transparent encryption"),
+];
+
+/// The named bits of SEV_STATUS.
+pub const SEV_STATUS_FLAGS: &[(Bit, &str)] = &[
+    (0, "SEV active"),
+    (1, "SEV-ES active"),
+    (2, "SEV-SNP active"),
+];
+
+/// The named bits of SYSCFG.
+pub const SYSCFG_FLAGS: &[(Bit, &str)] = &[
+    (17, "Fixed MTRR DRAM attributes"),
+    (18, "Fixed MTRR DRAM modification"),
+    (19, "Variable MTRR DRAM attributes"),
+    (20, "TOM2 enabled"),
+    (21, "Write-back above 4GB (TOM2)"),
+    (
+        SYSCFG_MEM_ENCRYPTION_MOD_EN,
+        "MemEncryptionModEn (SME enabled)",
+    ),
+    (24, "SecureNestedPagingEn (SNP enabled)"),
+    (25, "VMPL enabled"),
+    (26, "Host multi-key encryption enabled"),
+];
+
+/// Split the encrypted guest ASIDs into those for SEV-ES (or
+/// SEV-SNP) guests and those for plain SEV guests.
+///
+/// ASIDs 1 up to `min_sev_asid - 1` are for SEV-ES guests. The rest
+/// up to `max_asid` are for SEV guests.
+fn asid_split(max_asid: u32, min_sev_asid: u32) -> (u32, u32) {
+    let es_asids = min_sev_asid.saturating_sub(1).min(max_asid);
+
+    (es_asids, max_asid - es_asids)
+}
+
+/// Decode CPUID leaf 0x8000001F.
+fn memory_encryption_section(eax: u32, ebx: u32, ecx: u32, edx: u32) -> Section {
+    let mut section = Section::new(&format!(
+        "Memory encryption features (CPUID {:#x})",
+        CPUID_MEMORY_ENCRYPTION
+    ));
+
+    for (bit, name) in MEMORY_ENCRYPTION_FEATURES {
+        section.flag(name, eax.into(), *bit);
+    }
+
+    section.row("C-bit position", ebx & 0x3f);
+    section.row(
+        "Physical address reduction",
+        format!("{} bits", (ebx >> 6) & 0x3f),
+    );
+    section.row("Number of VMPLs", (ebx >> 12) & 0xf);
+    section.row("Simultaneous encrypted guests", ecx);
+    section.row("Minimum SEV ASID", edx);
+
+    let (es_asids, sev_asids) = asid_split(ecx, edx);
+    section.row("SEV-ES and SEV-SNP guests", es_asids);
+    section.row("SEV guests", sev_asids);
+
+    section
+}
+
+/// Decode all memory encryption information that is available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let query = CPUID_MEMORY_ENCRYPTION.into();
+    let mut sections = vec![];
+
+    if let Some(r) = cpu_info
+        .cpuid(query)
+        .filter(|_| cpu_info.is_cpuid_query_valid(query))
+    {
+        sections.push(memory_encryption_section(r.eax, r.ebx, r.ecx, r.edx));
+    }
+
+    sections.extend(msr::section(cpu_info, SYSCFG));
+    sections.extend(msr::section(cpu_info, SEV_STATUS));
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn memory_encryption_features_are_decoded() {
+        // A host with SME, SEV, SEV-ES and SEV-SNP.
+        let section = memory_encryption_section(0x0001_781f, 0x0000_516f, 509, 100);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("SME"), "Y");
+        assert_eq!(row("SEV"), "Y");
+        assert_eq!(row("SEV-ES"), "Y");
+        assert_eq!(row("SEV-SNP"), "Y");
+        assert_eq!(row("VMPL"), "N");
+        assert_eq!(row("Virtual transparent encryption"), "Y");
+        assert_eq!(row("C-bit position"), "47");
+        assert_eq!(row("Physical address reduction"), "5 bits");
+        assert_eq!(row("Number of VMPLs"), "5");
+        assert_eq!(row("Simultaneous encrypted guests"), "509");
+        assert_eq!(row("Minimum SEV ASID"), "100");
+        assert_eq!(row("SEV-ES and SEV-SNP guests"), "99");
+        assert_eq!(row("SEV guests"), "410");
+    }
+
+    #[test]
+    fn asids_are_split() {
+        assert_eq!(asid_split(509, 100), (99, 410));
+        assert_eq!(asid_split(15, 1), (0, 15));
+        assert_eq!(asid_split(15, 0), (0, 15));
+        assert_eq!(asid_split(15, 20), (15, 0));
+    }
+
+    #[test]
+    fn syscfg_is_decoded() {
+        let section = msr::find(SYSCFG)
+            .expect("registered")
+            .section(0x0000_0000_0084_0000);
+
+        assert_eq!(section.get("Fixed MTRR DRAM modification"), Some("Y"));
+        assert_eq!(section.get("MemEncryptionModEn (SME enabled)"), Some("Y"));
+        assert_eq!(section.get("SecureNestedPagingEn (SNP enabled)"), Some("N"));
+    }
+
+    #[test]
+    fn sev_status_is_decoded() {
+        let section = msr::find(SEV_STATUS).expect("registered").section(0b011);
+
+        assert_eq!(section.get("SEV active"), Some("Y"));
+        assert_eq!(section.get("SEV-ES active"), Some("Y"));
+        assert_eq!(section.get("SEV-SNP active"), Some("N"));
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(MEMORY_ENCRYPTION_FEATURES);
+    }
+}
@@ -0,0 +1,261 @@
+//! # Decode SGX Capabilities
+//!
+//! CPUID leaf 0x12 enumerates Intel Software Guard Extensions (SGX).
+//! Subleaf 0 lists the SGX instruction sets and the maximum enclave
+//! sizes, subleaf 1 the SECS attributes that enclaves may set, and the
+//! subleaves from 2 on describe the Enclave Page Cache (EPC) sections,
+//! the memory that holds enclave pages.
+//!
+//! SGX only works if firmware enabled it in IA32_FEATURE_CONTROL and
+//! reserved EPC memory. Without EPC sections, no enclave can run.
+//!
+//! See the Intel SDM Vol. 3D, Chapter 36.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+use crate::vmx::IA32_FEATURE_CONTROL;
+
+/// The CPUID leaf with SGX capabilities.
+pub const CPUID_SGX: u32 = 0x12;
+
+/// The IA32_FEATURE_CONTROL bit that lets the OS write the launch
+/// enclave public key hash.
+pub const FEATURE_CONTROL_SGX_LC: Bit = 17;
+
+/// The IA32_FEATURE_CONTROL bit that enables SGX.
+pub const FEATURE_CONTROL_SGX_ENABLE: Bit = 18;
+
+/// The bit in CPUID leaf 7 EBX that enumerates SGX.
+const SGX: Bit = 2;
+
+/// The instruction sets in subleaf 0 EAX.
+const SGX_FEATURES: &[(Bit, &str)] = &[
+    (0, "SGX1"),
+    (1, "SGX2"),
+    (5, "ENCLV"),
+    (6, "ENCLS ETRACKC, ERDINFO, ELDBC and ELDUC"),
+    (7, "ENCLU EVERIFYREPORT2"),
+    (10, "ENCLS EUPDATESVN"),
+    (11, "ENCLU EDECCSSA"),
+];
+
+/// A section of the Enclave Page Cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpcSection {
+    pub base: u64,
+    pub size: u64,
+
+    /// Whether the contents are integrity protected in addition to
+    /// being encrypted.
+    pub integrity_protected: bool,
+}
+
+impl EpcSection {
+    /// Decode an EPC subleaf. Returns `None` for the terminating
+    /// subleaf.
+    pub fn from_cpuid(r: CpuidResult) -> Option<Self> {
+        if r.eax & 0xf != 1 {
+            return None;
+        }
+
+        Some(EpcSection {
+            base: u64::from(r.ebx & 0xf_ffff) << 32 | u64::from(r.eax & !0xfff),
+            size: u64::from(r.edx & 0xf_ffff) << 32 | u64::from(r.ecx & !0xfff),
+            integrity_protected: r.ecx & 0xf == 1,
+        })
+    }
+}
+
+/// Walk the EPC subleaves.
+pub fn epc_sections(cpu_info: &dyn CpuInformation) -> Vec<EpcSection> {
+    (2..)
+        .map(|subleaf| {
+            cpu_info.cpuid(CpuidQuery {
+                leaf: CPUID_SGX,
+                subleaf,
+            })
+        })
+        .map_while(|r| r.and_then(EpcSection::from_cpuid))
+        .collect()
+}
+
+/// Format a size in bytes in MiB.
+fn format_mib(bytes: u64) -> String {
+    format!("{} MiB", bytes >> 20)
+}
+
+/// Whether enclaves can run.
+fn enclave_verdict(sgx1: bool, feature_control: Option<u64>, epc_size: u64) -> &'static str {
+    let enabled = feature_control.map(|v| {
+        let locked = v & 1 == 1;
+        let sgx_enabled = (v >> FEATURE_CONTROL_SGX_ENABLE) & 1 == 1;
+
+        locked && sgx_enabled
+    });
+
+    match (sgx1, enabled, epc_size) {
+        (false, _, _) => "no (SGX1 not supported)",
+        (_, Some(false), _) => "no (disabled by firmware)",
+        (_, _, 0) => "no (no EPC)",
+        (_, None, _) => "unknown (IA32_FEATURE_CONTROL missing)",
+        (true, Some(true), _) => "yes",
+    }
+}
+
+/// Decode CPUID leaf 0x12.
+fn sgx_section(subleaf0: CpuidResult, epc: &[EpcSection], feature_control: Option<u64>) -> Section {
+    let mut section = Section::new(&format!("SGX (CPUID {:#x})", CPUID_SGX));
+    let sgx1 = subleaf0.eax & 1 == 1;
+    let epc_size = epc.iter().map(|s| s.size).sum();
+
+    section.row(
+        "Enclaves can run",
+        enclave_verdict(sgx1, feature_control, epc_size),
+    );
+
+    for (bit, name) in SGX_FEATURES {
+        section.flag(name, subleaf0.eax.into(), *bit);
+    }
+
+    section.row("MISCSELECT", format!("{:#x}", subleaf0.ebx));
+    section.row(
+        "Maximum enclave size (32-bit)",
+        format!("2^{} bytes", subleaf0.edx & 0xff),
+    );
+    section.row(
+        "Maximum enclave size (64-bit)",
+        format!("2^{} bytes", (subleaf0.edx >> 8) & 0xff),
+    );
+
+    if let Some(v) = feature_control {
+        section.flag("SGX enabled by firmware", v, FEATURE_CONTROL_SGX_ENABLE);
+        section.flag(
+            "Launch control enabled by firmware",
+            v,
+            FEATURE_CONTROL_SGX_LC,
+        );
+    }
+
+    for (i, s) in epc.iter().enumerate() {
+        section.row(
+            &format!("EPC section {}", i),
+            format!(
+                "{} at {:#x}{}",
+                format_mib(s.size),
+                s.base,
+                if s.integrity_protected {
+                    ", integrity protected"
+                } else {
+                    ""
+                }
+            ),
+        );
+    }
+    section.row("Total EPC", format_mib(epc_size));
+
+    section
+}
+
+/// Decode SGX capabilities, if the CPU supports SGX.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let supported = cpu_info.is_cpuid_query_valid(7.into())
+        && cpu_info
+            .cpuid(7.into())
+            .is_some_and(|r| (r.ebx >> SGX) & 1 == 1);
+
+    cpu_info
+        .cpuid(CPUID_SGX.into())
+        .filter(|_| supported && cpu_info.is_cpuid_query_valid(CPUID_SGX.into()))
+        .map(|r| {
+            sgx_section(
+                r,
+                &epc_sections(cpu_info),
+                cpu_info.rdmsr(IA32_FEATURE_CONTROL),
+            )
+        })
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    fn result(eax: u32, ebx: u32, ecx: u32, edx: u32) -> CpuidResult {
+        CpuidResult { eax, ebx, ecx, edx }
+    }
+
+    #[test]
+    fn epc_sections_are_decoded() {
+        assert_eq!(
+            EpcSection::from_cpuid(result(0x7020_0001, 0, 0x05d8_0001, 0)),
+            Some(EpcSection {
+                base: 0x7020_0000,
+                size: 0x05d8_0000,
+                integrity_protected: true,
+            })
+        );
+        assert_eq!(
+            EpcSection::from_cpuid(result(0x0000_0001, 0x1, 0x8000_0002, 0x1)),
+            Some(EpcSection {
+                base: 0x1_0000_0000,
+                size: 0x1_8000_0000,
+                integrity_protected: false,
+            })
+        );
+        assert_eq!(EpcSection::from_cpuid(result(0, 0, 0, 0)), None);
+    }
+
+    #[test]
+    fn leaf_is_decoded() {
+        // A Skylake client CPU with 93.5 MiB of EPC.
+        let epc = [EpcSection {
+            base: 0x7020_0000,
+            size: 0x05d8_0000,
+            integrity_protected: true,
+        }];
+        let section = sgx_section(result(0x1, 0, 0, 0x241f), &epc, Some(0x0004_0005));
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Enclaves can run"), "yes");
+        assert_eq!(row("SGX1"), "Y");
+        assert_eq!(row("SGX2"), "N");
+        assert_eq!(row("Maximum enclave size (32-bit)"), "2^31 bytes");
+        assert_eq!(row("Maximum enclave size (64-bit)"), "2^36 bytes");
+        assert_eq!(row("SGX enabled by firmware"), "Y");
+        assert_eq!(row("Launch control enabled by firmware"), "N");
+        assert_eq!(
+            row("EPC section 0"),
+            "93 MiB at 0x70200000, integrity protected"
+        );
+        assert_eq!(row("Total EPC"), "93 MiB");
+    }
+
+    #[test]
+    fn enclaves_need_firmware_support() {
+        assert_eq!(
+            enclave_verdict(false, Some(0x0004_0005), 1 << 20),
+            "no (SGX1 not supported)"
+        );
+        assert_eq!(
+            enclave_verdict(true, Some(0x5), 1 << 20),
+            "no (disabled by firmware)"
+        );
+        assert_eq!(
+            enclave_verdict(true, Some(0x0004_0000), 1 << 20),
+            "no (disabled by firmware)"
+        );
+        assert_eq!(enclave_verdict(true, Some(0x0004_0005), 0), "no (no EPC)");
+        assert_eq!(
+            enclave_verdict(true, None, 1 << 20),
+            "unknown (IA32_FEATURE_CONTROL missing)"
+        );
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(SGX_FEATURES);
+    }
+}
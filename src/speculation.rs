@@ -0,0 +1,191 @@
+//! # Decode Speculation Control
+//!
+//! CPUs enumerate the interfaces that software uses to mitigate
+//! speculative execution attacks. AMD CPUs do this in EBX of CPUID
+//! leaf 0x80000008. Besides whether IBRS, STIBP and friends exist,
+//! this leaf also contains hints on whether they should be left on
+//! all the time.
+//!
+//! Intel CPUs state which vulnerabilities the hardware is not
+//! affected by in IA32_ARCH_CAPABILITIES.
+//!
+//! See AMD's "Indirect Branch Control Extension" and "Speculative
+//! Store Bypass Disable" white papers and Intel's "Affected Processors:
+//! Guidance for Security Issues on Intel Processors".
+
+use crate::cpu_information::CpuidRegister::{Ebx, Edx};
+use crate::cpu_information::{CpuInformation, Vendor};
+use crate::features::Bit;
+use crate::features::BoolExpression::{self, CpuidBitSet, MsrBitSet};
+use crate::msr;
+use crate::report::Section;
+
+/// The Intel MSR that enumerates hardware mitigations.
+pub const IA32_ARCH_CAPABILITIES: u32 = 0x10a;
+
+/// The Intel MSR that controls microcode mitigations.
+pub const IA32_MCU_OPT_CTRL: u32 = 0x123;
+
+/// The named bits of IA32_MCU_OPT_CTRL.
+pub const MCU_OPT_CTRL: &[(Bit, &str)] = &[
+    (0, "RNGDS_MITG_DIS"),
+    (1, "RTM_ALLOW"),
+    (2, "RTM_LOCKED"),
+    (3, "FB_CLEAR_DIS"),
+    (4, "GDS_MITG_DIS"),
+    (5, "GDS_MITG_LOCK"),
+];
+
+/// The Intel MSR that disables TSX.
+pub const IA32_TSX_CTRL: u32 = 0x122;
+
+/// The named bits of IA32_TSX_CTRL.
+pub const TSX_CTRL: &[(Bit, &str)] = &[(0, "RTM_DISABLE"), (1, "TSX_CPUID_CLEAR")];
+
+/// The Intel MSR that makes RTM transactions always abort.
+pub const MSR_TSX_FORCE_ABORT: u32 = 0x10f;
+
+/// The named bits of TSX_FORCE_ABORT.
+pub const TSX_FORCE_ABORT: &[(Bit, &str)] = &[
+    (0, "RTM_FORCE_ABORT"),
+    (1, "TSX_CPUID_CLEAR"),
+    (2, "SDV_ENABLE_RTM"),
+];
+
+/// The named bits of IA32_ARCH_CAPABILITIES.
+pub const ARCH_CAPABILITIES: &[(Bit, &str)] = &[
+    (0, "RDCL_NO"),
+    (1, "IBRS_ALL"),
+    (2, "RSBA"),
+    (3, "SKIP_L1DFL_VMENTRY"),
+    (4, "SSB_NO"),
+    (5, "MDS_NO"),
+    (6, "IF_PSCHANGE_MC_NO"),
+    (7, "TSX_CTRL"),
+    (8, "TAA_NO"),
+    (9, "MCU_CONTROL"),
+    (10, "MISC_PACKAGE_CTLS"),
+    (11, "ENERGY_FILTERING_CTL"),
+    (12, "DOITM"),
+    (13, "SBDR_SSDP_NO"),
+    (14, "FBSDP_NO"),
+    (15, "PSDP_NO"),
+    (16, "MCU_ENUMERATION"),
+    (17, "FB_CLEAR"),
+    (18, "FB_CLEAR_CTRL"),
+    (19, "RRSBA"),
+    (20, "BHI_NO"),
+    (21, "XAPIC_DISABLE_STATUS"),
+    (22, "MCU_EXTENDED_SERVICE"),
+    (23, "OVERCLOCKING_STATUS"),
+    (24, "PBRSB_NO"),
+    (25, "GDS_CTRL"),
+    (26, "GDS_NO"),
+    (27, "RFDS_NO"),
+    (28, "RFDS_CLEAR"),
+];
+
+/// The AMD CPUID leaf with speculation control information.
+pub const CPUID_AMD_EXTENDED_FEATURES: u32 = 0x8000_0008;
+
+/// The named speculation control bits in EBX of CPUID leaf
+/// 0x80000008.
+pub const AMD_SPECULATION_CONTROLS: &[(Bit, &str)] = &[
+    (12, "IBPB"),
+    (14, "IBRS"),
+    (15, "STIBP"),
+    (16, "IBRS always on"),
+    (17, "STIBP always on"),
+    (18, "IBRS preferred"),
+    (19, "IBRS same mode protection"),
+    (24, "SSBD"),
+    (25, "Virtualized SSBD"),
+    (26, "Not affected by SSB"),
+    (28, "PSFD"),
+    (29, "Not affected by BTC"),
+    (30, "IBPB clears return predictions"),
+];
+
+/// An expression that checks a bit in IA32_ARCH_CAPABILITIES.
+///
+/// The MSR only exists if CPUID leaf 7 enumerates it.
+pub fn arch_capability(bit: Bit) -> BoolExpression {
+    CpuidBitSet(7.into(), Edx, 29) & MsrBitSet(IA32_ARCH_CAPABILITIES, bit)
+}
+
+/// An expression that checks a bit in EBX of AMD's CPUID leaf
+/// 0x80000008.
+pub fn amd_speculation_control(bit: Bit) -> BoolExpression {
+    CpuidBitSet(CPUID_AMD_EXTENDED_FEATURES.into(), Ebx, bit)
+}
+
+/// Decode the speculation control bits of CPUID leaf 0x80000008.
+fn amd_speculation_section(ebx: u32) -> Section {
+    let mut section = Section::new(&format!(
+        "AMD speculation control (CPUID {:#x})",
+        CPUID_AMD_EXTENDED_FEATURES
+    ));
+
+    for (bit, name) in AMD_SPECULATION_CONTROLS {
+        section.flag(name, ebx.into(), *bit);
+    }
+
+    section
+}
+
+/// Decode all speculation control information that is available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let query = CPUID_AMD_EXTENDED_FEATURES.into();
+    let mut sections = vec![];
+
+    sections.extend(
+        [
+            IA32_ARCH_CAPABILITIES,
+            IA32_MCU_OPT_CTRL,
+            IA32_TSX_CTRL,
+            MSR_TSX_FORCE_ABORT,
+        ]
+        .iter()
+        .filter_map(|&index| msr::section(cpu_info, index)),
+    );
+
+    // Intel CPUs implement this leaf as well, but use EBX for other
+    // purposes.
+    if let Some(r) = cpu_info
+        .cpuid(query)
+        .filter(|_| cpu_info.vendor() == Some(Vendor::Amd))
+        .filter(|_| cpu_info.is_cpuid_query_valid(query))
+    {
+        sections.push(amd_speculation_section(r.ebx));
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn amd_speculation_controls_are_decoded() {
+        // IBPB, IBRS, STIBP, STIBP always on, IBRS same mode and SSBD.
+        let section = amd_speculation_section(0x010a_d000);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("IBPB"), "Y");
+        assert_eq!(row("IBRS"), "Y");
+        assert_eq!(row("STIBP"), "Y");
+        assert_eq!(row("IBRS always on"), "N");
+        assert_eq!(row("STIBP always on"), "Y");
+        assert_eq!(row("IBRS preferred"), "N");
+        assert_eq!(row("IBRS same mode protection"), "Y");
+        assert_eq!(row("SSBD"), "Y");
+        assert_eq!(row("PSFD"), "N");
+    }
+
+    #[test]
+    fn controls_are_sorted_and_unique() {
+        assert_sorted_unique(AMD_SPECULATION_CONTROLS);
+    }
+}
@@ -0,0 +1,354 @@
+//! # Decode Structured Extended Features
+//!
+//! CPUID leaf 7 enumerates most instruction set extensions and
+//! security features added since Haswell. Subleaf 0 reports the
+//! number of further subleaves in EAX. This module decodes subleaves 0
+//! to 2.
+//!
+//! See the Intel SDM Vol. 2A, CPUID—CPU Identification, and the Intel
+//! Architecture Instruction Set Extensions Programming Reference.
+
+use crate::cpu_information::CpuidRegister::{self, *};
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The CPUID leaf with structured extended feature flags.
+pub const CPUID_STRUCTURED_FEATURES: u32 = 7;
+
+/// Subleaf 0, EBX.
+const SUBLEAF0_EBX: &[(Bit, &str)] = &[
+    (0, "FSGSBASE"),
+    (1, "IA32_TSC_ADJUST"),
+    (2, "SGX"),
+    (3, "BMI1"),
+    (4, "HLE"),
+    (5, "AVX2"),
+    (6, "FDP_EXCPTN_ONLY"),
+    (7, "SMEP"),
+    (8, "BMI2"),
+    (9, "Enhanced REP MOVSB/STOSB"),
+    (10, "INVPCID"),
+    (11, "RTM"),
+    (12, "RDT-M"),
+    (13, "FPU CS and DS deprecated"),
+    (14, "MPX"),
+    (15, "RDT-A"),
+    (16, "AVX512F"),
+    (17, "AVX512DQ"),
+    (18, "RDSEED"),
+    (19, "ADX"),
+    (20, "SMAP"),
+    (21, "AVX512_IFMA"),
+    (23, "CLFLUSHOPT"),
+    (24, "CLWB"),
+    (25, "Intel PT"),
+    (26, "AVX512PF"),
+    (27, "AVX512ER"),
+    (28, "AVX512CD"),
+    (29, "SHA"),
+    (30, "AVX512BW"),
+    (31, "AVX512VL"),
+];
+
+/// Subleaf 0, ECX. Bits 17 to 21 hold the MAWAU value instead.
+const SUBLEAF0_ECX: &[(Bit, &str)] = &[
+    (0, "PREFETCHWT1"),
+    (1, "AVX512_VBMI"),
+    (2, "UMIP"),
+    (3, "PKU"),
+    (4, "OSPKE"),
+    (5, "WAITPKG"),
+    (6, "AVX512_VBMI2"),
+    (7, "CET_SS"),
+    (8, "GFNI"),
+    (9, "VAES"),
+    (10, "VPCLMULQDQ"),
+    (11, "AVX512_VNNI"),
+    (12, "AVX512_BITALG"),
+    (13, "TME"),
+    (14, "AVX512_VPOPCNTDQ"),
+    (16, "LA57"),
+    (22, "RDPID"),
+    (23, "Key Locker"),
+    (24, "BUS_LOCK_DETECT"),
+    (25, "CLDEMOTE"),
+    (27, "MOVDIRI"),
+    (28, "MOVDIR64B"),
+    (29, "ENQCMD"),
+    (30, "SGX_LC"),
+    (31, "PKS"),
+];
+
+/// Subleaf 0, EDX.
+const SUBLEAF0_EDX: &[(Bit, &str)] = &[
+    (1, "SGX-KEYS"),
+    (2, "AVX512_4VNNIW"),
+    (3, "AVX512_4FMAPS"),
+    (4, "Fast short REP MOV"),
+    (5, "UINTR"),
+    (8, "AVX512_VP2INTERSECT"),
+    (9, "SRBDS_CTRL"),
+    (10, "MD_CLEAR"),
+    (11, "RTM_ALWAYS_ABORT"),
+    (13, "RTM_FORCE_ABORT"),
+    (14, "SERIALIZE"),
+    (15, "Hybrid"),
+    (16, "TSXLDTRK"),
+    (18, "PCONFIG"),
+    (19, "Architectural LBRs"),
+    (20, "CET_IBT"),
+    (22, "AMX-BF16"),
+    (23, "AVX512_FP16"),
+    (24, "AMX-TILE"),
+    (25, "AMX-INT8"),
+    (26, "IBRS and IBPB"),
+    (27, "STIBP"),
+    (28, "L1D_FLUSH"),
+    (29, "IA32_ARCH_CAPABILITIES"),
+    (30, "IA32_CORE_CAPABILITIES"),
+    (31, "SSBD"),
+];
+
+/// Subleaf 1, EAX.
+const SUBLEAF1_EAX: &[(Bit, &str)] = &[
+    (0, "SHA512"),
+    (1, "SM3"),
+    (2, "SM4"),
+    (3, "RAO-INT"),
+    (4, "AVX-VNNI"),
+    (5, "AVX512_BF16"),
+    (6, "LASS"),
+    (7, "CMPCCXADD"),
+    (8, "ArchPerfmonExt"),
+    (10, "Fast zero-length REP MOVSB"),
+    (11, "Fast short REP STOSB"),
+    (12, "Fast short REP CMPSB/SCASB"),
+    (17, "FRED"),
+    (18, "LKGS"),
+    (19, "WRMSRNS"),
+    (21, "AMX-FP16"),
+    (22, "HRESET"),
+    (23, "AVX-IFMA"),
+    (26, "LAM"),
+    (27, "MSRLIST"),
+];
+
+/// Subleaf 1, EBX.
+const SUBLEAF1_EBX: &[(Bit, &str)] = &[(0, "IA32_PPIN"), (1, "PBNDKB")];
+
+/// Subleaf 1, EDX.
+const SUBLEAF1_EDX: &[(Bit, &str)] = &[
+    (4, "AVX-VNNI-INT8"),
+    (5, "AVX-NE-CONVERT"),
+    (8, "AMX-COMPLEX"),
+    (10, "AVX-VNNI-INT16"),
+    (14, "PREFETCHI"),
+    (18, "CET_SSS"),
+    (19, "AVX10"),
+    (21, "APX_F"),
+];
+
+/// Subleaf 2, EDX.
+const SUBLEAF2_EDX: &[(Bit, &str)] = &[
+    (0, "PSFD"),
+    (1, "IPRED_CTRL"),
+    (2, "RRSBA_CTRL"),
+    (3, "DDPD_U"),
+    (4, "BHI_CTRL"),
+    (5, "MCDT_NO"),
+    (6, "UC-lock disable"),
+    (7, "MONITOR_MITG_NO"),
+];
+
+/// The name of a feature bit in CPUID leaf 7.
+pub fn flag_name(subleaf: u32, register: CpuidRegister, bit: Bit) -> Option<&'static str> {
+    let flags = match (subleaf, register) {
+        (0, Ebx) => SUBLEAF0_EBX,
+        (0, Ecx) => SUBLEAF0_ECX,
+        (0, Edx) => SUBLEAF0_EDX,
+        (1, Eax) => SUBLEAF1_EAX,
+        (1, Ebx) => SUBLEAF1_EBX,
+        (1, Edx) => SUBLEAF1_EDX,
+        (2, Edx) => SUBLEAF2_EDX,
+        _ => return None,
+    };
+
+    flags.iter().find(|(b, _)| *b == bit).map(|(_, name)| *name)
+}
+
+fn title(subleaf: u32) -> String {
+    format!(
+        "Structured extended features (CPUID {:#x}.{})",
+        CPUID_STRUCTURED_FEATURES, subleaf
+    )
+}
+
+fn flags(section: &mut Section, value: u32, flags: &[(Bit, &str)]) {
+    for (bit, name) in flags {
+        section.flag(name, value.into(), *bit);
+    }
+}
+
+/// Decode subleaf 0.
+fn subleaf0_section(r: CpuidResult) -> Section {
+    let mut section = Section::new(&title(0));
+
+    section.row("Maximum subleaf", r.eax);
+    flags(&mut section, r.ebx, SUBLEAF0_EBX);
+    flags(&mut section, r.ecx, SUBLEAF0_ECX);
+    section.row("MAWAU", (r.ecx >> 17) & 0x1f);
+    flags(&mut section, r.edx, SUBLEAF0_EDX);
+
+    section
+}
+
+/// Decode subleaf 1.
+fn subleaf1_section(r: CpuidResult) -> Section {
+    let mut section = Section::new(&title(1));
+
+    flags(&mut section, r.eax, SUBLEAF1_EAX);
+    flags(&mut section, r.ebx, SUBLEAF1_EBX);
+    flags(&mut section, r.edx, SUBLEAF1_EDX);
+
+    section
+}
+
+/// Decode subleaf 2.
+fn subleaf2_section(r: CpuidResult) -> Section {
+    let mut section = Section::new(&title(2));
+
+    flags(&mut section, r.edx, SUBLEAF2_EDX);
+
+    section
+}
+
+/// Decode all subleaves of CPUID leaf 7 that are available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let query = |subleaf| CpuidQuery {
+        leaf: CPUID_STRUCTURED_FEATURES,
+        subleaf,
+    };
+
+    let subleaf0 = match cpu_info
+        .cpuid(query(0))
+        .filter(|_| cpu_info.is_cpuid_query_valid(query(0)))
+    {
+        Some(r) => r,
+        None => return vec![],
+    };
+
+    let decoders: [fn(CpuidResult) -> Section; 2] = [subleaf1_section, subleaf2_section];
+    let mut sections = vec![subleaf0_section(subleaf0)];
+
+    sections.extend(
+        (1..=subleaf0.eax)
+            .zip(decoders.iter())
+            .filter_map(|(subleaf, decode)| cpu_info.cpuid(query(subleaf)).map(decode)),
+    );
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_sorted_unique;
+
+    struct TestCpu {
+        subleaves: Vec<CpuidResult>,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            match query.leaf {
+                0 => Some(CpuidResult {
+                    eax: CPUID_STRUCTURED_FEATURES,
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                }),
+                CPUID_STRUCTURED_FEATURES => self.subleaves.get(query.subleaf as usize).copied(),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            None
+        }
+    }
+
+    fn result(eax: u32, ebx: u32, ecx: u32, edx: u32) -> CpuidResult {
+        CpuidResult { eax, ebx, ecx, edx }
+    }
+
+    #[test]
+    fn subleaf0_is_decoded() {
+        // A Skylake-SP with AVX-512, SMEP and SMAP, but neither PKS nor
+        // CET. MAWAU is 0x1c.
+        let section = subleaf0_section(result(0, 0xd19f_4fbb, 0x0038_0808, 0xbc00_0400));
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Maximum subleaf"), "0");
+        assert_eq!(row("SMEP"), "Y");
+        assert_eq!(row("SMAP"), "Y");
+        assert_eq!(row("AVX512F"), "Y");
+        assert_eq!(row("AVX512_IFMA"), "N");
+        assert_eq!(row("PKU"), "Y");
+        assert_eq!(row("PKS"), "N");
+        assert_eq!(row("CET_SS"), "N");
+        assert_eq!(row("MAWAU"), "28");
+        assert_eq!(row("MD_CLEAR"), "Y");
+        assert_eq!(row("SERIALIZE"), "N");
+        assert_eq!(row("SSBD"), "Y");
+    }
+
+    #[test]
+    fn subleaves_1_and_2_are_decoded() {
+        let section = subleaf1_section(result(0x0000_0030, 0x1, 0, 0x0000_0010));
+
+        assert_eq!(section.get("AVX-VNNI"), Some("Y"));
+        assert_eq!(section.get("AVX512_BF16"), Some("Y"));
+        assert_eq!(section.get("FRED"), Some("N"));
+        assert_eq!(section.get("IA32_PPIN"), Some("Y"));
+        assert_eq!(section.get("AVX-VNNI-INT8"), Some("Y"));
+
+        let section = subleaf2_section(result(0, 0, 0, 0x0000_0014));
+
+        assert_eq!(section.get("PSFD"), Some("N"));
+        assert_eq!(section.get("BHI_CTRL"), Some("Y"));
+        assert_eq!(section.get("DDPD_U"), Some("N"));
+    }
+
+    #[test]
+    fn only_enumerated_subleaves_are_decoded() {
+        let zero = result(0, 0, 0, 0);
+        let cpu = |max_subleaf| TestCpu {
+            subleaves: vec![result(max_subleaf, 0, 0, 0), zero, zero],
+        };
+
+        assert_eq!(sections(&cpu(0)).len(), 1);
+        assert_eq!(sections(&cpu(1)).len(), 2);
+        assert_eq!(sections(&cpu(2)).len(), 3);
+        assert_eq!(sections(&cpu(5)).len(), 3);
+    }
+
+    #[test]
+    fn flags_are_named() {
+        assert_eq!(flag_name(0, Ebx, 16), Some("AVX512F"));
+        assert_eq!(flag_name(2, Edx, 4), Some("BHI_CTRL"));
+        assert_eq!(flag_name(0, Ebx, 22), None);
+        assert_eq!(flag_name(0, Eax, 0), None);
+    }
+
+    #[test]
+    fn flags_are_sorted_and_unique() {
+        assert_sorted_unique(SUBLEAF0_EBX);
+        assert_sorted_unique(SUBLEAF0_ECX);
+        assert_sorted_unique(SUBLEAF0_EDX);
+        assert_sorted_unique(SUBLEAF1_EAX);
+        assert_sorted_unique(SUBLEAF1_EBX);
+        assert_sorted_unique(SUBLEAF1_EDX);
+        assert_sorted_unique(SUBLEAF2_EDX);
+    }
+}
@@ -0,0 +1,141 @@
+//! # Decode AMD SVM Capabilities
+//!
+//! AMD CPUs enumerate the optional parts of Secure Virtual Machine
+//! (SVM) support in CPUID leaf 0x8000000A. EAX holds the SVM
+//! revision, EBX the number of address space identifiers (ASIDs) and
+//! EDX one bit per feature.
+//!
+//! See the AMD APM Vol. 2, Section 15 and Vol. 3, Appendix E.
+
+use crate::cpu_information::CpuInformation;
+use crate::cpu_information::CpuidRegister::Edx;
+use crate::features::Bit;
+use crate::features::BoolExpression::{self, CpuidBitSet};
+use crate::msr;
+use crate::report::Section;
+
+/// The MSR that controls whether SVM can be enabled.
+pub const VM_CR: u32 = 0xc001_0114;
+
+/// The VM_CR bit that locks SVMDIS.
+pub const VM_CR_LOCK: Bit = 3;
+
+/// The VM_CR bit that prevents enabling SVM.
+pub const VM_CR_SVMDIS: Bit = 4;
+
+/// The CPUID leaf with SVM revision and feature information.
+pub const CPUID_SVM_FEATURES: u32 = 0x8000_000a;
+
+/// The named feature bits in EDX of CPUID leaf 0x8000000A.
+pub const SVM_FEATURES: &[(Bit, &str)] = &[
+    (0, "Nested paging"),
+    (1, "LBR virtualization"),
+    (2, "SVM lock"),
+    (3, "NRIP save"),
+    (4, "TSC rate MSR"),
+    (5, "VMCB clean bits"),
+    (6, "Flush by ASID"),
+    (7, "Decode assists"),
+    (10, "Pause filter"),
+    (12, "Pause filter threshold"),
+    (13, "AVIC"),
+    (15, "VMSAVE/VMLOAD virtualization"),
+    (16, "Virtual GIF"),
+    (17, "Guest mode execute trap"),
+    (18, "x2AVIC"),
+    (19, "Supervisor shadow stack checks"),
+    (20, "SPEC_CTRL virtualization"),
+    (21, "Read-only guest page tables"),
+    (23, "Host MCE override"),
+    (24, "INVLPGB/TLBSYNC interception"),
+    (25, "Virtual NMI"),
+    (26, "IBS virtualization"),
+];
+
+/// An expression that checks a feature bit in CPUID leaf 0x8000000A.
+pub fn svm_feature(bit: Bit) -> BoolExpression {
+    CpuidBitSet(CPUID_SVM_FEATURES.into(), Edx, bit)
+}
+
+/// Decode CPUID leaf 0x8000000A.
+fn features_section(eax: u32, ebx: u32, edx: u32) -> Section {
+    let mut section = Section::new(&format!("SVM features (CPUID {:#x})", CPUID_SVM_FEATURES));
+
+    section.row("SVM revision", eax & 0xff);
+    section.row("Number of ASIDs", ebx);
+
+    for (bit, name) in SVM_FEATURES {
+        section.flag(name, edx.into(), *bit);
+    }
+
+    section
+}
+
+/// Decode all SVM capability information that is available.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let query = CPUID_SVM_FEATURES.into();
+
+    cpu_info
+        .cpuid(query)
+        .filter(|_| cpu_info.is_cpuid_query_valid(query))
+        .map(|r| features_section(r.eax, r.ebx, r.edx))
+        .into_iter()
+        .chain(msr::section(cpu_info, VM_CR))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::{CpuidQuery, CpuidResult};
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn svm_features_are_decoded() {
+        // An SVM revision 1 CPU with AVIC and virtual GIF.
+        let section = features_section(0x1, 0x8000, 0x0001_bcff);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("SVM revision"), "1");
+        assert_eq!(row("Number of ASIDs"), "32768");
+        assert_eq!(row("Nested paging"), "Y");
+        assert_eq!(row("Decode assists"), "Y");
+        assert_eq!(row("Pause filter"), "Y");
+        assert_eq!(row("AVIC"), "Y");
+        assert_eq!(row("Virtual GIF"), "Y");
+        assert_eq!(row("x2AVIC"), "N");
+        assert_eq!(row("Virtual NMI"), "N");
+    }
+
+    #[test]
+    fn features_are_sorted_and_unique() {
+        assert_sorted_unique(SVM_FEATURES);
+    }
+
+    #[test]
+    fn invalid_leaves_are_not_decoded() {
+        struct TestCpu;
+
+        impl CpuInformation for TestCpu {
+            fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+                // Claims to support only leaves up to 0x80000008.
+                Some(CpuidResult {
+                    eax: if query.leaf == 0x8000_0000 {
+                        0x8000_0008
+                    } else {
+                        0
+                    },
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                })
+            }
+
+            fn rdmsr(&self, _index: u32) -> Option<u64> {
+                None
+            }
+        }
+
+        assert!(sections(&TestCpu).is_empty());
+    }
+}
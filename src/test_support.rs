@@ -0,0 +1,66 @@
+//! # Test Doubles
+//!
+//! CPUs with next to no information and checks on lookup tables that
+//! the tests of several modules share.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+
+/// A CPU without any CPUID leaves or MSRs.
+pub struct NoInformation;
+
+impl CpuInformation for NoInformation {
+    fn cpuid(&self, _query: CpuidQuery) -> Option<CpuidResult> {
+        None
+    }
+
+    fn rdmsr(&self, _index: u32) -> Option<u64> {
+        None
+    }
+}
+
+/// CPUID leaf 0 with the vendor string in EBX, EDX and ECX.
+fn vendor_leaf(query: CpuidQuery, ebx: u32, edx: u32, ecx: u32) -> Option<CpuidResult> {
+    (query.leaf == 0).then_some(CpuidResult {
+        eax: 0,
+        ebx,
+        ecx,
+        edx,
+    })
+}
+
+/// An Intel CPU that only has CPUID leaf 0.
+pub struct IntelCpu;
+
+impl CpuInformation for IntelCpu {
+    fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+        // "GenuineIntel"
+        vendor_leaf(query, 0x756e_6547, 0x4965_6e69, 0x6c65_746e)
+    }
+
+    fn rdmsr(&self, _index: u32) -> Option<u64> {
+        None
+    }
+}
+
+/// An AMD CPU that only has CPUID leaf 0.
+pub struct AmdCpu;
+
+impl CpuInformation for AmdCpu {
+    fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+        // "AuthenticAMD"
+        vendor_leaf(query, 0x6874_7541, 0x6974_6e65, 0x444d_4163)
+    }
+
+    fn rdmsr(&self, _index: u32) -> Option<u64> {
+        None
+    }
+}
+
+/// Assert that a table of bits is sorted by bit and names each bit
+/// only once.
+pub fn assert_sorted_unique(table: &[(Bit, &str)]) {
+    for w in table.windows(2) {
+        assert!(w[0].0 < w[1].0, "{:?} is not before {:?}", w[0], w[1]);
+    }
+}
@@ -0,0 +1,414 @@
+//! # Decode the Processor Topology
+//!
+//! CPUID leaves 0xB and 0x1F describe how the x2APIC ID of a logical
+//! processor splits into SMT, core, module, tile and die IDs. Each
+//! subleaf describes one level: how far to shift the x2APIC ID to get
+//! the ID at the next level, and how many logical processors share
+//! the next level. Leaf 0x1F supersedes leaf 0xB and adds the levels
+//! between core and package.
+//!
+//! If the dump contains the CPUID values of all logical CPUs, their
+//! x2APIC IDs give the number of cores and packages that are actually
+//! present.
+//!
+//! See the Intel SDM Vol. 2A, CPUID—CPU Identification.
+
+use std::collections::BTreeSet as Set;
+
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::report::Section;
+
+/// The original extended topology leaf.
+pub const CPUID_EXTENDED_TOPOLOGY: u32 = 0xb;
+
+/// The V2 extended topology leaf.
+pub const CPUID_EXTENDED_TOPOLOGY_V2: u32 = 0x1f;
+
+/// The type of a topology level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelType {
+    Smt,
+    Core,
+    Module,
+    Tile,
+    Die,
+    DieGroup,
+    Reserved(u32),
+}
+
+impl From<u32> for LevelType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => LevelType::Smt,
+            2 => LevelType::Core,
+            3 => LevelType::Module,
+            4 => LevelType::Tile,
+            5 => LevelType::Die,
+            6 => LevelType::DieGroup,
+            other => LevelType::Reserved(other),
+        }
+    }
+}
+
+impl std::fmt::Display for LevelType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LevelType::Smt => f.write_str("SMT"),
+            LevelType::Core => f.write_str("Core"),
+            LevelType::Module => f.write_str("Module"),
+            LevelType::Tile => f.write_str("Tile"),
+            LevelType::Die => f.write_str("Die"),
+            LevelType::DieGroup => f.write_str("Die group"),
+            LevelType::Reserved(value) => write!(f, "Reserved ({})", value),
+        }
+    }
+}
+
+/// A level of the topology.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    pub level_type: LevelType,
+
+    /// The number of bits to shift the x2APIC ID right to get the ID
+    /// of the next level.
+    pub shift: u32,
+
+    /// The number of logical processors at this level.
+    pub logical_processors: u32,
+}
+
+/// The topology leaf that the CPU supports, preferring leaf 0x1F.
+pub fn topology_leaf(cpu_info: &dyn CpuInformation) -> Option<u32> {
+    [CPUID_EXTENDED_TOPOLOGY_V2, CPUID_EXTENDED_TOPOLOGY]
+        .iter()
+        .copied()
+        .find(|&leaf| {
+            cpu_info.is_cpuid_query_valid(leaf.into())
+                && cpu_info
+                    .cpuid(leaf.into())
+                    .is_some_and(|r| r.ebx & 0xffff != 0)
+        })
+}
+
+/// Walk the subleaves of a topology leaf until the first invalid
+/// level.
+pub fn levels(cpu_info: &dyn CpuInformation, leaf: u32) -> Vec<Level> {
+    (0..)
+        .map(|subleaf| cpu_info.cpuid(CpuidQuery { leaf, subleaf }))
+        .take_while(|r| r.is_some_and(|r| (r.ecx >> 8) & 0xff != 0))
+        .flatten()
+        .map(|r| Level {
+            level_type: ((r.ecx >> 8) & 0xff).into(),
+            shift: r.eax & 0x1f,
+            logical_processors: r.ebx & 0xffff,
+        })
+        .collect()
+}
+
+/// The x2APIC ID of a logical CPU.
+fn x2apic_id(cpu_info: &dyn CpuInformation, leaf: u32) -> Option<u32> {
+    cpu_info.cpuid(leaf.into()).map(|r| r.edx)
+}
+
+/// Count the logical CPUs, cores and packages of the given x2APIC
+/// IDs.
+fn count(levels: &[Level], x2apic_ids: &[u32]) -> (usize, usize, usize) {
+    let smt = levels.iter().find(|l| l.level_type == LevelType::Smt);
+    let core_shift = smt.map_or(0, |l| l.shift);
+    let package_shift = levels.last().map_or(0, |l| l.shift);
+    let distinct = |shift: u32| {
+        x2apic_ids
+            .iter()
+            .map(|id| id.checked_shr(shift).unwrap_or(0))
+            .collect::<Set<u32>>()
+            .len()
+    };
+
+    (
+        x2apic_ids.len(),
+        distinct(core_shift),
+        distinct(package_shift),
+    )
+}
+
+/// The x2APIC IDs of all logical CPUs in the dump.
+fn x2apic_ids(cpu_info: &dyn CpuInformation, leaf: u32) -> Vec<u32> {
+    // The other logical CPUs don't necessarily have leaf 0, so the
+    // boot CPU decides which leaf is valid.
+    cpu_info
+        .logical_cpus()
+        .into_iter()
+        .filter_map(|cpu| x2apic_id(cpu, leaf))
+        .collect()
+}
+
+/// The first logical CPU of each package with its package ID.
+///
+/// This is empty if the dump doesn't contain all logical CPUs.
+pub fn packages(cpu_info: &dyn CpuInformation) -> Vec<(u32, &dyn CpuInformation)> {
+    let leaf = match topology_leaf(cpu_info) {
+        Some(leaf) => leaf,
+        None => return vec![],
+    };
+    let package_shift = levels(cpu_info, leaf).last().map_or(0, |l| l.shift);
+    let mut packages: Vec<(u32, &dyn CpuInformation)> = vec![];
+
+    for cpu in cpu_info.logical_cpus() {
+        if let Some(package) =
+            x2apic_id(cpu, leaf).map(|id| id.checked_shr(package_shift).unwrap_or(0))
+        {
+            if packages.iter().all(|(p, _)| *p != package) {
+                packages.push((package, cpu));
+            }
+        }
+    }
+
+    packages
+}
+
+/// Decode the topology leaf of the boot CPU and, if available, count
+/// the cores and packages of all logical CPUs.
+fn topology_section(
+    leaf: u32,
+    levels: &[Level],
+    boot_x2apic_id: Option<u32>,
+    x2apic_ids: &[u32],
+) -> Section {
+    let mut section = Section::new(&format!("Topology (CPUID {:#x})", leaf));
+
+    for level in levels {
+        section.row(
+            &level.level_type.to_string(),
+            format!(
+                "{} logical processors, x2APIC ID shift {}",
+                level.logical_processors, level.shift
+            ),
+        );
+    }
+
+    if let Some(id) = boot_x2apic_id {
+        section.row("x2APIC ID", format!("{:#x}", id));
+    }
+
+    let smt = levels.iter().find(|l| l.level_type == LevelType::Smt);
+    if let Some(smt) = smt {
+        section.row("Threads per core", smt.logical_processors);
+    }
+    if let Some(package) = levels.last() {
+        section.row("Logical processors per package", package.logical_processors);
+    }
+
+    if !x2apic_ids.is_empty() {
+        let (cpus, cores, packages) = count(levels, x2apic_ids);
+
+        section.row("Logical CPUs in dump", cpus);
+        section.row("Cores in dump", cores);
+        section.row("Packages in dump", packages);
+    }
+
+    section
+}
+
+/// Decode the topology, if the CPU enumerates it.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let leaf = match topology_leaf(cpu_info) {
+        Some(leaf) => leaf,
+        None => return vec![],
+    };
+
+    vec![topology_section(
+        leaf,
+        &levels(cpu_info, leaf),
+        x2apic_id(cpu_info, leaf),
+        &x2apic_ids(cpu_info, leaf),
+    )]
+}
+
+/// A count with the singular or plural of a noun.
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// A one-line summary of the topology for the report header.
+///
+/// With all logical CPUs in the dump, this counts them. Otherwise, it
+/// shows what the boot CPU enumerates for its package.
+pub fn summary(cpu_info: &dyn CpuInformation) -> Option<String> {
+    let leaf = topology_leaf(cpu_info)?;
+    let levels = levels(cpu_info, leaf);
+    let threads = levels
+        .iter()
+        .find(|l| l.level_type == LevelType::Smt)
+        .map_or(1, |l| l.logical_processors as usize)
+        .max(1);
+    let x2apic_ids = x2apic_ids(cpu_info, leaf);
+
+    let counts = if x2apic_ids.is_empty() {
+        let per_package = levels.last()?.logical_processors as usize;
+
+        format!(
+            "{}, {} per package",
+            plural(per_package / threads, "core"),
+            plural(per_package, "logical processor")
+        )
+    } else {
+        let (cpus, cores, packages) = count(&levels, &x2apic_ids);
+
+        format!(
+            "{}, {}, {} in dump",
+            plural(packages, "package"),
+            plural(cores, "core"),
+            plural(cpus, "logical CPU")
+        )
+    };
+
+    Some(format!(
+        "Topology: {} ({} per core)",
+        counts,
+        plural(threads, "thread")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidResult;
+
+    /// A two-package system with two cores per package and two
+    /// threads per core that only enumerates leaf 0xB.
+    struct TestCpu {
+        x2apic_id: u32,
+        others: Vec<TestCpu>,
+    }
+
+    impl TestCpu {
+        fn new() -> Self {
+            let others = [0, 1, 2, 3, 8, 9, 10, 11]
+                .iter()
+                .map(|&x2apic_id| TestCpu {
+                    x2apic_id,
+                    others: vec![],
+                })
+                .collect();
+
+            TestCpu {
+                x2apic_id: 0,
+                others,
+            }
+        }
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let result = |eax, ebx, ecx| CpuidResult {
+                eax,
+                ebx,
+                ecx,
+                edx: self.x2apic_id,
+            };
+
+            match (query.leaf, query.subleaf) {
+                (0, _) => Some(CpuidResult {
+                    eax: 0x16,
+                    ebx: 0,
+                    ecx: 0,
+                    edx: 0,
+                }),
+                (0xb, 0) => Some(result(1, 2, 0x100)),
+                (0xb, 1) => Some(result(3, 4, 0x201)),
+                (0xb, 2) => Some(result(0, 0, 2)),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            None
+        }
+
+        fn logical_cpus(&self) -> Vec<&dyn CpuInformation> {
+            self.others
+                .iter()
+                .map(|cpu| cpu as &dyn CpuInformation)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn leaf_0xb_is_used_without_leaf_0x1f() {
+        assert_eq!(topology_leaf(&TestCpu::new()), Some(0xb));
+    }
+
+    #[test]
+    fn levels_are_walked() {
+        assert_eq!(
+            levels(&TestCpu::new(), 0xb),
+            vec![
+                Level {
+                    level_type: LevelType::Smt,
+                    shift: 1,
+                    logical_processors: 2,
+                },
+                Level {
+                    level_type: LevelType::Core,
+                    shift: 3,
+                    logical_processors: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn topology_is_decoded() {
+        let sections = sections(&TestCpu::new());
+        let section = &sections[0];
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(section.title, "Topology (CPUID 0xb)");
+        assert_eq!(row("SMT"), "2 logical processors, x2APIC ID shift 1");
+        assert_eq!(row("Core"), "4 logical processors, x2APIC ID shift 3");
+        assert_eq!(row("x2APIC ID"), "0x0");
+        assert_eq!(row("Threads per core"), "2");
+        assert_eq!(row("Logical processors per package"), "4");
+        assert_eq!(row("Logical CPUs in dump"), "8");
+        assert_eq!(row("Cores in dump"), "4");
+        assert_eq!(row("Packages in dump"), "2");
+    }
+
+    #[test]
+    fn summary_counts_logical_cpus() {
+        assert_eq!(
+            summary(&TestCpu::new()).as_deref(),
+            Some("Topology: 2 packages, 4 cores, 8 logical CPUs in dump (2 threads per core)")
+        );
+
+        let boot_cpu_only = TestCpu {
+            x2apic_id: 0,
+            others: vec![],
+        };
+        assert_eq!(
+            summary(&boot_cpu_only).as_deref(),
+            Some("Topology: 2 cores, 4 logical processors per package (2 threads per core)")
+        );
+    }
+
+    #[test]
+    fn first_cpu_of_each_package_is_found() {
+        let cpu = TestCpu::new();
+        let packages: Vec<(u32, u32)> = packages(&cpu)
+            .into_iter()
+            .map(|(package, cpu)| (package, x2apic_id(cpu, 0xb).expect("x2APIC ID")))
+            .collect();
+
+        assert_eq!(packages, vec![(0, 0), (1, 8)]);
+    }
+
+    #[test]
+    fn level_types_are_named() {
+        assert_eq!(LevelType::from(5).to_string(), "Die");
+        assert_eq!(LevelType::from(9).to_string(), "Reserved (9)");
+    }
+}
@@ -0,0 +1,74 @@
+//! # Decode VMware Hypervisor Leaves
+//!
+//! VMware reports the virtual TSC and APIC bus frequencies in CPUID
+//! leaf 0x40000010. ECX of the same leaf says which instruction the
+//! guest should use for hypercalls instead of the I/O port backdoor.
+//!
+//! See `arch/x86/kernel/cpu/vmware.c` in the Linux kernel.
+
+use crate::cpu_information::CpuInformation;
+use crate::features::Bit;
+use crate::report::Section;
+
+/// The VMware timing and feature leaf.
+pub const CPUID_VMWARE_TIMING: u32 = 0x4000_0010;
+
+/// Features in ECX of leaf 0x40000010.
+const FEATURES: &[(Bit, &str)] = &[(0, "Hypercalls via VMMCALL"), (1, "Hypercalls via VMCALL")];
+
+/// Format a frequency in kHz, or "unknown" if it is zero.
+fn frequency(khz: u32) -> String {
+    if khz == 0 {
+        "unknown".to_owned()
+    } else {
+        format!("{}.{:03} MHz", khz / 1000, khz % 1000)
+    }
+}
+
+/// Decode leaf 0x40000010.
+fn timing_section(eax: u32, ebx: u32, ecx: u32) -> Section {
+    let mut section = Section::new(&format!(
+        "VMware timing and features (CPUID {:#x})",
+        CPUID_VMWARE_TIMING
+    ));
+
+    section.row("TSC frequency", frequency(eax));
+    section.row("APIC bus frequency", frequency(ebx));
+    for (bit, name) in FEATURES {
+        section.flag(name, ecx.into(), *bit);
+    }
+
+    section
+}
+
+/// Decode the VMware leaves that are available.
+///
+/// The caller must make sure that the hypervisor is VMware.
+pub fn sections(cpu_info: &dyn CpuInformation, max_leaf: u32) -> Vec<Section> {
+    Some(CPUID_VMWARE_TIMING)
+        .filter(|l| *l <= max_leaf)
+        .and_then(|l| cpu_info.cpuid(l.into()))
+        .map(|r| timing_section(r.eax, r.ebx, r.ecx))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_is_decoded() {
+        let section = timing_section(2_893_202, 66_000, 0b10);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("TSC frequency"), "2893.202 MHz");
+        assert_eq!(row("APIC bus frequency"), "66.000 MHz");
+        assert_eq!(row("Hypercalls via VMMCALL"), "N");
+        assert_eq!(row("Hypercalls via VMCALL"), "Y");
+        assert_eq!(
+            timing_section(0, 0, 0).get("TSC frequency"),
+            Some("unknown")
+        );
+    }
+}
@@ -0,0 +1,705 @@
+//! # Decode VMX Capability MSRs
+//!
+//! The VMX capability MSRs report which VM-execution controls a
+//! hypervisor can use. Each control MSR has two halves. Bits 31:0
+//! are the allowed-0 settings: a set bit means the control must be
+//! set. Bits 63:32 are the allowed-1 settings: a set bit means the
+//! control may be set.
+//!
+//! If bit 55 of IA32_VMX_BASIC is set, the TRUE control MSRs
+//! supersede the legacy ones. They may report default-1 controls as
+//! optional that the legacy MSRs report as always on.
+//!
+//! See the Intel SDM Vol. 3, Appendix A.
+
+use std::fmt;
+
+use crate::cpu_information::CpuInformation;
+use crate::features::Bit;
+use crate::features::BoolExpression::{self, MsrBitSet};
+use crate::msr;
+use crate::report::Section;
+
+pub const IA32_FEATURE_CONTROL: u32 = 0x3a;
+pub const IA32_VMX_BASIC: u32 = 0x480;
+pub const IA32_VMX_PINBASED_CTLS: u32 = 0x481;
+pub const IA32_VMX_PROCBASED_CTLS: u32 = 0x482;
+pub const IA32_VMX_EXIT_CTLS: u32 = 0x483;
+pub const IA32_VMX_ENTRY_CTLS: u32 = 0x484;
+pub const IA32_VMX_MISC: u32 = 0x485;
+pub const IA32_VMX_PROCBASED_CTLS2: u32 = 0x48b;
+pub const IA32_VMX_EPT_VPID_CAP: u32 = 0x48c;
+pub const IA32_VMX_TRUE_PINBASED_CTLS: u32 = 0x48d;
+pub const IA32_VMX_TRUE_PROCBASED_CTLS: u32 = 0x48e;
+pub const IA32_VMX_TRUE_EXIT_CTLS: u32 = 0x48f;
+pub const IA32_VMX_TRUE_ENTRY_CTLS: u32 = 0x490;
+pub const IA32_VMX_VMFUNC: u32 = 0x491;
+pub const IA32_VMX_PROCBASED_CTLS3: u32 = 0x492;
+
+/// The IA32_VMX_BASIC bit that indicates support for the TRUE
+/// control MSRs.
+const BASIC_TRUE_CONTROLS: Bit = 55;
+
+/// The primary processor-based control that enables the tertiary
+/// controls.
+const PROCBASED_ACTIVATE_TERTIARY: Bit = 17;
+
+/// The primary processor-based control that enables the secondary
+/// controls.
+const PROCBASED_ACTIVATE_SECONDARY: Bit = 31;
+
+/// A named VM-execution control.
+pub type Control = (Bit, &'static str);
+
+/// Pin-based VM-execution controls.
+pub const PINBASED_CONTROLS: &[Control] = &[
+    (0, "External-interrupt exiting"),
+    (3, "NMI exiting"),
+    (5, "Virtual NMIs"),
+    (6, "Activate VMX-preemption timer"),
+    (7, "Process posted interrupts"),
+];
+
+/// Primary processor-based VM-execution controls.
+pub const PROCBASED_CONTROLS: &[Control] = &[
+    (2, "Interrupt-window exiting"),
+    (3, "Use TSC offsetting"),
+    (7, "HLT exiting"),
+    (9, "INVLPG exiting"),
+    (10, "MWAIT exiting"),
+    (11, "RDPMC exiting"),
+    (12, "RDTSC exiting"),
+    (15, "CR3-load exiting"),
+    (16, "CR3-store exiting"),
+    (17, "Activate tertiary controls"),
+    (19, "CR8-load exiting"),
+    (20, "CR8-store exiting"),
+    (21, "Use TPR shadow"),
+    (22, "NMI-window exiting"),
+    (23, "MOV-DR exiting"),
+    (24, "Unconditional I/O exiting"),
+    (25, "Use I/O bitmaps"),
+    (27, "Monitor trap flag"),
+    (28, "Use MSR bitmaps"),
+    (29, "MONITOR exiting"),
+    (30, "PAUSE exiting"),
+    (31, "Activate secondary controls"),
+];
+
+/// Secondary processor-based VM-execution controls.
+pub const SECONDARY_CONTROLS: &[Control] = &[
+    (0, "Virtualize APIC accesses"),
+    (1, "Enable EPT"),
+    (2, "Descriptor-table exiting"),
+    (3, "Enable RDTSCP"),
+    (4, "Virtualize x2APIC mode"),
+    (5, "Enable VPID"),
+    (6, "WBINVD exiting"),
+    (7, "Unrestricted guest"),
+    (8, "APIC-register virtualization"),
+    (9, "Virtual-interrupt delivery"),
+    (10, "PAUSE-loop exiting"),
+    (11, "RDRAND exiting"),
+    (12, "Enable INVPCID"),
+    (13, "Enable VM functions"),
+    (14, "VMCS shadowing"),
+    (15, "Enable ENCLS exiting"),
+    (16, "RDSEED exiting"),
+    (17, "Enable PML"),
+    (18, "EPT-violation #VE"),
+    (19, "Conceal VMX from PT"),
+    (20, "Enable XSAVES/XRSTORS"),
+    (21, "PASID translation"),
+    (22, "Mode-based execute control for EPT"),
+    (23, "Sub-page write permissions for EPT"),
+    (24, "Intel PT uses guest physical addresses"),
+    (25, "Use TSC scaling"),
+    (26, "Enable user wait and pause"),
+    (27, "Enable PCONFIG"),
+    (28, "Enable ENCLV exiting"),
+    (30, "VMM bus-lock detection"),
+    (31, "Notify VM exiting"),
+];
+
+/// Tertiary processor-based VM-execution controls.
+pub const TERTIARY_CONTROLS: &[Control] = &[
+    (0, "LOADIWKEY exiting"),
+    (1, "Enable HLAT"),
+    (2, "EPT paging-write control"),
+    (3, "Guest-paging verification"),
+    (4, "IPI virtualization"),
+    (7, "Virtualize IA32_SPEC_CTRL"),
+];
+
+/// Primary VM-exit controls.
+pub const EXIT_CONTROLS: &[Control] = &[
+    (2, "Save debug controls"),
+    (9, "Host address-space size"),
+    (12, "Load IA32_PERF_GLOBAL_CTRL"),
+    (15, "Acknowledge interrupt on exit"),
+    (18, "Save IA32_PAT"),
+    (19, "Load IA32_PAT"),
+    (20, "Save IA32_EFER"),
+    (21, "Load IA32_EFER"),
+    (22, "Save VMX-preemption timer value"),
+    (23, "Clear IA32_BNDCFGS"),
+    (24, "Conceal VMX from PT"),
+    (25, "Clear IA32_RTIT_CTL"),
+    (26, "Clear IA32_LBR_CTL"),
+    (27, "Clear UINV"),
+    (28, "Load CET state"),
+    (29, "Load PKRS"),
+    (30, "Save IA32_PERF_GLOBAL_CTL"),
+    (31, "Activate secondary controls"),
+];
+
+/// VM-entry controls.
+pub const ENTRY_CONTROLS: &[Control] = &[
+    (2, "Load debug controls"),
+    (9, "IA-32e mode guest"),
+    (10, "Entry to SMM"),
+    (11, "Deactivate dual-monitor treatment"),
+    (13, "Load IA32_PERF_GLOBAL_CTRL"),
+    (14, "Load IA32_PAT"),
+    (15, "Load IA32_EFER"),
+    (16, "Load IA32_BNDCFGS"),
+    (17, "Conceal VMX from PT"),
+    (18, "Load IA32_RTIT_CTL"),
+    (19, "Load UINV"),
+    (20, "Load CET state"),
+    (21, "Load guest IA32_LBR_CTL"),
+    (22, "Load PKRS"),
+];
+
+/// How a VM-execution control can be configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlSetting {
+    /// The control must be 0.
+    Unsupported,
+
+    /// The control can be 0 or 1.
+    Optional,
+
+    /// The control must be 1.
+    Required,
+}
+
+impl fmt::Display for ControlSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ControlSetting::Unsupported => "not supported",
+            ControlSetting::Optional => "supported",
+            ControlSetting::Required => "always on",
+        })
+    }
+}
+
+/// Decode the setting of a single control from a VMX control
+/// capability MSR value.
+pub fn control_setting(capability: u64, bit: Bit) -> ControlSetting {
+    assert!(u32::from(bit) < 32);
+
+    let allowed_0 = capability & (1 << bit) != 0;
+    let allowed_1 = capability & (1 << (32 + bit)) != 0;
+
+    if !allowed_1 {
+        ControlSetting::Unsupported
+    } else if allowed_0 {
+        ControlSetting::Required
+    } else {
+        ControlSetting::Optional
+    }
+}
+
+/// Decode the setting of a single tertiary control.
+///
+/// IA32_VMX_PROCBASED_CTLS3 only has allowed-1 settings, one for each
+/// of the 64 controls.
+pub fn tertiary_control_setting(capability: u64, bit: Bit) -> ControlSetting {
+    assert!(u32::from(bit) < u64::BITS);
+
+    if capability & (1 << bit) != 0 {
+        ControlSetting::Optional
+    } else {
+        ControlSetting::Unsupported
+    }
+}
+
+/// An expression that checks whether a control can be set.
+///
+/// `legacy` and `true_msr` are the control capability MSR and its
+/// TRUE variant. The TRUE variant is only consulted when
+/// IA32_VMX_BASIC says it exists.
+fn allowed_1(legacy: u32, true_msr: u32, bit: Bit) -> BoolExpression {
+    assert!(u32::from(bit) < 32);
+
+    let has_true_controls = || MsrBitSet(IA32_VMX_BASIC, BASIC_TRUE_CONTROLS);
+
+    (has_true_controls() & MsrBitSet(true_msr, 32 + bit))
+        | (!has_true_controls() & MsrBitSet(legacy, 32 + bit))
+}
+
+/// An expression that checks whether a pin-based control can be set.
+pub fn pinbased_control(bit: Bit) -> BoolExpression {
+    allowed_1(IA32_VMX_PINBASED_CTLS, IA32_VMX_TRUE_PINBASED_CTLS, bit)
+}
+
+/// An expression that checks whether a primary processor-based
+/// control can be set.
+pub fn procbased_control(bit: Bit) -> BoolExpression {
+    allowed_1(IA32_VMX_PROCBASED_CTLS, IA32_VMX_TRUE_PROCBASED_CTLS, bit)
+}
+
+/// An expression that checks whether a VM-exit control can be set.
+pub fn exit_control(bit: Bit) -> BoolExpression {
+    allowed_1(IA32_VMX_EXIT_CTLS, IA32_VMX_TRUE_EXIT_CTLS, bit)
+}
+
+/// An expression that checks whether a VM-entry control can be set.
+pub fn entry_control(bit: Bit) -> BoolExpression {
+    allowed_1(IA32_VMX_ENTRY_CTLS, IA32_VMX_TRUE_ENTRY_CTLS, bit)
+}
+
+/// An expression that checks whether a secondary processor-based
+/// control can be set.
+///
+/// This includes the check whether secondary controls can be
+/// activated at all.
+pub fn secondary_control(bit: Bit) -> BoolExpression {
+    assert!(u32::from(bit) < 32);
+
+    procbased_control(PROCBASED_ACTIVATE_SECONDARY) & MsrBitSet(IA32_VMX_PROCBASED_CTLS2, 32 + bit)
+}
+
+/// An expression that checks whether a tertiary processor-based
+/// control can be set.
+///
+/// This includes the check whether tertiary controls can be
+/// activated at all.
+pub fn tertiary_control(bit: Bit) -> BoolExpression {
+    assert!(u32::from(bit) < u64::BITS);
+
+    procbased_control(PROCBASED_ACTIVATE_TERTIARY) & MsrBitSet(IA32_VMX_PROCBASED_CTLS3, bit)
+}
+
+/// Find the authoritative capability MSR for a set of controls.
+///
+/// Returns the MSR index and value. This is the TRUE variant, if
+/// IA32_VMX_BASIC indicates its existence, and the legacy MSR
+/// otherwise.
+fn effective_control_msr(
+    cpu_info: &dyn CpuInformation,
+    legacy: u32,
+    true_msr: u32,
+) -> Option<(u32, u64)> {
+    let basic = cpu_info.rdmsr(IA32_VMX_BASIC).unwrap_or(0);
+    let index = if basic & (1 << BASIC_TRUE_CONTROLS) != 0 {
+        true_msr
+    } else {
+        legacy
+    };
+
+    Some((index, cpu_info.rdmsr(index)?))
+}
+
+/// Decode all named controls of a capability MSR into a section.
+///
+/// `decode` extracts the setting of a single control from the
+/// capability MSR value.
+fn control_section(
+    title: &str,
+    index: u32,
+    capability: u64,
+    controls: &[Control],
+    decode: fn(u64, Bit) -> ControlSetting,
+) -> Section {
+    let mut section = Section::new(&format!("{} (MSR {:#x})", title, index));
+
+    for (bit, name) in controls {
+        section.row(name, decode(capability, *bit));
+    }
+
+    section
+}
+
+/// Names for the bits in IA32_VMX_EPT_VPID_CAP that are shown as
+/// individual flags.
+const EPT_VPID_FLAGS: &[(Bit, &str)] = &[
+    (0, "Execute-only translations"),
+    (16, "2MB pages"),
+    (17, "1GB pages"),
+    (20, "INVEPT"),
+    (21, "Accessed and dirty flags"),
+    (22, "Advanced VM-exit information"),
+    (23, "Supervisor shadow-stack control"),
+    (32, "INVVPID"),
+];
+
+/// Join the names of all set bits in `value`.
+fn bit_names(value: u64, names: &[(Bit, &str)]) -> String {
+    let set: Vec<&str> = names
+        .iter()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    if set.is_empty() {
+        "none".to_owned()
+    } else {
+        set.join(", ")
+    }
+}
+
+/// Decode IA32_VMX_EPT_VPID_CAP.
+fn ept_vpid_section(capability: u64) -> Section {
+    let mut section = Section::new(&format!(
+        "EPT and VPID capabilities (MSR {:#x})",
+        IA32_VMX_EPT_VPID_CAP
+    ));
+
+    section.row(
+        "Page-walk lengths",
+        bit_names(capability, &[(6, "4"), (7, "5")]),
+    );
+    section.row(
+        "EPT memory types",
+        bit_names(capability, &[(8, "UC"), (14, "WB")]),
+    );
+
+    for (bit, name) in EPT_VPID_FLAGS {
+        section.flag(name, capability, *bit);
+    }
+
+    section.row(
+        "INVEPT types",
+        bit_names(capability, &[(25, "single-context"), (26, "all-context")]),
+    );
+    section.row(
+        "INVVPID types",
+        bit_names(
+            capability,
+            &[
+                (40, "individual-address"),
+                (41, "single-context"),
+                (42, "all-context"),
+                (43, "single-context-retaining-globals"),
+            ],
+        ),
+    );
+    section.row("Maximum HLAT prefix size", (capability >> 48) & 0x3f);
+
+    section
+}
+
+/// Decode IA32_VMX_BASIC.
+fn basic_section(basic: u64) -> Section {
+    let mut section = Section::new(&format!(
+        "Basic VMX information (MSR {:#x})",
+        IA32_VMX_BASIC
+    ));
+
+    section.row("VMCS revision ID", format!("{:#x}", basic & 0x7fff_ffff));
+    section.row(
+        "VMCS region size",
+        format!("{} bytes", (basic >> 32) & 0x1fff),
+    );
+    section.flag("32-bit physical addresses only", basic, 48);
+    section.flag("Dual-monitor treatment of SMM", basic, 49);
+    section.row(
+        "VMCS memory type",
+        match (basic >> 50) & 0xf {
+            0 => "UC".to_owned(),
+            6 => "WB".to_owned(),
+            t => format!("reserved ({})", t),
+        },
+    );
+    section.flag("INS/OUTS exit information", basic, 54);
+    section.flag("TRUE control MSRs", basic, BASIC_TRUE_CONTROLS);
+    section.flag("Exception injection without error code", basic, 56);
+
+    section
+}
+
+/// Decode IA32_VMX_MISC.
+fn misc_section(misc: u64) -> Section {
+    let mut section = Section::new(&format!(
+        "Miscellaneous VMX data (MSR {:#x})",
+        IA32_VMX_MISC
+    ));
+
+    let timer_shift = misc & 0x1f;
+    section.row(
+        "Preemption timer rate",
+        format!("TSC / {} (shift {})", 1u64 << timer_shift, timer_shift),
+    );
+    section.flag("Store EFER.LMA on VM exit", misc, 5);
+    section.row(
+        "Activity states",
+        bit_names(misc, &[(6, "HLT"), (7, "shutdown"), (8, "wait-for-SIPI")]),
+    );
+    section.flag("Intel PT in VMX operation", misc, 14);
+    section.flag("RDMSR of IA32_SMBASE in SMM", misc, 15);
+    section.row("CR3-target values", (misc >> 16) & 0x1ff);
+    section.row("Maximum MSR-list entries", 512 * (((misc >> 25) & 0x7) + 1));
+    section.flag("VMWRITE to any VMCS field", misc, 29);
+    section.flag("Zero-length instruction injection", misc, 30);
+    section.row("MSEG revision", format!("{:#x}", misc >> 32));
+
+    section
+}
+
+/// Decode the VMX control capability MSRs.
+///
+/// Only MSRs that are available are decoded.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    let mut sections: Vec<Section> = msr::section(cpu_info, IA32_FEATURE_CONTROL)
+        .into_iter()
+        .collect();
+
+    if let Some(basic) = cpu_info.rdmsr(IA32_VMX_BASIC) {
+        sections.push(basic_section(basic));
+    }
+
+    if let Some((index, capability)) = effective_control_msr(
+        cpu_info,
+        IA32_VMX_PINBASED_CTLS,
+        IA32_VMX_TRUE_PINBASED_CTLS,
+    ) {
+        sections.push(control_section(
+            "Pin-based VM-execution controls",
+            index,
+            capability,
+            PINBASED_CONTROLS,
+            control_setting,
+        ));
+    }
+
+    if let Some((index, capability)) = effective_control_msr(
+        cpu_info,
+        IA32_VMX_PROCBASED_CTLS,
+        IA32_VMX_TRUE_PROCBASED_CTLS,
+    ) {
+        sections.push(control_section(
+            "Primary processor-based VM-execution controls",
+            index,
+            capability,
+            PROCBASED_CONTROLS,
+            control_setting,
+        ));
+    }
+
+    if let Some(capability) = cpu_info.rdmsr(IA32_VMX_PROCBASED_CTLS2) {
+        sections.push(control_section(
+            "Secondary processor-based VM-execution controls",
+            IA32_VMX_PROCBASED_CTLS2,
+            capability,
+            SECONDARY_CONTROLS,
+            control_setting,
+        ));
+    }
+
+    if let Some((index, capability)) =
+        effective_control_msr(cpu_info, IA32_VMX_EXIT_CTLS, IA32_VMX_TRUE_EXIT_CTLS)
+    {
+        sections.push(control_section(
+            "VM-exit controls",
+            index,
+            capability,
+            EXIT_CONTROLS,
+            control_setting,
+        ));
+    }
+
+    if let Some((index, capability)) =
+        effective_control_msr(cpu_info, IA32_VMX_ENTRY_CTLS, IA32_VMX_TRUE_ENTRY_CTLS)
+    {
+        sections.push(control_section(
+            "VM-entry controls",
+            index,
+            capability,
+            ENTRY_CONTROLS,
+            control_setting,
+        ));
+    }
+
+    if let Some(misc) = cpu_info.rdmsr(IA32_VMX_MISC) {
+        sections.push(misc_section(misc));
+    }
+
+    if let Some(capability) = cpu_info.rdmsr(IA32_VMX_EPT_VPID_CAP) {
+        sections.push(ept_vpid_section(capability));
+    }
+
+    if let Some(capability) = cpu_info.rdmsr(IA32_VMX_VMFUNC) {
+        let mut section = Section::new(&format!("VM functions (MSR {:#x})", IA32_VMX_VMFUNC));
+        section.flag("EPTP switching", capability, 0);
+        sections.push(section);
+    }
+
+    if let Some(capability) = cpu_info.rdmsr(IA32_VMX_PROCBASED_CTLS3) {
+        sections.push(control_section(
+            "Tertiary processor-based VM-execution controls",
+            IA32_VMX_PROCBASED_CTLS3,
+            capability,
+            TERTIARY_CONTROLS,
+            tertiary_control_setting,
+        ));
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::{CpuidQuery, CpuidResult};
+    use crate::test_support::assert_sorted_unique;
+
+    #[test]
+    fn control_settings_are_decoded() {
+        // Pin-based controls of a CPU without posted interrupts.
+        let capability = 0x0000_007f_0000_0016;
+
+        assert_eq!(control_setting(capability, 0), ControlSetting::Optional);
+        assert_eq!(control_setting(capability, 1), ControlSetting::Required);
+        assert_eq!(control_setting(capability, 7), ControlSetting::Unsupported);
+        assert_eq!(control_setting(capability, 8), ControlSetting::Unsupported);
+    }
+
+    #[test]
+    fn tertiary_control_settings_are_decoded() {
+        assert_eq!(
+            tertiary_control_setting(0b10010, 1),
+            ControlSetting::Optional
+        );
+        assert_eq!(
+            tertiary_control_setting(0b10010, 4),
+            ControlSetting::Optional
+        );
+        assert_eq!(
+            tertiary_control_setting(0b10010, 0),
+            ControlSetting::Unsupported
+        );
+        assert_eq!(
+            tertiary_control_setting(1 << 63, 63),
+            ControlSetting::Optional
+        );
+    }
+
+    #[test]
+    fn ept_vpid_capabilities_are_decoded() {
+        // A typical IA32_VMX_EPT_VPID_CAP of a recent client CPU.
+        let section = ept_vpid_section(0x0000_0f01_0673_4141);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Page-walk lengths"), "4");
+        assert_eq!(row("EPT memory types"), "UC, WB");
+        assert_eq!(row("Execute-only translations"), "Y");
+        assert_eq!(row("1GB pages"), "Y");
+        assert_eq!(row("Accessed and dirty flags"), "Y");
+        assert_eq!(row("Supervisor shadow-stack control"), "N");
+        assert_eq!(row("INVEPT types"), "single-context, all-context");
+        assert_eq!(
+            row("INVVPID types"),
+            "individual-address, single-context, all-context, single-context-retaining-globals"
+        );
+        assert_eq!(row("Maximum HLAT prefix size"), "0");
+    }
+
+    #[test]
+    fn basic_information_is_decoded() {
+        let section = basic_section(0x00da_0400_0000_0004);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("VMCS revision ID"), "0x4");
+        assert_eq!(row("VMCS region size"), "1024 bytes");
+        assert_eq!(row("32-bit physical addresses only"), "N");
+        assert_eq!(row("Dual-monitor treatment of SMM"), "Y");
+        assert_eq!(row("VMCS memory type"), "WB");
+        assert_eq!(row("INS/OUTS exit information"), "Y");
+        assert_eq!(row("TRUE control MSRs"), "Y");
+        assert_eq!(row("Exception injection without error code"), "N");
+    }
+
+    #[test]
+    fn misc_data_is_decoded() {
+        let section = misc_section(0x0000_0000_7004_c1e7);
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Preemption timer rate"), "TSC / 128 (shift 7)");
+        assert_eq!(row("Store EFER.LMA on VM exit"), "Y");
+        assert_eq!(row("Activity states"), "HLT, shutdown, wait-for-SIPI");
+        assert_eq!(row("Intel PT in VMX operation"), "Y");
+        assert_eq!(row("CR3-target values"), "4");
+        assert_eq!(row("Maximum MSR-list entries"), "512");
+        assert_eq!(row("VMWRITE to any VMCS field"), "Y");
+        assert_eq!(row("Zero-length instruction injection"), "Y");
+    }
+
+    struct TestCpu {
+        basic: u64,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, _query: CpuidQuery) -> Option<CpuidResult> {
+            None
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            match index {
+                IA32_VMX_BASIC => Some(self.basic),
+                // NMI exiting is always on in the legacy MSR, but optional in the TRUE MSR.
+                IA32_VMX_PINBASED_CTLS => Some(0x0000_007f_0000_0016),
+                IA32_VMX_TRUE_PINBASED_CTLS => Some(0x0000_007f_0000_0000),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn true_controls_are_preferred() {
+        let legacy_cpu = TestCpu { basic: 0 };
+        let true_cpu = TestCpu { basic: 1 << 55 };
+
+        assert_eq!(
+            effective_control_msr(
+                &legacy_cpu,
+                IA32_VMX_PINBASED_CTLS,
+                IA32_VMX_TRUE_PINBASED_CTLS
+            ),
+            Some((IA32_VMX_PINBASED_CTLS, 0x0000_007f_0000_0016))
+        );
+        assert_eq!(
+            effective_control_msr(
+                &true_cpu,
+                IA32_VMX_PINBASED_CTLS,
+                IA32_VMX_TRUE_PINBASED_CTLS
+            ),
+            Some((IA32_VMX_TRUE_PINBASED_CTLS, 0x0000_007f_0000_0000))
+        );
+
+        // The TRUE MSR is not consulted if it doesn't exist.
+        assert_eq!(
+            pinbased_control(3).simplify(&legacy_cpu),
+            BoolExpression::Constant(true)
+        );
+        assert_eq!(
+            procbased_control(3).simplify(&legacy_cpu),
+            MsrBitSet(IA32_VMX_PROCBASED_CTLS, 35)
+        );
+        assert_eq!(
+            procbased_control(3).simplify(&true_cpu),
+            MsrBitSet(IA32_VMX_TRUE_PROCBASED_CTLS, 35)
+        );
+    }
+
+    #[test]
+    fn controls_are_sorted_and_unique() {
+        assert_sorted_unique(PINBASED_CONTROLS);
+        assert_sorted_unique(PROCBASED_CONTROLS);
+        assert_sorted_unique(SECONDARY_CONTROLS);
+        assert_sorted_unique(TERTIARY_CONTROLS);
+        assert_sorted_unique(EXIT_CONTROLS);
+        assert_sorted_unique(ENTRY_CONTROLS);
+    }
+}
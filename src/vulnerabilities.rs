@@ -0,0 +1,343 @@
+//! # Estimate Vulnerability Exposure
+//!
+//! Linux reports in `/sys/devices/system/cpu/vulnerabilities` which
+//! speculative execution vulnerabilities affect a CPU. This module
+//! computes a similar report from a dump.
+//!
+//! The estimate is based purely on what the CPU enumerates: the
+//! "not affected" bits in IA32_ARCH_CAPABILITIES and AMD's CPUID
+//! leaf 0x80000008 as well as the available mitigation interfaces.
+//! Unlike Linux, it doesn't know which CPU models are affected, so
+//! older CPUs that predate these bits may be reported as vulnerable
+//! even though they are not.
+
+use crate::cpu_information::CpuidRegister::*;
+use crate::cpu_information::{missing_data, CpuInformation, Vendor};
+use crate::features::BoolExpression::{self, Constant, CpuidBitSet, MsrBitSet};
+use crate::features::{Bit, FeatureStatus};
+use crate::report::Section;
+use crate::speculation::{amd_speculation_control, arch_capability, IA32_MCU_OPT_CTRL};
+
+/// A speculative execution vulnerability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Vulnerability {
+    name: &'static str,
+
+    /// Whether the CPU is not affected.
+    not_affected: BoolExpression,
+
+    /// The assessments of an affected CPU in order of preference,
+    /// each with the condition under which it applies: hardware
+    /// mitigations first, then states that leave the CPU vulnerable.
+    outcomes: Vec<(String, BoolExpression)>,
+}
+
+impl Vulnerability {
+    fn new(name: &'static str, not_affected: BoolExpression) -> Self {
+        Self {
+            name,
+            not_affected,
+            outcomes: vec![],
+        }
+    }
+
+    fn mitigation(mut self, name: &'static str, available: BoolExpression) -> Self {
+        self.outcomes
+            .push((format!("Vulnerable, mitigation: {}", name), available));
+        self
+    }
+
+    /// A state in which the CPU stays vulnerable even though it has a
+    /// mitigation, e.g. because it is turned off.
+    fn unmitigated(mut self, assessment: &'static str, condition: BoolExpression) -> Self {
+        self.outcomes.push((assessment.to_owned(), condition));
+        self
+    }
+
+    /// A one-line assessment of the exposure.
+    ///
+    /// If no outcome applies but some couldn't be decided, the
+    /// assessment says what is missing rather than claiming that
+    /// there is no mitigation.
+    fn assess(&self, cpu_info: &dyn CpuInformation) -> String {
+        match self.not_affected.status(cpu_info) {
+            FeatureStatus::Present | FeatureStatus::NotApplicable => "Not affected".to_owned(),
+            FeatureStatus::Unknown { missing } => {
+                format!("Unknown ({})", missing_data(&missing))
+            }
+            FeatureStatus::Absent => {
+                let mut undecided = None;
+
+                for (assessment, condition) in &self.outcomes {
+                    match condition.status(cpu_info) {
+                        FeatureStatus::Present => return assessment.clone(),
+                        FeatureStatus::Unknown { missing } => {
+                            undecided.get_or_insert(missing);
+                        }
+                        _ => (),
+                    }
+                }
+
+                match undecided {
+                    Some(missing) => format!(
+                        "Vulnerable, mitigation state unknown ({})",
+                        missing_data(&missing)
+                    ),
+                    None => "Vulnerable, no hardware mitigation".to_owned(),
+                }
+            }
+        }
+    }
+}
+
+/// A bit in CPUID leaf 7 EDX.
+fn leaf7_edx(bit: Bit) -> BoolExpression {
+    CpuidBitSet(7.into(), Edx, bit)
+}
+
+fn intel_vulnerabilities() -> Vec<Vulnerability> {
+    let md_clear = leaf7_edx(10);
+
+    vec![
+        Vulnerability::new("Meltdown", arch_capability(0)),
+        Vulnerability::new("Spectre v2", Constant(false))
+            .mitigation("enhanced IBRS", arch_capability(1))
+            .mitigation("IBRS", leaf7_edx(26)),
+        Vulnerability::new("Speculative store bypass", arch_capability(4))
+            .mitigation("SSBD", leaf7_edx(31)),
+        Vulnerability::new("L1TF", arch_capability(0)).mitigation("L1D flush", leaf7_edx(28)),
+        Vulnerability::new("MDS", arch_capability(5)).mitigation("MD_CLEAR", md_clear.clone()),
+        // Only CPUs with TSX are affected by TAA.
+        Vulnerability::new(
+            "TSX async abort",
+            arch_capability(8) | !CpuidBitSet(7.into(), Ebx, 11),
+        )
+        .mitigation("TSX disable", arch_capability(7))
+        .mitigation("MD_CLEAR", md_clear.clone()),
+        Vulnerability::new(
+            "MMIO stale data",
+            arch_capability(13) & arch_capability(14) & arch_capability(15),
+        )
+        .mitigation("FB_CLEAR", arch_capability(17))
+        .mitigation("MD_CLEAR", md_clear),
+        // Microcode enumerates SRBDS_CTRL only on affected CPUs.
+        Vulnerability::new("SRBDS", !leaf7_edx(9))
+            .mitigation("microcode", leaf7_edx(9) & !MsrBitSet(IA32_MCU_OPT_CTRL, 0))
+            .unmitigated(
+                "Vulnerable, microcode mitigation disabled (IA32_MCU_OPT_CTRL)",
+                leaf7_edx(9) & MsrBitSet(IA32_MCU_OPT_CTRL, 0),
+            ),
+        Vulnerability::new("Register file data sampling", arch_capability(27))
+            .mitigation("RFDS_CLEAR", arch_capability(28)),
+        Vulnerability::new("Gather data sampling", arch_capability(26))
+            .mitigation(
+                "microcode",
+                arch_capability(25) & !MsrBitSet(IA32_MCU_OPT_CTRL, 4),
+            )
+            .unmitigated(
+                "Vulnerable, microcode mitigation disabled (IA32_MCU_OPT_CTRL)",
+                arch_capability(25) & MsrBitSet(IA32_MCU_OPT_CTRL, 4),
+            ),
+        // Enhanced IBRS also covers returns, unless RSBA says that
+        // returns may use the indirect branch predictor.
+        Vulnerability::new("Retbleed", arch_capability(1) & !arch_capability(2))
+            .mitigation("IBRS", leaf7_edx(26)),
+    ]
+}
+
+fn amd_vulnerabilities() -> Vec<Vulnerability> {
+    vec![
+        Vulnerability::new("Meltdown", Constant(true)),
+        Vulnerability::new("Spectre v2", Constant(false))
+            .mitigation("IBRS always on", amd_speculation_control(16))
+            .mitigation("IBRS", amd_speculation_control(14)),
+        Vulnerability::new("Speculative store bypass", amd_speculation_control(26))
+            .mitigation("SSBD", amd_speculation_control(24))
+            .mitigation("virtualized SSBD", amd_speculation_control(25)),
+        Vulnerability::new("L1TF", Constant(true)),
+        Vulnerability::new("MDS", Constant(true)),
+        Vulnerability::new("TSX async abort", Constant(true)),
+        Vulnerability::new("MMIO stale data", Constant(true)),
+        Vulnerability::new("SRBDS", Constant(true)),
+        Vulnerability::new("Register file data sampling", Constant(true)),
+        Vulnerability::new("Gather data sampling", Constant(true)),
+        Vulnerability::new("Retbleed", amd_speculation_control(29))
+            .mitigation("IBPB", amd_speculation_control(12)),
+    ]
+}
+
+/// Estimate the exposure to speculative execution vulnerabilities.
+///
+/// Returns `None` if the CPU vendor is unknown.
+pub fn section(cpu_info: &dyn CpuInformation) -> Option<Section> {
+    let vulnerabilities = match cpu_info.vendor()? {
+        Vendor::Intel => intel_vulnerabilities(),
+        Vendor::Amd => amd_vulnerabilities(),
+    };
+
+    let mut section = Section::new("Vulnerabilities (estimated from enumeration)");
+    for vulnerability in &vulnerabilities {
+        section.row(vulnerability.name, vulnerability.assess(cpu_info));
+    }
+
+    Some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::{CpuidQuery, CpuidResult};
+    use crate::speculation::IA32_ARCH_CAPABILITIES;
+
+    struct TestCpu {
+        leaf7_ebx: u32,
+        leaf7_edx: u32,
+        arch_capabilities: Option<u64>,
+        mcu_opt_ctrl: Option<u64>,
+    }
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let (ebx, ecx, edx) = match query.leaf {
+                // "GenuineIntel"
+                0 => (0x756e_6547, 0x6c65_746e, 0x4965_6e69),
+                7 => (self.leaf7_ebx, 0, self.leaf7_edx),
+                _ => return None,
+            };
+
+            Some(CpuidResult {
+                eax: 7,
+                ebx,
+                ecx,
+                edx,
+            })
+        }
+
+        fn rdmsr(&self, index: u32) -> Option<u64> {
+            match index {
+                IA32_ARCH_CAPABILITIES => self.arch_capabilities,
+                IA32_MCU_OPT_CTRL => self.mcu_opt_ctrl,
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_is_estimated_from_arch_capabilities() {
+        // With RTM, MD_CLEAR, IA32_ARCH_CAPABILITIES and SSBD.
+        let cpu = TestCpu {
+            leaf7_ebx: 1 << 11,
+            leaf7_edx: (1 << 10) | (1 << 29) | (1 << 31),
+            // RDCL_NO, IBRS_ALL, MDS_NO and TSX_CTRL.
+            arch_capabilities: Some(0xa3),
+            mcu_opt_ctrl: None,
+        };
+        let section = section(&cpu).expect("vendor is known");
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Meltdown"), "Not affected");
+        assert_eq!(row("Spectre v2"), "Vulnerable, mitigation: enhanced IBRS");
+        assert_eq!(
+            row("Speculative store bypass"),
+            "Vulnerable, mitigation: SSBD"
+        );
+        assert_eq!(row("MDS"), "Not affected");
+        assert_eq!(
+            row("TSX async abort"),
+            "Vulnerable, mitigation: TSX disable"
+        );
+        assert_eq!(
+            row("Gather data sampling"),
+            "Vulnerable, no hardware mitigation"
+        );
+        assert_eq!(row("Retbleed"), "Not affected");
+    }
+
+    #[test]
+    fn gds_mitigation_is_checked() {
+        let gds = |mcu_opt_ctrl| {
+            let cpu = TestCpu {
+                leaf7_ebx: 0,
+                leaf7_edx: 1 << 29,
+                // GDS_CTRL
+                arch_capabilities: Some(1 << 25),
+                mcu_opt_ctrl,
+            };
+
+            section(&cpu)
+                .unwrap()
+                .get("Gather data sampling")
+                .map(str::to_owned)
+        };
+
+        assert_eq!(
+            gds(Some(0)).as_deref(),
+            Some("Vulnerable, mitigation: microcode")
+        );
+        assert_eq!(
+            gds(Some(1 << 4)).as_deref(),
+            Some("Vulnerable, microcode mitigation disabled (IA32_MCU_OPT_CTRL)")
+        );
+        assert_eq!(
+            gds(None).as_deref(),
+            Some("Vulnerable, mitigation state unknown (missing MSR 0x123)")
+        );
+    }
+
+    #[test]
+    fn srbds_mitigation_is_checked() {
+        let srbds = |mcu_opt_ctrl| {
+            let cpu = TestCpu {
+                leaf7_ebx: 0,
+                leaf7_edx: 1 << 9,
+                arch_capabilities: None,
+                mcu_opt_ctrl,
+            };
+
+            section(&cpu).unwrap().get("SRBDS").map(str::to_owned)
+        };
+
+        assert_eq!(
+            srbds(Some(0)).as_deref(),
+            Some("Vulnerable, mitigation: microcode")
+        );
+        assert_eq!(
+            srbds(Some(1)).as_deref(),
+            Some("Vulnerable, microcode mitigation disabled (IA32_MCU_OPT_CTRL)")
+        );
+        // Most dumps don't have IA32_MCU_OPT_CTRL.
+        assert_eq!(
+            srbds(None).as_deref(),
+            Some("Vulnerable, mitigation state unknown (missing MSR 0x123)")
+        );
+    }
+
+    #[test]
+    fn missing_arch_capabilities_are_reported() {
+        let enumerated = TestCpu {
+            leaf7_ebx: 0,
+            leaf7_edx: 1 << 29,
+            arch_capabilities: None,
+            mcu_opt_ctrl: None,
+        };
+        let not_enumerated = TestCpu {
+            leaf7_ebx: 0,
+            leaf7_edx: 0,
+            arch_capabilities: None,
+            mcu_opt_ctrl: None,
+        };
+
+        assert_eq!(
+            section(&enumerated).unwrap().get("Meltdown"),
+            Some("Unknown (missing MSR 0x10a)")
+        );
+        assert_eq!(
+            section(&not_enumerated).unwrap().get("Meltdown"),
+            Some("Vulnerable, no hardware mitigation")
+        );
+        assert_eq!(
+            section(&not_enumerated).unwrap().get("TSX async abort"),
+            Some("Not affected")
+        );
+    }
+}
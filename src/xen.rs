@@ -0,0 +1,123 @@
+//! # Decode Xen Hypervisor Leaves
+//!
+//! Xen places its leaves right after the signature leaf:
+//!
+//! - base + 1: Xen version
+//! - base + 2: hypercall page setup
+//! - base + 3: time and TSC handling
+//! - base + 4: HVM guest features
+//!
+//! See `xen/include/public/arch-x86/cpuid.h` in the Xen sources.
+
+use crate::cpu_information::{CpuInformation, CpuidQuery, CpuidResult};
+use crate::features::Bit;
+use crate::hypervisor::CPUID_HYPERVISOR_BASE;
+use crate::report::Section;
+
+/// Flags in EAX of the time leaf.
+const TIME_FLAGS: &[(Bit, &str)] = &[
+    (0, "Emulated TSC"),
+    (1, "Host TSC is reliable"),
+    (2, "RDTSCP"),
+];
+
+/// HVM features in EAX of the HVM leaf.
+const HVM_FEATURES: &[(Bit, &str)] = &[
+    (0, "Virtualized APIC registers"),
+    (1, "Virtualized x2APIC accesses"),
+    (2, "IOMMU mappings"),
+    (3, "VCPU ID present"),
+    (4, "Domain ID present"),
+    (5, "Extended destination ID"),
+    (6, "Per-VCPU upcall vector"),
+];
+
+/// Decode the version, hypercall, time and HVM leaves.
+fn xen_section(
+    version: Option<CpuidResult>,
+    hypercall: Option<CpuidResult>,
+    time: Option<CpuidResult>,
+    hvm: Option<CpuidResult>,
+) -> Section {
+    let mut section = Section::new(&format!("Xen (CPUID {:#x})", CPUID_HYPERVISOR_BASE));
+
+    if let Some(r) = version {
+        section.row("Version", format!("{}.{}", r.eax >> 16, r.eax & 0xffff));
+    }
+
+    if let Some(r) = hypercall {
+        section.row("Hypercall pages", r.eax);
+        section.row("Hypercall page MSR", format!("{:#x}", r.ebx));
+    }
+
+    if let Some(r) = time {
+        for (bit, name) in TIME_FLAGS {
+            section.flag(name, r.eax.into(), *bit);
+        }
+        section.row("TSC mode", r.ebx);
+        section.row("Guest TSC frequency", format!("{} kHz", r.ecx));
+    }
+
+    if let Some(r) = hvm {
+        for (bit, name) in HVM_FEATURES {
+            section.flag(name, r.eax.into(), *bit);
+        }
+    }
+
+    section
+}
+
+/// Decode the Xen leaves that are available.
+///
+/// The caller must make sure that the hypervisor is Xen.
+pub fn sections(cpu_info: &dyn CpuInformation, max_leaf: u32) -> Vec<Section> {
+    let leaf = |offset: u32| {
+        Some(CPUID_HYPERVISOR_BASE + offset)
+            .filter(|l| *l <= max_leaf)
+            .and_then(|leaf| cpu_info.cpuid(CpuidQuery { leaf, subleaf: 0 }))
+    };
+
+    vec![xen_section(leaf(1), leaf(2), leaf(3), leaf(4))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(eax: u32, ebx: u32, ecx: u32) -> Option<CpuidResult> {
+        Some(CpuidResult {
+            eax,
+            ebx,
+            ecx,
+            edx: 0,
+        })
+    }
+
+    #[test]
+    fn xen_leaves_are_decoded() {
+        let section = xen_section(
+            result(0x0004_0011, 0, 0),
+            result(1, 0x4000_0000, 0),
+            result(0b110, 0, 2_893_202),
+            result(0b1_1100, 0, 0),
+        );
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Version"), "4.17");
+        assert_eq!(row("Hypercall pages"), "1");
+        assert_eq!(row("Hypercall page MSR"), "0x40000000");
+        assert_eq!(row("Emulated TSC"), "N");
+        assert_eq!(row("Host TSC is reliable"), "Y");
+        assert_eq!(row("Guest TSC frequency"), "2893202 kHz");
+        assert_eq!(row("Virtualized APIC registers"), "N");
+        assert_eq!(row("IOMMU mappings"), "Y");
+        assert_eq!(row("Domain ID present"), "Y");
+    }
+
+    #[test]
+    fn missing_leaves_are_skipped() {
+        let section = xen_section(result(0x0004_0011, 0, 0), None, None, None);
+
+        assert_eq!(section.rows.len(), 1);
+    }
+}
@@ -0,0 +1,377 @@
+//! # Decode XSAVE State Components
+//!
+//! CPUID leaf 0xD enumerates the state components that XSAVE can
+//! manage. Subleaf 0 lists the user state components (XCR0), subleaf
+//! 1 the XSAVE extensions and the supervisor state components
+//! (IA32_XSS). Each subleaf from 2 on describes the size and location
+//! of one component.
+//!
+//! XSAVE and XSAVEOPT use the standard format, where every component
+//! has a fixed offset. XSAVEC and XSAVES use the compacted format,
+//! where only the requested components are stored back to back. The
+//! size of the save area therefore depends on the feature mask, which
+//! [standard_size] and [compacted_size] compute.
+//!
+//! See the Intel SDM Vol. 1, Chapter 13.
+
+use crate::cpu_information::CpuidRegister::Ecx;
+use crate::cpu_information::{CpuInformation, CpuidQuery};
+use crate::features::Bit;
+use crate::features::BoolExpression::{self, CpuidBitSet};
+use crate::report::Section;
+
+/// The CPUID leaf with XSAVE information.
+pub const CPUID_XSAVE: u32 = 0xd;
+
+/// The size of the legacy region and the XSAVE header, which every
+/// save area contains.
+const LEGACY_AND_HEADER_SIZE: u32 = 512 + 64;
+
+/// The state component for user-mode CET.
+pub const CET_U_STATE: Bit = 11;
+
+/// The state component for supervisor-mode CET.
+pub const CET_S_STATE: Bit = 12;
+
+/// The state component for user interrupts.
+pub const UINTR_STATE: Bit = 14;
+
+/// The names of the state components.
+const COMPONENTS: &[(Bit, &str)] = &[
+    (0, "x87"),
+    (1, "SSE"),
+    (2, "AVX"),
+    (3, "MPX BNDREGS"),
+    (4, "MPX BNDCSR"),
+    (5, "AVX-512 opmask"),
+    (6, "AVX-512 ZMM_Hi256"),
+    (7, "AVX-512 Hi16_ZMM"),
+    (8, "PT"),
+    (9, "PKRU"),
+    (10, "PASID"),
+    (11, "CET_U"),
+    (12, "CET_S"),
+    (13, "HDC"),
+    (14, "UINTR"),
+    (15, "LBR"),
+    (16, "HWP"),
+    (17, "AMX TILECFG"),
+    (18, "AMX TILEDATA"),
+    (19, "APX"),
+];
+
+/// The XSAVE extensions in EAX of subleaf 1.
+const EXTENSIONS: &[(Bit, &str)] = &[
+    (0, "XSAVEOPT"),
+    (1, "XSAVEC"),
+    (2, "XGETBV with ECX=1"),
+    (3, "XSAVES/XRSTORS"),
+    (4, "XFD"),
+];
+
+/// An expression that checks whether IA32_XSS supports a supervisor
+/// state component.
+pub fn supervisor_component(index: Bit) -> BoolExpression {
+    CpuidBitSet(query(1), Ecx, index)
+}
+
+/// The name of a state component.
+pub fn component_name(index: u32) -> String {
+    COMPONENTS
+        .iter()
+        .find(|(bit, _)| u32::from(*bit) == index)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("Component {}", index))
+}
+
+/// A state component beyond x87 and SSE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Component {
+    pub index: u32,
+    pub size: u32,
+
+    /// The offset in the standard format. Supervisor components are
+    /// not part of the standard format and have offset 0.
+    pub offset: u32,
+
+    /// Whether the component is managed via IA32_XSS instead of XCR0.
+    pub supervisor: bool,
+
+    /// Whether the component starts at a 64-byte boundary in the
+    /// compacted format.
+    pub aligned: bool,
+}
+
+fn query(subleaf: u32) -> CpuidQuery {
+    CpuidQuery {
+        leaf: CPUID_XSAVE,
+        subleaf,
+    }
+}
+
+/// The state components in XCR0 and IA32_XSS that the CPU supports.
+pub fn supported_mask(cpu_info: &dyn CpuInformation) -> Option<u64> {
+    if !cpu_info.is_cpuid_query_valid(query(0)) {
+        return None;
+    }
+
+    let user = cpu_info
+        .cpuid(query(0))
+        .map(|r| u64::from(r.edx) << 32 | u64::from(r.eax))?;
+    let supervisor = cpu_info
+        .cpuid(query(1))
+        .map_or(0, |r| u64::from(r.edx) << 32 | u64::from(r.ecx));
+
+    Some(user | supervisor)
+}
+
+/// The supported state components from 2 on, if their subleaves are
+/// in the dump.
+pub fn components(cpu_info: &dyn CpuInformation) -> Vec<Component> {
+    let mask = supported_mask(cpu_info).unwrap_or(0);
+
+    (2..64)
+        .filter(|index| (mask >> index) & 1 == 1)
+        .filter_map(|index| {
+            cpu_info.cpuid(query(index)).map(|r| Component {
+                index,
+                size: r.eax,
+                offset: r.ebx,
+                supervisor: r.ecx & 1 == 1,
+                aligned: (r.ecx >> 1) & 1 == 1,
+            })
+        })
+        .collect()
+}
+
+/// Why an XSAVE area size can't be computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SizeError {
+    /// The CPU doesn't support this component or the dump lacks its
+    /// subleaf.
+    Unsupported(u32),
+
+    /// Supervisor components only exist in the compacted format.
+    Supervisor(u32),
+}
+
+impl std::fmt::Display for SizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SizeError::Unsupported(index) => {
+                write!(f, "{} is not supported", component_name(*index))
+            }
+            SizeError::Supervisor(index) => {
+                write!(f, "{} is a supervisor component", component_name(*index))
+            }
+        }
+    }
+}
+
+/// Look up the components in a mask.
+fn mask_components(components: &[Component], mask: u64) -> Result<Vec<Component>, SizeError> {
+    (2..64)
+        .filter(|index| (mask >> index) & 1 == 1)
+        .map(|index| {
+            components
+                .iter()
+                .find(|c| c.index == index)
+                .copied()
+                .ok_or(SizeError::Unsupported(index))
+        })
+        .collect()
+}
+
+/// The size of a standard format save area (XSAVE, XSAVEOPT) for the
+/// state components in `mask`.
+pub fn standard_size(components: &[Component], mask: u64) -> Result<u32, SizeError> {
+    mask_components(components, mask)?
+        .iter()
+        .try_fold(LEGACY_AND_HEADER_SIZE, |size, c| {
+            if c.supervisor {
+                Err(SizeError::Supervisor(c.index))
+            } else {
+                Ok(size.max(c.offset + c.size))
+            }
+        })
+}
+
+/// The size of a compacted format save area (XSAVEC, XSAVES) for the
+/// state components in `mask`.
+pub fn compacted_size(components: &[Component], mask: u64) -> Result<u32, SizeError> {
+    Ok(mask_components(components, mask)?
+        .iter()
+        .fold(LEGACY_AND_HEADER_SIZE, |size, c| {
+            let start = if c.aligned { (size + 63) & !63 } else { size };
+
+            start + c.size
+        }))
+}
+
+/// Decode CPUID leaf 0xD.
+fn xsave_section(cpu_info: &dyn CpuInformation) -> Option<Section> {
+    let mask = supported_mask(cpu_info)?;
+    let subleaf0 = cpu_info.cpuid(query(0))?;
+
+    let mut section = Section::new(&format!("XSAVE (CPUID {:#x})", CPUID_XSAVE));
+
+    section.row("Supported components", format!("{:#x}", mask));
+    section.row("Size for enabled XCR0", subleaf0.ebx);
+    section.row("Size for all XCR0 components", subleaf0.ecx);
+
+    if let Some(subleaf1) = cpu_info.cpuid(query(1)) {
+        for (bit, name) in EXTENSIONS {
+            section.flag(name, subleaf1.eax.into(), *bit);
+        }
+        section.row("Size for enabled XCR0 and IA32_XSS", subleaf1.ebx);
+    }
+
+    for component in components(cpu_info) {
+        let description = if component.supervisor {
+            format!("{} bytes, supervisor", component.size)
+        } else {
+            format!("{} bytes at offset {}", component.size, component.offset)
+        };
+        let alignment = if component.aligned {
+            ", 64-byte aligned when compacted"
+        } else {
+            ""
+        };
+
+        section.row(
+            &format!("{} ({})", component_name(component.index), component.index),
+            format!("{}{}", description, alignment),
+        );
+    }
+
+    Some(section)
+}
+
+/// Decode the XSAVE state components, if the CPU enumerates them.
+pub fn sections(cpu_info: &dyn CpuInformation) -> Vec<Section> {
+    xsave_section(cpu_info).into_iter().collect()
+}
+
+/// The save area sizes for a feature mask.
+pub fn size_section(cpu_info: &dyn CpuInformation, mask: u64) -> Section {
+    let components = components(cpu_info);
+    let format = |size: Result<u32, SizeError>| match size {
+        Ok(size) => format!("{} bytes", size),
+        Err(e) => format!("not possible ({})", e),
+    };
+
+    let mut section = Section::new(&format!("XSAVE area size for {:#x}", mask));
+
+    section.row("Standard format", format(standard_size(&components, mask)));
+    section.row(
+        "Compacted format",
+        format(compacted_size(&components, mask)),
+    );
+
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_information::CpuidResult;
+    use crate::test_support::assert_sorted_unique;
+
+    /// A Skylake-SP with AVX-512, PKRU and PT as supervisor component.
+    struct TestCpu;
+
+    impl CpuInformation for TestCpu {
+        fn cpuid(&self, query: CpuidQuery) -> Option<CpuidResult> {
+            let result = |eax, ebx, ecx, edx| Some(CpuidResult { eax, ebx, ecx, edx });
+
+            match (query.leaf, query.subleaf) {
+                (0x0, _) => result(0x16, 0, 0, 0),
+                (0xd, 0) => result(0x2ff, 0xa88, 0xa88, 0),
+                (0xd, 1) => result(0xf, 0xa88, 0x100, 0),
+                (0xd, 2) => result(256, 576, 0, 0),
+                (0xd, 3) => result(64, 960, 0, 0),
+                (0xd, 4) => result(64, 1024, 0, 0),
+                (0xd, 5) => result(64, 1088, 0, 0),
+                (0xd, 6) => result(512, 1152, 0, 0),
+                (0xd, 7) => result(1024, 1664, 0, 0),
+                (0xd, 8) => result(128, 0, 1, 0),
+                (0xd, 9) => result(8, 2688, 0, 0),
+                _ => None,
+            }
+        }
+
+        fn rdmsr(&self, _index: u32) -> Option<u64> {
+            None
+        }
+    }
+
+    #[test]
+    fn leaf_is_decoded() {
+        let sections = sections(&TestCpu);
+        let section = &sections[0];
+        let row = |name: &str| section.get(name).expect("row exists");
+
+        assert_eq!(row("Supported components"), "0x3ff");
+        assert_eq!(row("Size for all XCR0 components"), "2696");
+        assert_eq!(row("XSAVEC"), "Y");
+        assert_eq!(row("XFD"), "N");
+        assert_eq!(row("AVX (2)"), "256 bytes at offset 576");
+        assert_eq!(row("PT (8)"), "128 bytes, supervisor");
+        assert_eq!(row("PKRU (9)"), "8 bytes at offset 2688");
+    }
+
+    #[test]
+    fn standard_size_is_computed() {
+        let components = components(&TestCpu);
+
+        assert_eq!(standard_size(&components, 0x3), Ok(576));
+        assert_eq!(standard_size(&components, 0x7), Ok(832));
+        assert_eq!(standard_size(&components, 0xe7), Ok(2688));
+        assert_eq!(standard_size(&components, 0x2e7), Ok(2696));
+        assert_eq!(
+            standard_size(&components, 0x107),
+            Err(SizeError::Supervisor(8))
+        );
+    }
+
+    #[test]
+    fn compacted_size_is_computed() {
+        let components = components(&TestCpu);
+
+        // Without MPX, AVX-512 directly follows AVX.
+        assert_eq!(compacted_size(&components, 0xe7), Ok(2432));
+        assert_eq!(compacted_size(&components, 0x107), Ok(960));
+        assert_eq!(
+            compacted_size(&components, 0x1_0000),
+            Err(SizeError::Unsupported(16))
+        );
+    }
+
+    #[test]
+    fn alignment_applies_to_compacted_format() {
+        let components = [
+            Component {
+                index: 9,
+                size: 8,
+                offset: 2688,
+                supervisor: false,
+                aligned: false,
+            },
+            Component {
+                index: 17,
+                size: 64,
+                offset: 2752,
+                supervisor: false,
+                aligned: true,
+            },
+        ];
+
+        assert_eq!(compacted_size(&components, 1 << 9 | 1 << 17), Ok(704));
+    }
+
+    #[test]
+    fn components_are_sorted_and_unique() {
+        assert_sorted_unique(COMPONENTS);
+        assert_sorted_unique(EXTENSIONS);
+    }
+}